@@ -0,0 +1,132 @@
+use std::time::Duration;
+
+use serenity::{
+    builder::{
+        CreateEmbed, CreateInteractionResponse, CreateInteractionResponseMessage, CreateMessage,
+        EditInteractionResponse,
+    },
+    model::prelude::CommandInteraction,
+    prelude::Context,
+};
+
+use crate::{commands::utils::is_owner, data::TTSData};
+
+/// Delay between sends to stay well below Discord's global rate limit.
+const BROADCAST_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Number of channels a broadcast was delivered to.
+#[derive(Debug, Default, PartialEq)]
+pub struct BroadcastResult {
+    pub succeeded: usize,
+    pub failed: usize,
+}
+
+impl BroadcastResult {
+    pub fn record<T, E>(&mut self, result: &Result<T, E>) {
+        match result {
+            Ok(_) => self.succeeded += 1,
+            Err(_) => self.failed += 1,
+        }
+    }
+}
+
+pub async fn broadcast_command(
+    ctx: &Context,
+    command: &CommandInteraction,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if !is_owner(ctx, command.user.id).await {
+        command
+            .create_response(
+                &ctx.http,
+                CreateInteractionResponse::Message(
+                    CreateInteractionResponseMessage::new()
+                        .content("このコマンドはBotのオーナーのみ使用可能です．")
+                        .ephemeral(true),
+                ),
+            )
+            .await?;
+        return Ok(());
+    }
+
+    let message = command
+        .data
+        .options
+        .first()
+        .map(|option| option.value.clone())
+        .and_then(|value| value.as_str().map(|value| value.to_string()))
+        .unwrap_or_default();
+
+    command
+        .create_response(
+            &ctx.http,
+            CreateInteractionResponse::Defer(
+                CreateInteractionResponseMessage::new().ephemeral(true),
+            ),
+        )
+        .await?;
+
+    let instances = {
+        let data_read = ctx.data.read().await;
+        let storage_lock = data_read
+            .get::<TTSData>()
+            .expect("Cannot get TTSStorage")
+            .clone();
+        let storage = storage_lock.read().await;
+        storage.values().cloned().collect::<Vec<_>>()
+    };
+    let mut text_channels = vec![];
+    for instance in instances {
+        text_channels.push(instance.lock().await.text_channel);
+    }
+
+    let mut result = BroadcastResult::default();
+    for text_channel in text_channels {
+        let sent = text_channel
+            .send_message(
+                &ctx.http,
+                CreateMessage::new().embed(
+                    CreateEmbed::new()
+                        .title("お知らせ")
+                        .description(message.as_str()),
+                ),
+            )
+            .await;
+        if let Err(err) = &sent {
+            println!("Cannot broadcast to {}: {:?}", text_channel.get(), err);
+        }
+        result.record(&sent);
+        tokio::time::sleep(BROADCAST_INTERVAL).await;
+    }
+
+    command
+        .edit_response(
+            &ctx.http,
+            EditInteractionResponse::new().content(format!(
+                "送信しました\n成功: {}\n失敗: {}",
+                result.succeeded, result.failed
+            )),
+        )
+        .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tallies_successes_and_failures() {
+        let mut result = BroadcastResult::default();
+        result.record(&Ok::<(), &str>(()));
+        result.record(&Err::<(), &str>("missing access"));
+        result.record(&Ok::<(), &str>(()));
+        assert_eq!(
+            result,
+            BroadcastResult {
+                succeeded: 2,
+                failed: 1,
+            }
+        );
+    }
+}
@@ -0,0 +1,87 @@
+use serenity::{
+    builder::{CreateInteractionResponse, CreateInteractionResponseMessage},
+    model::prelude::CommandInteraction,
+    prelude::Context,
+};
+
+use crate::data::TTSData;
+
+pub async fn addchannel_command(
+    ctx: &Context,
+    command: &CommandInteraction,
+) -> Result<(), Box<dyn std::error::Error>> {
+    set_channel(ctx, command, true).await
+}
+
+pub async fn removechannel_command(
+    ctx: &Context,
+    command: &CommandInteraction,
+) -> Result<(), Box<dyn std::error::Error>> {
+    set_channel(ctx, command, false).await
+}
+
+/// Add or remove the invoking channel from the instance's read channels.
+async fn set_channel(
+    ctx: &Context,
+    command: &CommandInteraction,
+    add: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let guild_id = match command.guild_id {
+        Some(guild_id) => guild_id,
+        None => {
+            command
+                .create_response(
+                    &ctx.http,
+                    CreateInteractionResponse::Message(
+                        CreateInteractionResponseMessage::new()
+                            .content("このコマンドはサーバーでのみ使用可能です．")
+                            .ephemeral(true),
+                    ),
+                )
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let storage_lock = {
+        let data_read = ctx.data.read().await;
+        data_read
+            .get::<TTSData>()
+            .expect("Cannot get TTSStorage")
+            .clone()
+    };
+
+    let instance = storage_lock.read().await.get(&guild_id).cloned();
+    let content = match instance {
+        None => "読み上げしていません",
+        Some(instance) => {
+            let mut instance = instance.lock().await;
+            if add {
+                if instance.add_text_channel(command.channel_id) {
+                    "このチャンネルを読み上げ対象に追加しました"
+                } else {
+                    "このチャンネルはすでに読み上げ対象です"
+                }
+            } else if instance.text_channel == command.channel_id {
+                "読み上げを開始したチャンネルは外せません．`/stop` で停止してください．"
+            } else if instance.remove_text_channel(command.channel_id) {
+                "このチャンネルを読み上げ対象から外しました"
+            } else {
+                "このチャンネルは読み上げ対象に追加されていません"
+            }
+        }
+    };
+
+    command
+        .create_response(
+            &ctx.http,
+            CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::new()
+                    .content(content)
+                    .ephemeral(true),
+            ),
+        )
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,114 @@
+use serenity::{
+    builder::{
+        CreateInteractionResponse, CreateInteractionResponseMessage, EditInteractionResponse,
+    },
+    model::prelude::CommandInteraction,
+    prelude::Context,
+};
+
+use crate::{commands::utils::is_owner, data::TTSData};
+
+/// Whether an instance is stale and should be removed:
+/// it has no live voice connection and nobody is in its voice channel.
+/// A connected instance waiting for users, or one reconnecting to users, is kept.
+fn should_remove(connected: bool, has_users: bool) -> bool {
+    !connected && !has_users
+}
+
+pub async fn cleanup_command(
+    ctx: &Context,
+    command: &CommandInteraction,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if !is_owner(ctx, command.user.id).await {
+        command
+            .create_response(
+                &ctx.http,
+                CreateInteractionResponse::Message(
+                    CreateInteractionResponseMessage::new()
+                        .content("このコマンドはBotのオーナーのみ使用可能です．")
+                        .ephemeral(true),
+                ),
+            )
+            .await?;
+        return Ok(());
+    }
+
+    command
+        .create_response(
+            &ctx.http,
+            CreateInteractionResponse::Defer(
+                CreateInteractionResponseMessage::new().ephemeral(true),
+            ),
+        )
+        .await?;
+
+    let manager = songbird::get(ctx).await;
+    let bot_id = ctx.cache.current_user().id;
+
+    let storage_lock = {
+        let data_read = ctx.data.read().await;
+        data_read
+            .get::<TTSData>()
+            .expect("Cannot get TTSStorage")
+            .clone()
+    };
+
+    let mut removed = 0;
+    {
+        let instances = storage_lock
+            .read()
+            .await
+            .iter()
+            .map(|(guild_id, instance)| (*guild_id, instance.clone()))
+            .collect::<Vec<_>>();
+        for (guild_id, instance) in instances {
+            let voice_channel = instance.lock().await.voice_channel;
+            let connected = manager
+                .as_ref()
+                .is_some_and(|manager| manager.get(guild_id).is_some());
+            let has_users = guild_id.to_guild_cached(&ctx.cache).is_some_and(|guild| {
+                guild
+                    .voice_states
+                    .values()
+                    .any(|state| state.channel_id == Some(voice_channel) && state.user_id != bot_id)
+            });
+
+            if !should_remove(connected, has_users) {
+                continue;
+            }
+
+            storage_lock.write().await.remove(&guild_id);
+            if connected {
+                if let Some(manager) = &manager {
+                    if let Err(err) = manager.remove(guild_id).await {
+                        println!("Cannot leave voice channel: {:?}", err);
+                    }
+                }
+            }
+            removed += 1;
+        }
+    }
+
+    command
+        .edit_response(
+            &ctx.http,
+            EditInteractionResponse::new()
+                .content(format!("{}件のインスタンスを削除しました", removed)),
+        )
+        .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn removes_only_disconnected_instances_without_users() {
+        assert!(should_remove(false, false));
+        assert!(!should_remove(false, true));
+        assert!(!should_remove(true, false));
+        assert!(!should_remove(true, true));
+    }
+}
@@ -1,20 +1,89 @@
 use serenity::{
-    model::prelude::{
-        component::ButtonStyle,
-        interaction::{application_command::ApplicationCommandInteraction, MessageFlags},
+    builder::{
+        CreateActionRow, CreateButton, CreateEmbed, CreateInteractionResponse,
+        CreateInteractionResponseMessage, CreateSelectMenu, CreateSelectMenuKind,
+        CreateSelectMenuOption,
     },
+    model::prelude::{ButtonStyle, CommandInteraction},
     prelude::Context,
 };
 
 use crate::{
     data::{DatabaseClientData, TTSClientData},
-    tts::tts_type::TTSType,
+    database::user_config::{UserConfig, DEFAULT_PITCH, DEFAULT_SPEAKING_RATE},
+    tts::{azure::azure::DEFAULT_AZURE_VOICE, tts_type::TTSType},
 };
 
+/// Maximum number of VOICEVOX styles in the config menu.
+/// Only one select menu row is left, so the rest are reachable with /setspeaker.
+const MAX_MENU_STYLES: usize = 24;
+
+/// Styles shown in the VOICEVOX select menu.
+/// Shorter lists, such as the fallback when the API is unavailable, are returned whole.
+///
+/// Example:
+/// ```rust
+/// assert_eq!(menu_styles(&styles[..1]).len(), 1);
+/// assert_eq!(menu_styles(&styles[..30]).len(), 24);
+/// ```
+pub fn menu_styles(styles: &[(String, i64)]) -> &[(String, i64)] {
+    &styles[..styles.len().min(MAX_MENU_STYLES)]
+}
+
+/// Describe the user's effective voice settings for the config embed.
+pub fn describe_user_config(config: &UserConfig) -> String {
+    let engine = match config.tts_type.clone().unwrap_or(TTSType::GCP) {
+        TTSType::GCP => "Google TTS",
+        TTSType::VOICEVOX => "VOICEVOX",
+        TTSType::Azure => "Azure",
+    };
+    let gcp_voice = config
+        .gcp_tts_voice
+        .as_ref()
+        .map_or("未設定".to_string(), |voice| voice.name.clone());
+
+    format!(
+        "エンジン: {}\nGoogle TTS 音声: {}\nVOICEVOX Speaker: {}\nAzure 音声: {}\n速度: {:.1}\nピッチ: {:.1}",
+        engine,
+        gcp_voice,
+        config.voicevox_speaker.unwrap_or(1),
+        config.azure_voice.as_deref().unwrap_or(DEFAULT_AZURE_VOICE),
+        config.speaking_rate.unwrap_or(DEFAULT_SPEAKING_RATE),
+        config.pitch.unwrap_or(DEFAULT_PITCH),
+    )
+}
+
+/// Map the `engine` option of /config to a TTS engine.
+pub fn parse_engine(value: &str) -> Option<TTSType> {
+    match value {
+        "google" => Some(TTSType::GCP),
+        "voicevox" => Some(TTSType::VOICEVOX),
+        "azure" => Some(TTSType::Azure),
+        _ => None,
+    }
+}
+
+/// Whether the engine can be used with the configured clients.
+/// Azure needs a key in the config file; the other engines are always available.
+pub fn engine_available(engine: &TTSType, azure_configured: bool) -> bool {
+    *engine != TTSType::Azure || azure_configured
+}
+
 pub async fn config_command(
     ctx: &Context,
-    command: &ApplicationCommandInteraction,
+    command: &CommandInteraction,
 ) -> Result<(), Box<dyn std::error::Error>> {
+    let engine = command
+        .data
+        .options
+        .iter()
+        .find(|option| option.name == "engine")
+        .map(|option| option.value.clone())
+        .and_then(|value| value.as_str().and_then(parse_engine));
+    if let Some(engine) = engine {
+        return set_engine(ctx, command, engine).await;
+    }
+
     let data_read = ctx.data.read().await;
 
     let config = {
@@ -24,7 +93,10 @@ pub async fn config_command(
             .clone();
         let mut database = database.lock().await;
         database
-            .get_user_config_or_default(command.user.id.0)
+            .get_user_config_or_guild_default(
+                command.user.id.get(),
+                command.guild_id.map(|guild_id| guild_id.get()),
+            )
             .await
             .unwrap()
             .unwrap()
@@ -34,82 +106,285 @@ pub async fn config_command(
         .get::<TTSClientData>()
         .expect("Cannot get TTSClientData")
         .clone();
-    let voicevox_speakers = tts_client.lock().await.1.get_styles().await;
+    let (voicevox, azure_available) = {
+        let tts_client = tts_client.lock().await;
+        (tts_client.1.clone(), tts_client.2.is_some())
+    };
+    let voicevox_speakers = voicevox.get_styles().await;
 
+    let description = describe_user_config(&config);
     let voicevox_speaker = config.voicevox_speaker.unwrap_or(1);
     let tts_type = config.tts_type.unwrap_or(TTSType::GCP);
+    let read_own_name = config.read_own_name.unwrap_or(true);
+    let effects_profile = config.gcp_effects_profile.clone();
 
-    command
-        .create_interaction_response(&ctx.http, |f| {
-            f.interaction_response_data(|d| {
-                d.content("読み上げ設定")
-                    .components(|c| {
-                        let mut c = c;
-                        c = c
-                            .create_action_row(|a| {
-                                a.create_select_menu(|m| {
-                                    m.custom_id("TTS_CONFIG_ENGINE")
-                                        .options(|o| {
-                                            o.create_option(|co| {
-                                                co.label("Google TTS")
-                                                    .value("TTS_CONFIG_ENGINE_SELECTED_GOOGLE")
-                                                    .default_selection(tts_type == TTSType::GCP)
-                                            })
-                                            .create_option(|co| {
-                                                co.label("VOICEVOX")
-                                                    .value("TTS_CONFIG_ENGINE_SELECTED_VOICEVOX")
-                                                    .default_selection(
-                                                        tts_type == TTSType::VOICEVOX,
-                                                    )
-                                            })
-                                        })
-                                        .placeholder("読み上げAPIを選択")
-                                })
-                            })
-                            .create_action_row(|a| {
-                                a.create_button(|f| {
-                                    f.label("サーバー設定")
-                                        .custom_id("TTS_CONFIG_SERVER")
-                                        .style(ButtonStyle::Primary)
-                                })
-                            });
-
-                        for (index, speaker_chunk) in
-                            voicevox_speakers[0..24].chunks(25).enumerate()
-                        {
-                            c = c.create_action_row(|a| {
-                                let mut a = a;
-                                a = a.create_select_menu(|m| {
-                                    m.custom_id(
-                                        "TTS_CONFIG_VOICEVOX_SPEAKER_".to_string()
-                                            + &index.to_string(),
-                                    )
-                                    .options(|o| {
-                                        let mut o = o;
-                                        for (name, id) in speaker_chunk {
-                                            o = o.create_option(|co| {
-                                                co.label(name)
-                                                    .value(format!(
-                                                        "TTS_CONFIG_VOICEVOX_SPEAKER_SELECTED_{}",
-                                                        id
-                                                    ))
-                                                    .default_selection(*id == voicevox_speaker)
-                                            })
-                                        }
-                                        o
-                                    })
-                                    .placeholder("VOICEVOX Speakerを指定")
-                                });
-                                a
-                            })
-                        }
-
-                        println!("{:?}", c);
-                        c
-                    })
-                    .flags(MessageFlags::EPHEMERAL)
+    let mut engine_options = vec![
+        CreateSelectMenuOption::new("Google TTS", "TTS_CONFIG_ENGINE_SELECTED_GOOGLE")
+            .default_selection(tts_type == TTSType::GCP),
+        CreateSelectMenuOption::new("VOICEVOX", "TTS_CONFIG_ENGINE_SELECTED_VOICEVOX")
+            .default_selection(tts_type == TTSType::VOICEVOX),
+    ];
+    if azure_available {
+        engine_options.push(
+            CreateSelectMenuOption::new("Azure", "TTS_CONFIG_ENGINE_SELECTED_AZURE")
+                .default_selection(tts_type == TTSType::Azure),
+        );
+    }
+
+    let mut buttons = vec![
+        CreateButton::new("TTS_CONFIG_SERVER")
+            .label("サーバー設定")
+            .style(ButtonStyle::Primary),
+        CreateButton::new("TTS_CONFIG_TOGGLE_READ_OWN_NAME")
+            .label(if read_own_name {
+                "名前読み上げ: ON"
+            } else {
+                "名前読み上げ: OFF"
+            })
+            .style(ButtonStyle::Secondary),
+        CreateButton::new("TTS_CONFIG_EFFECTS_PROFILE")
+            .label(format!(
+                "音声プロファイル: {}",
+                effects_profile.as_deref().unwrap_or("なし")
+            ))
+            .style(ButtonStyle::Secondary),
+        CreateButton::new("TTS_CONFIG_CUSTOM_JOIN_BUTTON")
+            .label("参加メッセージ")
+            .style(ButtonStyle::Secondary),
+    ];
+    if azure_available {
+        buttons.push(
+            CreateButton::new("TTS_CONFIG_AZURE_VOICE_BUTTON")
+                .label("Azure音声")
+                .style(ButtonStyle::Secondary),
+        );
+    }
+
+    let mut components = vec![
+        CreateActionRow::SelectMenu(
+            CreateSelectMenu::new(
+                "TTS_CONFIG_ENGINE",
+                CreateSelectMenuKind::String {
+                    options: engine_options,
+                },
+            )
+            .placeholder("読み上げAPIを選択"),
+        ),
+        CreateActionRow::Buttons(buttons),
+        CreateActionRow::Buttons(vec![
+            CreateButton::new("TTS_CONFIG_RATE_DOWN")
+                .label("速度 -")
+                .style(ButtonStyle::Secondary),
+            CreateButton::new("TTS_CONFIG_RATE_UP")
+                .label("速度 +")
+                .style(ButtonStyle::Secondary),
+            CreateButton::new("TTS_CONFIG_PITCH_DOWN")
+                .label("ピッチ -")
+                .style(ButtonStyle::Secondary),
+            CreateButton::new("TTS_CONFIG_PITCH_UP")
+                .label("ピッチ +")
+                .style(ButtonStyle::Secondary),
+        ]),
+    ];
+
+    for (index, speaker_chunk) in menu_styles(&voicevox_speakers).chunks(25).enumerate() {
+        let options = speaker_chunk
+            .iter()
+            .map(|(name, id)| {
+                CreateSelectMenuOption::new(
+                    name,
+                    format!("TTS_CONFIG_VOICEVOX_SPEAKER_SELECTED_{}", id),
+                )
+                .default_selection(*id == voicevox_speaker)
             })
-        })
+            .collect();
+        components.push(CreateActionRow::SelectMenu(
+            CreateSelectMenu::new(
+                "TTS_CONFIG_VOICEVOX_SPEAKER_".to_string() + &index.to_string(),
+                CreateSelectMenuKind::String { options },
+            )
+            .placeholder("VOICEVOX Speakerを指定"),
+        ));
+    }
+
+    println!("{:?}", components);
+
+    command
+        .create_response(
+            &ctx.http,
+            CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::new()
+                    .content("読み上げ設定")
+                    .embed(
+                        CreateEmbed::new()
+                            .title("現在の設定")
+                            .description(description),
+                    )
+                    .components(components)
+                    .ephemeral(true),
+            ),
+        )
+        .await?;
+    Ok(())
+}
+
+/// Write the engine to the user's config and confirm.
+async fn set_engine(
+    ctx: &Context,
+    command: &CommandInteraction,
+    engine: TTSType,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let azure_configured = {
+        let data_read = ctx.data.read().await;
+        let tts_client = data_read
+            .get::<TTSClientData>()
+            .expect("Cannot get TTSClientData")
+            .clone();
+        let azure_configured = tts_client.lock().await.2.is_some();
+        azure_configured
+    };
+    if !engine_available(&engine, azure_configured) {
+        command
+            .create_response(
+                &ctx.http,
+                CreateInteractionResponse::Message(
+                    CreateInteractionResponseMessage::new()
+                        .content("Azureは設定されていないため使用できません")
+                        .ephemeral(true),
+                ),
+            )
+            .await?;
+        return Ok(());
+    }
+
+    {
+        let data_read = ctx.data.read().await;
+        let database = data_read
+            .get::<DatabaseClientData>()
+            .expect("Cannot get DatabaseClientData")
+            .clone();
+        let mut database = database.lock().await;
+        let mut config = database
+            .get_user_config_or_guild_default(
+                command.user.id.get(),
+                command.guild_id.map(|guild_id| guild_id.get()),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        config.tts_type = Some(engine);
+        database
+            .set_user_config(command.user.id.get(), config)
+            .await
+            .unwrap();
+    }
+
+    command
+        .create_response(
+            &ctx.http,
+            CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::new()
+                    .content("読み上げAPIを変更しました")
+                    .ephemeral(true),
+            ),
+        )
         .await?;
+
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tts::gcp_tts::structs::voice_selection_params::VoiceSelectionParams;
+
+    fn styles(n: usize) -> Vec<(String, i64)> {
+        (0..n).map(|i| (format!("style {}", i), i as i64)).collect()
+    }
+
+    #[test]
+    fn engine_option_maps_to_engines() {
+        assert_eq!(parse_engine("google"), Some(TTSType::GCP));
+        assert_eq!(parse_engine("voicevox"), Some(TTSType::VOICEVOX));
+        assert_eq!(parse_engine("azure"), Some(TTSType::Azure));
+        assert_eq!(parse_engine("Google"), None);
+        assert_eq!(parse_engine("polly"), None);
+    }
+
+    #[test]
+    fn azure_needs_configuration() {
+        assert!(!engine_available(&TTSType::Azure, false));
+        assert!(engine_available(&TTSType::Azure, true));
+        assert!(engine_available(&TTSType::GCP, false));
+        assert!(engine_available(&TTSType::VOICEVOX, false));
+    }
+
+    #[test]
+    fn menu_styles_keeps_short_lists() {
+        assert!(menu_styles(&styles(0)).is_empty());
+        assert_eq!(menu_styles(&styles(1)), &styles(1)[..]);
+    }
+
+    #[test]
+    fn menu_styles_caps_long_lists() {
+        let styles = styles(30);
+        assert_eq!(menu_styles(&styles).len(), MAX_MENU_STYLES);
+        assert_eq!(menu_styles(&styles), &styles[..MAX_MENU_STYLES]);
+    }
+
+    fn empty_config() -> UserConfig {
+        UserConfig {
+            tts_type: None,
+            gcp_tts_voice: None,
+            voicevox_speaker: None,
+            opt_out: None,
+            read_own_name: None,
+            speaking_rate: None,
+            pitch: None,
+            azure_voice: None,
+            custom_join_message: None,
+            gcp_effects_profile: None,
+        }
+    }
+
+    #[test]
+    fn unset_values_are_described_with_defaults() {
+        assert_eq!(
+            describe_user_config(&empty_config()),
+            "エンジン: Google TTS\nGoogle TTS 音声: 未設定\nVOICEVOX Speaker: 1\nAzure 音声: ja-JP-NanamiNeural\n速度: 1.2\nピッチ: 1.0"
+        );
+    }
+
+    #[test]
+    fn each_engine_is_named() {
+        let describe = |tts_type| {
+            describe_user_config(&UserConfig {
+                tts_type: Some(tts_type),
+                ..empty_config()
+            })
+        };
+        assert!(describe(TTSType::GCP).starts_with("エンジン: Google TTS\n"));
+        assert!(describe(TTSType::VOICEVOX).starts_with("エンジン: VOICEVOX\n"));
+        assert!(describe(TTSType::Azure).starts_with("エンジン: Azure\n"));
+    }
+
+    #[test]
+    fn set_values_are_described() {
+        let config = UserConfig {
+            gcp_tts_voice: Some(VoiceSelectionParams {
+                languageCode: String::from("ja-JP"),
+                name: String::from("ja-JP-Wavenet-C"),
+                ssmlGender: String::from("neutral"),
+            }),
+            voicevox_speaker: Some(3),
+            azure_voice: Some(String::from("ja-JP-KeitaNeural")),
+            speaking_rate: Some(1.5),
+            pitch: Some(-2.0),
+            ..empty_config()
+        };
+        assert_eq!(
+            describe_user_config(&config),
+            "エンジン: Google TTS\nGoogle TTS 音声: ja-JP-Wavenet-C\nVOICEVOX Speaker: 3\nAzure 音声: ja-JP-KeitaNeural\n速度: 1.5\nピッチ: -2.0"
+        );
+    }
+}
@@ -0,0 +1,234 @@
+use std::time::{Duration, Instant};
+
+use serenity::{
+    builder::{
+        CreateEmbed, CreateInteractionResponse, CreateInteractionResponseMessage,
+        EditInteractionResponse,
+    },
+    model::prelude::{CommandDataOptionValue, CommandInteraction},
+    prelude::Context,
+};
+
+use crate::{
+    commands::utils::is_owner,
+    data::{DatabaseClientData, TTSClientData},
+    database::{server_config::ServerConfig, user_config::DEFAULT_SPEAKING_RATE},
+    implement::message::{
+        apply_text_rules, is_effectively_empty, is_ssml_trusted, message_chunks,
+        parse_inline_speed, strip_content, to_ssml,
+    },
+    tts::{
+        message::{engine_clients, synthesize_chunk, SynthesisVoice},
+        ssml,
+        tts_type::TTSType,
+    },
+};
+
+/// Result of a diagnostic synthesis run.
+/// Synthesis is never cached, so every run reaches the engine.
+pub struct DiagResult {
+    pub engine: TTSType,
+    pub text: String,
+    pub chunks: usize,
+    pub config_elapsed: Duration,
+    pub parse_elapsed: Duration,
+    pub synthesize_elapsed: Duration,
+    pub audio_size: Option<usize>,
+    pub error: Option<String>,
+}
+
+impl DiagResult {
+    /// Format the result for the diagnostic response.
+    pub fn describe(&self) -> String {
+        let total = self.config_elapsed + self.parse_elapsed + self.synthesize_elapsed;
+        let result = match (&self.audio_size, &self.error) {
+            (_, Some(error)) => format!("失敗: {}", error),
+            (Some(size), None) => format!("成功 ({} bytes)", size),
+            (None, None) => String::from("不明"),
+        };
+
+        format!(
+            "エンジン: {:?}\n読み上げテキスト: {}\n分割数: {}\nキャッシュ: キャッシュなし\n設定取得: {}ms\n変換: {}ms\n音声合成: {}ms\n合計: {}ms\n結果: {}",
+            self.engine,
+            self.text,
+            self.chunks,
+            self.config_elapsed.as_millis(),
+            self.parse_elapsed.as_millis(),
+            self.synthesize_elapsed.as_millis(),
+            total.as_millis(),
+            result
+        )
+    }
+}
+
+pub async fn diag_command(
+    ctx: &Context,
+    command: &CommandInteraction,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if !is_owner(ctx, command.user.id).await {
+        command
+            .create_response(
+                &ctx.http,
+                CreateInteractionResponse::Message(
+                    CreateInteractionResponseMessage::new()
+                        .content("このコマンドはBotのオーナーのみ使用可能です．")
+                        .ephemeral(true),
+                ),
+            )
+            .await?;
+        return Ok(());
+    }
+
+    let input = command
+        .data
+        .options
+        .first()
+        .and_then(|subcommand| match &subcommand.value {
+            CommandDataOptionValue::SubCommand(options) => options.first(),
+            _ => None,
+        })
+        .map(|option| option.value.clone())
+        .and_then(|value| value.as_str().map(|value| value.to_string()))
+        .unwrap_or_default();
+
+    command
+        .create_response(
+            &ctx.http,
+            CreateInteractionResponse::Defer(
+                CreateInteractionResponseMessage::new().ephemeral(true),
+            ),
+        )
+        .await?;
+
+    let data_read = ctx.data.read().await;
+
+    let start = Instant::now();
+    let (user_config, server_config) = {
+        let database = data_read
+            .get::<DatabaseClientData>()
+            .expect("Cannot get DatabaseClientData")
+            .clone();
+        let mut database = database.lock().await;
+        let user_config = database
+            .get_user_config_or_guild_default(
+                command.user.id.get(),
+                command.guild_id.map(|guild_id| guild_id.get()),
+            )
+            .await?
+            .ok_or("Cannot get user config")?;
+        let server_config = match command.guild_id {
+            Some(guild_id) => database
+                .get_server_config_or_default(guild_id.get())
+                .await?
+                .unwrap_or_default(),
+            None => ServerConfig::default(),
+        };
+        (user_config, server_config)
+    };
+    let config_elapsed = start.elapsed();
+
+    let start = Instant::now();
+    let text = apply_text_rules(&strip_content(&input, &server_config), &server_config);
+    let empty = is_effectively_empty(&text);
+    let trusted = command.member.as_ref().is_some_and(|member| {
+        is_ssml_trusted(member.roles.iter().map(|role| role.get()), &server_config)
+    });
+    let text = to_ssml(&text, &server_config, trusted);
+    let chunks = message_chunks(text.clone(), &server_config);
+    let parse_elapsed = start.elapsed();
+
+    let speaking_rate = if server_config.allow_inline_speed.unwrap_or(false) {
+        parse_inline_speed(&input).0
+    } else {
+        None
+    }
+    .or(user_config.speaking_rate)
+    .unwrap_or(DEFAULT_SPEAKING_RATE);
+    let voice = SynthesisVoice::from_user_config(&user_config, speaking_rate);
+
+    let start = Instant::now();
+    let audio = if empty {
+        Err(String::from("読み上げるテキストがありません"))
+    } else {
+        let storage = data_read
+            .get::<TTSClientData>()
+            .expect("Cannot get TTSClientData")
+            .clone();
+        let mut tts = engine_clients(&storage, std::slice::from_ref(&voice)).await;
+
+        let mut audio = Ok(0);
+        for chunk in &chunks {
+            match synthesize_chunk(&mut tts, &voice, chunk).await {
+                Ok(chunk_audio) => audio = audio.map(|size| size + chunk_audio.len()),
+                Err(err) => {
+                    audio = Err(err.to_string());
+                    break;
+                }
+            }
+        }
+        audio
+    };
+    let synthesize_elapsed = start.elapsed();
+
+    let result = DiagResult {
+        engine: voice.tts_type,
+        text: ssml::to_plain_text(&text),
+        chunks: chunks.len(),
+        config_elapsed,
+        parse_elapsed,
+        synthesize_elapsed,
+        audio_size: audio.as_ref().ok().copied(),
+        error: audio.err(),
+    };
+
+    command
+        .edit_response(
+            &ctx.http,
+            EditInteractionResponse::new().embed(
+                CreateEmbed::new()
+                    .title("音声合成診断")
+                    .description(result.describe()),
+            ),
+        )
+        .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(audio_size: Option<usize>, error: Option<&str>) -> DiagResult {
+        DiagResult {
+            engine: TTSType::VOICEVOX,
+            text: String::from("こんにちは"),
+            chunks: 2,
+            config_elapsed: Duration::from_millis(5),
+            parse_elapsed: Duration::from_millis(10),
+            synthesize_elapsed: Duration::from_millis(300),
+            audio_size,
+            error: error.map(String::from),
+        }
+    }
+
+    #[test]
+    fn describe_reports_each_stage() {
+        let description = result(Some(2048), None).describe();
+        assert!(description.contains("エンジン: VOICEVOX"));
+        assert!(description.contains("読み上げテキスト: こんにちは"));
+        assert!(description.contains("分割数: 2"));
+        assert!(description.contains("キャッシュ: キャッシュなし"));
+        assert!(description.contains("設定取得: 5ms"));
+        assert!(description.contains("変換: 10ms"));
+        assert!(description.contains("音声合成: 300ms"));
+        assert!(description.contains("合計: 315ms"));
+        assert!(description.contains("結果: 成功 (2048 bytes)"));
+    }
+
+    #[test]
+    fn describe_prefers_the_error() {
+        let description = result(Some(10), Some("timeout")).describe();
+        assert!(description.contains("結果: 失敗: timeout"));
+    }
+}
@@ -0,0 +1,34 @@
+use serenity::{
+    builder::{CreateInteractionResponse, CreateInteractionResponseMessage},
+    model::prelude::CommandInteraction,
+    prelude::Context,
+};
+
+use crate::data::ConfigData;
+
+pub async fn feedback_command(
+    ctx: &Context,
+    command: &CommandInteraction,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let feedback_url = ctx
+        .data
+        .read()
+        .await
+        .get::<ConfigData>()
+        .expect("Cannot get ConfigData")
+        .feedback_url()
+        .to_string();
+
+    command
+        .create_response(
+            &ctx.http,
+            CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::new()
+                    .content(format!("フィードバックはこちらから: {}", feedback_url))
+                    .ephemeral(true),
+            ),
+        )
+        .await?;
+
+    Ok(())
+}
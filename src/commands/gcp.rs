@@ -0,0 +1,127 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serenity::{
+    builder::{
+        CreateActionRow, CreateButton, CreateEmbed, CreateInteractionResponse,
+        CreateInteractionResponseMessage,
+    },
+    model::prelude::{ButtonStyle, CommandInteraction},
+    prelude::Context,
+};
+
+use crate::{commands::utils::is_owner, data::TTSClientData, tts::gcp_tts::gcp_tts::TTS};
+
+/// Describe the GCP token for /gcp: whether it is valid, when it was fetched,
+/// when it expires and how long is left. Times are unix seconds.
+///
+/// Example:
+/// ```rust
+/// let status = describe_token_status(false, updated_at, now + 600, now);
+/// assert!(status.contains("残り10分"));
+/// ```
+pub fn describe_token_status(
+    expired: bool,
+    updated_at: SystemTime,
+    expires_at: i64,
+    now: i64,
+) -> String {
+    let updated_at = updated_at
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| format!("<t:{}:R>", elapsed.as_secs()))
+        .unwrap_or_else(|_| String::from("不明"));
+    let remaining = expires_at - now;
+    let remaining = if remaining <= 0 {
+        String::from("期限切れ")
+    } else {
+        format!("残り{}分{}秒", remaining / 60, remaining % 60)
+    };
+    format!(
+        "トークン: {}\n最終更新: {}\n有効期限: <t:{}:T> ({})",
+        if expired { "期限切れ" } else { "有効" },
+        updated_at,
+        expires_at,
+        remaining
+    )
+}
+
+/// `describe_token_status` for the client's current token.
+pub fn describe_token(tts: &TTS) -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs() as i64)
+        .unwrap_or(0);
+    describe_token_status(
+        tts.token.has_expired(),
+        tts.token_updated_at,
+        tts.token.expires_at().timestamp(),
+        now,
+    )
+}
+
+pub async fn gcp_command(
+    ctx: &Context,
+    command: &CommandInteraction,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if !is_owner(ctx, command.user.id).await {
+        command
+            .create_response(
+                &ctx.http,
+                CreateInteractionResponse::Message(
+                    CreateInteractionResponseMessage::new()
+                        .content("このコマンドはBotのオーナーのみ使用可能です．")
+                        .ephemeral(true),
+                ),
+            )
+            .await?;
+        return Ok(());
+    }
+
+    let status = {
+        let data_read = ctx.data.read().await;
+        let tts_client = data_read
+            .get::<TTSClientData>()
+            .expect("Cannot get TTSClientData")
+            .clone();
+        let tts_client = tts_client.lock().await;
+        describe_token(&tts_client.0)
+    };
+
+    command
+        .create_response(
+            &ctx.http,
+            CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::new()
+                    .embed(CreateEmbed::new().title("GCP 認証").description(status))
+                    .components(vec![CreateActionRow::Buttons(vec![CreateButton::new(
+                        "GCP_REFRESH_TOKEN",
+                    )
+                    .label("トークンを更新")
+                    .style(ButtonStyle::Primary)])])
+                    .ephemeral(true),
+            ),
+        )
+        .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shows_expiry_and_time_left() {
+        let status = describe_token_status(false, UNIX_EPOCH, 4_000, 3_000);
+        assert_eq!(
+            status,
+            "トークン: 有効\n最終更新: <t:0:R>\n有効期限: <t:4000:T> (残り16分40秒)"
+        );
+    }
+
+    #[test]
+    fn shows_expired_tokens() {
+        let status = describe_token_status(true, UNIX_EPOCH, 3_000, 3_000);
+        assert!(status.starts_with("トークン: 期限切れ"));
+        assert!(status.ends_with("(期限切れ)"));
+    }
+}
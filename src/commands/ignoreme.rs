@@ -0,0 +1,52 @@
+use serenity::{
+    builder::{CreateInteractionResponse, CreateInteractionResponseMessage},
+    model::prelude::CommandInteraction,
+    prelude::Context,
+};
+
+use crate::data::DatabaseClientData;
+
+pub async fn ignoreme_command(
+    ctx: &Context,
+    command: &CommandInteraction,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let opt_out = {
+        let data_read = ctx.data.read().await;
+        let database = data_read
+            .get::<DatabaseClientData>()
+            .expect("Cannot get DatabaseClientData")
+            .clone();
+        let mut database = database.lock().await;
+        let mut config = database
+            .get_user_config_or_guild_default(
+                command.user.id.get(),
+                command.guild_id.map(|guild_id| guild_id.get()),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        let opt_out = config.toggle_opt_out();
+        database
+            .set_user_config(command.user.id.get(), config)
+            .await
+            .unwrap();
+        opt_out
+    };
+
+    command
+        .create_response(
+            &ctx.http,
+            CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::new()
+                    .content(if opt_out {
+                        "あなたのメッセージを読み上げないように設定しました"
+                    } else {
+                        "あなたのメッセージを読み上げるように設定しました"
+                    })
+                    .ephemeral(true),
+            ),
+        )
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,11 @@
+use serenity::{model::prelude::CommandInteraction, prelude::Context};
+
+use super::setup::start_tts;
+
+/// Join the caller's voice channel and read its text chat.
+pub async fn join_command(
+    ctx: &Context,
+    command: &CommandInteraction,
+) -> Result<(), Box<dyn std::error::Error>> {
+    start_tts(ctx, command, "VOICE_CHANNEL").await
+}
@@ -1,4 +1,21 @@
+pub mod broadcast;
+pub mod channel;
+pub mod cleanup;
 pub mod config;
+pub mod diag;
+pub mod feedback;
+pub mod gcp;
+pub mod ignoreme;
+pub mod join;
+pub mod mute;
+pub mod ping;
+pub mod resetconfig;
+pub mod schedule;
+pub mod serverconfig;
+pub mod setspeaker;
 pub mod setup;
+pub mod setvoice;
 pub mod skip;
+pub mod skipall;
 pub mod stop;
+pub mod utils;
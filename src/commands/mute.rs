@@ -0,0 +1,106 @@
+use serenity::{
+    builder::{CreateInteractionResponse, CreateInteractionResponseMessage},
+    model::prelude::{CommandInteraction, ResolvedValue},
+    prelude::Context,
+};
+
+use crate::data::TTSData;
+
+pub async fn mute_command(
+    ctx: &Context,
+    command: &CommandInteraction,
+) -> Result<(), Box<dyn std::error::Error>> {
+    set_muted(ctx, command, true).await
+}
+
+pub async fn unmute_command(
+    ctx: &Context,
+    command: &CommandInteraction,
+) -> Result<(), Box<dyn std::error::Error>> {
+    set_muted(ctx, command, false).await
+}
+
+/// Add or remove the user given in the first option from the instance's muted users.
+async fn set_muted(
+    ctx: &Context,
+    command: &CommandInteraction,
+    muted: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let guild_id = match command.guild_id {
+        Some(guild_id) => guild_id,
+        None => {
+            command
+                .create_response(
+                    &ctx.http,
+                    CreateInteractionResponse::Message(
+                        CreateInteractionResponseMessage::new()
+                            .content("このコマンドはサーバーでのみ使用可能です．")
+                            .ephemeral(true),
+                    ),
+                )
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let user = match command.data.options().first().map(|option| &option.value) {
+        Some(ResolvedValue::User(user, _)) => (*user).clone(),
+        _ => {
+            command
+                .create_response(
+                    &ctx.http,
+                    CreateInteractionResponse::Message(
+                        CreateInteractionResponseMessage::new()
+                            .content("ユーザーを指定してください．")
+                            .ephemeral(true),
+                    ),
+                )
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let storage_lock = {
+        let data_read = ctx.data.read().await;
+        data_read
+            .get::<TTSData>()
+            .expect("Cannot get TTSStorage")
+            .clone()
+    };
+
+    let changed = {
+        let storage = storage_lock.read().await;
+        match storage.get(&guild_id) {
+            Some(instance) => {
+                let mut instance = instance.lock().await;
+                Some(if muted {
+                    instance.mute_user(user.id)
+                } else {
+                    instance.unmute_user(user.id)
+                })
+            }
+            None => None,
+        }
+    };
+
+    let content = match (changed, muted) {
+        (None, _) => String::from("読み上げしていません"),
+        (Some(true), true) => format!("{} さんの読み上げをミュートしました", user.name),
+        (Some(false), true) => format!("{} さんはすでにミュートされています", user.name),
+        (Some(true), false) => format!("{} さんのミュートを解除しました", user.name),
+        (Some(false), false) => format!("{} さんはミュートされていません", user.name),
+    };
+
+    command
+        .create_response(
+            &ctx.http,
+            CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::new()
+                    .content(content)
+                    .ephemeral(true),
+            ),
+        )
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,101 @@
+use std::time::Duration;
+
+use serenity::{
+    builder::{CreateEmbed, CreateInteractionResponse, CreateInteractionResponseMessage},
+    model::prelude::CommandInteraction,
+    prelude::Context,
+};
+
+use crate::{
+    data::{ShardManagerData, StartTimeData},
+    tts::message::{dropped_messages, synthesis_latency_ms},
+};
+
+/// Format an uptime like "2h 13m", with days when longer than a day.
+pub fn format_uptime(uptime: Duration) -> String {
+    let minutes = uptime.as_secs() / 60;
+    let (days, hours, minutes) = (minutes / 1440, minutes / 60 % 24, minutes % 60);
+    if days > 0 {
+        format!("{}d {}h {}m", days, hours, minutes)
+    } else {
+        format!("{}h {}m", hours, minutes)
+    }
+}
+
+pub async fn ping_command(
+    ctx: &Context,
+    command: &CommandInteraction,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (shard_manager, start_time) = {
+        let data_read = ctx.data.read().await;
+        (
+            data_read
+                .get::<ShardManagerData>()
+                .expect("Cannot get ShardManagerData")
+                .clone(),
+            data_read.get::<StartTimeData>().cloned(),
+        )
+    };
+
+    let latency = {
+        let runners = shard_manager.runners.lock().await;
+        runners.get(&ctx.shard_id).and_then(|runner| runner.latency)
+    };
+
+    let latency = match latency {
+        Some(latency) => format!("{}ms", latency.as_millis()),
+        None => String::from("計測中"),
+    };
+    let synthesis_latency = match synthesis_latency_ms() {
+        Some(latency) => format!("{}ms", latency),
+        None => String::from("未計測"),
+    };
+    let uptime = match start_time {
+        Some(start_time) => format_uptime(start_time.elapsed()),
+        None => String::from("不明"),
+    };
+
+    command
+        .create_response(
+            &ctx.http,
+            CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::new()
+                    .embed(
+                        CreateEmbed::new()
+                            .title("Pong!")
+                            .field("Gateway", latency, true)
+                            .field("音声合成 (平均)", synthesis_latency, true)
+                            .field("稼働時間", uptime, true)
+                            .field("読み上げ失敗", dropped_messages().to_string(), true),
+                    )
+                    .ephemeral(true),
+            ),
+        )
+        .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uptime_is_formatted_in_hours_and_minutes() {
+        assert_eq!(format_uptime(Duration::from_secs(0)), "0h 0m");
+        assert_eq!(format_uptime(Duration::from_secs(59)), "0h 0m");
+        assert_eq!(
+            format_uptime(Duration::from_secs(2 * 3600 + 13 * 60 + 30)),
+            "2h 13m"
+        );
+    }
+
+    #[test]
+    fn uptime_over_a_day_includes_days() {
+        assert_eq!(format_uptime(Duration::from_secs(86400)), "1d 0h 0m");
+        assert_eq!(
+            format_uptime(Duration::from_secs(3 * 86400 + 5 * 3600 + 7 * 60)),
+            "3d 5h 7m"
+        );
+    }
+}
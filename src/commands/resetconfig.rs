@@ -0,0 +1,40 @@
+use serenity::{
+    builder::{CreateInteractionResponse, CreateInteractionResponseMessage},
+    model::prelude::CommandInteraction,
+    prelude::Context,
+};
+
+use crate::data::DatabaseClientData;
+
+pub async fn resetconfig_command(
+    ctx: &Context,
+    command: &CommandInteraction,
+) -> Result<(), Box<dyn std::error::Error>> {
+    {
+        let data_read = ctx.data.read().await;
+        let database = data_read
+            .get::<DatabaseClientData>()
+            .expect("Cannot get DatabaseClientData")
+            .clone();
+        let mut database = database.lock().await;
+        database
+            .reset_user_config(
+                command.user.id.get(),
+                command.guild_id.map(|guild_id| guild_id.get()),
+            )
+            .await?;
+    }
+
+    command
+        .create_response(
+            &ctx.http,
+            CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::new()
+                    .content("あなたの設定を初期状態に戻しました")
+                    .ephemeral(true),
+            ),
+        )
+        .await?;
+
+    Ok(())
+}
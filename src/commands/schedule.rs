@@ -0,0 +1,211 @@
+use serenity::{
+    builder::{CreateInteractionResponse, CreateInteractionResponseMessage},
+    model::prelude::{CommandDataOptionValue, CommandInteraction, GuildId},
+    prelude::Context,
+};
+
+use crate::{
+    data::DatabaseClientData,
+    database::{
+        schedule::{
+            next_schedule_id, Schedule, MAX_SCHEDULES, MAX_SCHEDULE_INTERVAL_MINUTES,
+            MAX_SCHEDULE_PHRASE_LENGTH, MIN_SCHEDULE_INTERVAL_MINUTES,
+        },
+        server_config::ServerConfig,
+    },
+};
+
+pub async fn schedule_command(
+    ctx: &Context,
+    command: &CommandInteraction,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let guild_id = match command.guild_id {
+        Some(guild_id) => guild_id,
+        None => {
+            return reply(ctx, command, "このコマンドはサーバーでのみ使用可能です．").await;
+        }
+    };
+
+    let subcommand = match command.data.options.first() {
+        Some(subcommand) => subcommand,
+        None => return Ok(()),
+    };
+    let options = match &subcommand.value {
+        CommandDataOptionValue::SubCommand(options) => options.as_slice(),
+        _ => &[],
+    };
+    let option = |name: &str| {
+        options
+            .iter()
+            .find(|option| option.name == name)
+            .map(|option| option.value.clone())
+    };
+
+    match subcommand.name.as_str() {
+        "add" => {
+            let phrase = option("phrase")
+                .and_then(|value| value.as_str().map(|value| value.trim().to_string()))
+                .unwrap_or_default();
+            let interval = option("interval")
+                .and_then(|value| value.as_i64())
+                .and_then(|value| u64::try_from(value).ok())
+                .unwrap_or_default();
+
+            if phrase.is_empty() {
+                return reply(ctx, command, "読み上げる文を指定してください").await;
+            }
+            if phrase.chars().count() > MAX_SCHEDULE_PHRASE_LENGTH {
+                return reply(
+                    ctx,
+                    command,
+                    &format!("{}文字以内で指定してください", MAX_SCHEDULE_PHRASE_LENGTH),
+                )
+                .await;
+            }
+            if !(MIN_SCHEDULE_INTERVAL_MINUTES..=MAX_SCHEDULE_INTERVAL_MINUTES).contains(&interval)
+            {
+                return reply(
+                    ctx,
+                    command,
+                    &format!(
+                        "間隔は{}分から{}分の間で指定してください",
+                        MIN_SCHEDULE_INTERVAL_MINUTES, MAX_SCHEDULE_INTERVAL_MINUTES
+                    ),
+                )
+                .await;
+            }
+
+            let added = update_schedules(ctx, guild_id, |config| {
+                if config.schedules.len() >= MAX_SCHEDULES {
+                    return None;
+                }
+                let id = next_schedule_id(&config.schedules, config.last_schedule_id);
+                config.last_schedule_id = Some(id);
+                config.schedules.push(Schedule {
+                    id,
+                    phrase: phrase.clone(),
+                    interval_minutes: interval,
+                });
+                Some(id)
+            })
+            .await?;
+
+            match added {
+                Some(id) => {
+                    reply(
+                        ctx,
+                        command,
+                        &format!("{}分ごとの読み上げを追加しました (ID: {})", interval, id),
+                    )
+                    .await
+                }
+                None => {
+                    reply(
+                        ctx,
+                        command,
+                        &format!("登録できるのは{}件までです", MAX_SCHEDULES),
+                    )
+                    .await
+                }
+            }
+        }
+        "list" => {
+            let schedules = get_schedules(ctx, guild_id).await?;
+            let content = if schedules.is_empty() {
+                String::from("登録されている読み上げはありません")
+            } else {
+                schedules
+                    .iter()
+                    .map(|s| format!("{}: {}分ごと「{}」", s.id, s.interval_minutes, s.phrase))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            };
+            reply(ctx, command, &content).await
+        }
+        "remove" => {
+            let id = option("id")
+                .and_then(|value| value.as_i64())
+                .and_then(|value| u64::try_from(value).ok())
+                .unwrap_or_default();
+            let removed = update_schedules(ctx, guild_id, |config| {
+                let len = config.schedules.len();
+                config.schedules.retain(|s| u64::from(s.id) != id);
+                config.schedules.len() != len
+            })
+            .await?;
+            reply(
+                ctx,
+                command,
+                &if removed {
+                    format!("ID {} の読み上げを削除しました", id)
+                } else {
+                    format!("ID {} の読み上げはありません", id)
+                },
+            )
+            .await
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Get the server's schedules without saving anything.
+async fn get_schedules(
+    ctx: &Context,
+    guild_id: GuildId,
+) -> Result<Vec<Schedule>, Box<dyn std::error::Error>> {
+    let data_read = ctx.data.read().await;
+    let database = data_read
+        .get::<DatabaseClientData>()
+        .expect("Cannot get DatabaseClientData")
+        .clone();
+    let mut database = database.lock().await;
+    let config = database
+        .get_server_config_or_default(guild_id.get())
+        .await?
+        .unwrap();
+    Ok(config.schedules)
+}
+
+/// Run `f` on the server config and save it if it changed.
+async fn update_schedules<T>(
+    ctx: &Context,
+    guild_id: GuildId,
+    f: impl FnOnce(&mut ServerConfig) -> T,
+) -> Result<T, Box<dyn std::error::Error>> {
+    let data_read = ctx.data.read().await;
+    let database = data_read
+        .get::<DatabaseClientData>()
+        .expect("Cannot get DatabaseClientData")
+        .clone();
+    let mut database = database.lock().await;
+    let mut config = database
+        .get_server_config_or_default(guild_id.get())
+        .await?
+        .unwrap();
+
+    let before = config.clone();
+    let result = f(&mut config);
+    if config != before {
+        database.set_server_config(guild_id.get(), config).await?;
+    }
+
+    Ok(result)
+}
+
+async fn reply(
+    ctx: &Context,
+    command: &CommandInteraction,
+    content: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    command
+        .create_response(
+            &ctx.http,
+            CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::new()
+                    .content(content)
+                    .ephemeral(true),
+            ),
+        )
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,407 @@
+use std::collections::HashSet;
+
+use serenity::{
+    builder::{CreateAttachment, CreateInteractionResponse, CreateInteractionResponseMessage},
+    model::prelude::{CommandInteraction, GuildId, ResolvedValue},
+    prelude::Context,
+};
+
+use crate::{
+    data::{ConfigData, DatabaseClientData},
+    database::{
+        dictionary::{build_rule_regex, Dictionary},
+        schedule::{
+            MAX_SCHEDULES, MAX_SCHEDULE_INTERVAL_MINUTES, MAX_SCHEDULE_PHRASE_LENGTH,
+            MIN_SCHEDULE_INTERVAL_MINUTES,
+        },
+        server_config::{ServerConfig, SERVER_CONFIG_NUMBERS},
+    },
+};
+
+/// Largest snapshot accepted by `/serverconfig import`, in bytes.
+const MAX_SNAPSHOT_SIZE: u32 = 1024 * 1024;
+
+/// Check a snapshot against the guild before importing it.
+/// Returns a description of each problem found.
+pub fn validate_snapshot(
+    config: &ServerConfig,
+    channels: &HashSet<u64>,
+    roles: &HashSet<u64>,
+    blacklist: &[String],
+) -> Vec<String> {
+    let mut problems = vec![];
+
+    for rule in &config.dictionary.rules {
+        if rule.is_regex {
+            if let Err(err) = build_rule_regex(&rule.rule) {
+                problems.push(format!("辞書 {}: {}", rule.id, err));
+            }
+        }
+        if let Some(entry) = Dictionary::find_blacklisted(&rule.rule, blacklist) {
+            problems.push(format!("辞書 {}: 禁止パターン {}", rule.id, entry));
+        }
+    }
+
+    let configured_channels = config
+        .autostart_channel_id
+        .iter()
+        .chain(config.autostart_text_channel_id.iter())
+        .chain(config.ignored_text_channels.iter());
+    for channel in configured_channels {
+        if !channels.contains(channel) {
+            problems.push(format!("チャンネル {} はこのサーバーにありません", channel));
+        }
+    }
+
+    for role in &config.ssml_allowed_roles {
+        if !roles.contains(role) {
+            problems.push(format!("ロール {} はこのサーバーにありません", role));
+        }
+    }
+
+    for (key, label, min, max) in SERVER_CONFIG_NUMBERS {
+        if config
+            .number(key)
+            .is_some_and(|value| value < *min || value > *max)
+        {
+            problems.push(format!(
+                "{}: {}から{}の間で指定してください",
+                label, min, max
+            ));
+        }
+    }
+
+    if config.schedules.len() > MAX_SCHEDULES {
+        problems.push(format!("定期読み上げは{}件までです", MAX_SCHEDULES));
+    }
+    let mut schedule_ids = HashSet::new();
+    for schedule in &config.schedules {
+        if !schedule_ids.insert(schedule.id) {
+            problems.push(format!("定期読み上げ {}: IDが重複しています", schedule.id));
+        }
+        if !(MIN_SCHEDULE_INTERVAL_MINUTES..=MAX_SCHEDULE_INTERVAL_MINUTES)
+            .contains(&schedule.interval_minutes)
+        {
+            problems.push(format!(
+                "定期読み上げ {}: 間隔は{}分から{}分の間で指定してください",
+                schedule.id, MIN_SCHEDULE_INTERVAL_MINUTES, MAX_SCHEDULE_INTERVAL_MINUTES
+            ));
+        }
+        let phrase_length = schedule.phrase.trim().chars().count();
+        if phrase_length == 0 || phrase_length > MAX_SCHEDULE_PHRASE_LENGTH {
+            problems.push(format!(
+                "定期読み上げ {}: 読み上げる文は1文字以上{}文字以内で指定してください",
+                schedule.id, MAX_SCHEDULE_PHRASE_LENGTH
+            ));
+        }
+    }
+
+    problems
+}
+
+pub async fn serverconfig_command(
+    ctx: &Context,
+    command: &CommandInteraction,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let guild_id = match command.guild_id {
+        Some(guild_id) => guild_id,
+        None => {
+            command
+                .create_response(
+                    &ctx.http,
+                    CreateInteractionResponse::Message(
+                        CreateInteractionResponseMessage::new()
+                            .content("このコマンドはサーバーでのみ使用可能です．")
+                            .ephemeral(true),
+                    ),
+                )
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let options = command.data.options();
+    let subcommand = match options.first() {
+        Some(subcommand) => subcommand,
+        None => return Ok(()),
+    };
+
+    match subcommand.name {
+        "export" => export(ctx, command, guild_id).await,
+        "import" => {
+            let attachment = match &subcommand.value {
+                ResolvedValue::SubCommand(options) => options.first().map(|option| &option.value),
+                _ => None,
+            };
+            match attachment {
+                Some(ResolvedValue::Attachment(attachment)) => {
+                    if attachment.size > MAX_SNAPSHOT_SIZE {
+                        reply(ctx, command, "ファイルが大きすぎます").await
+                    } else {
+                        let data = attachment.download().await?;
+                        import(ctx, command, guild_id, &data).await
+                    }
+                }
+                _ => reply(ctx, command, "ファイルを指定してください").await,
+            }
+        }
+        "undo" => {
+            let restored = {
+                let data_read = ctx.data.read().await;
+                let database = data_read
+                    .get::<DatabaseClientData>()
+                    .expect("Cannot get DatabaseClientData")
+                    .clone();
+                let mut database = database.lock().await;
+                database.undo_server_config(guild_id.get()).await?
+            };
+            reply(
+                ctx,
+                command,
+                if restored {
+                    "サーバー設定を直前の状態に戻しました"
+                } else {
+                    "戻せる設定がありません"
+                },
+            )
+            .await
+        }
+        _ => Ok(()),
+    }
+}
+
+async fn export(
+    ctx: &Context,
+    command: &CommandInteraction,
+    guild_id: GuildId,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let config = {
+        let data_read = ctx.data.read().await;
+        let database = data_read
+            .get::<DatabaseClientData>()
+            .expect("Cannot get DatabaseClientData")
+            .clone();
+        let mut database = database.lock().await;
+        database
+            .get_server_config_or_default(guild_id.get())
+            .await
+            .unwrap()
+            .unwrap()
+    };
+    let json = serde_json::to_vec_pretty(&config)?;
+
+    command
+        .create_response(
+            &ctx.http,
+            CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::new()
+                    .content("現在のサーバー設定")
+                    .add_file(CreateAttachment::bytes(
+                        json,
+                        format!("server_config_{}.json", guild_id.get()),
+                    ))
+                    .ephemeral(true),
+            ),
+        )
+        .await?;
+
+    Ok(())
+}
+
+async fn import(
+    ctx: &Context,
+    command: &CommandInteraction,
+    guild_id: GuildId,
+    data: &[u8],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut config: ServerConfig = match serde_json::from_slice(data) {
+        Ok(config) => config,
+        Err(err) => {
+            return reply(
+                ctx,
+                command,
+                &format!("設定ファイルを読み込めません\n{}", err),
+            )
+            .await;
+        }
+    };
+
+    let channels = guild_id
+        .channels(&ctx.http)
+        .await?
+        .keys()
+        .map(|id| id.get())
+        .collect::<HashSet<_>>();
+    let roles = guild_id
+        .roles(&ctx.http)
+        .await?
+        .keys()
+        .map(|id| id.get())
+        .collect::<HashSet<_>>();
+    let blacklist = {
+        let data_read = ctx.data.read().await;
+        data_read
+            .get::<ConfigData>()
+            .expect("Cannot get ConfigData")
+            .dictionary_blacklist
+            .clone()
+            .unwrap_or_default()
+    };
+
+    let problems = validate_snapshot(&config, &channels, &roles, &blacklist);
+    if !problems.is_empty() {
+        return reply(
+            ctx,
+            command,
+            &format!("設定を読み込めませんでした\n{}", problems.join("\n")),
+        )
+        .await;
+    }
+
+    {
+        let data_read = ctx.data.read().await;
+        let database = data_read
+            .get::<DatabaseClientData>()
+            .expect("Cannot get DatabaseClientData")
+            .clone();
+        let mut database = database.lock().await;
+        // Keep handing out new schedule ids after the import.
+        let current = database
+            .get_server_config_or_default(guild_id.get())
+            .await?
+            .unwrap();
+        config.last_schedule_id = current
+            .schedules
+            .iter()
+            .map(|schedule| schedule.id)
+            .chain(current.last_schedule_id)
+            .chain(config.last_schedule_id)
+            .max();
+        database.set_server_config(guild_id.get(), config).await?;
+    }
+
+    reply(
+        ctx,
+        command,
+        "サーバー設定を読み込みました\n`/serverconfig undo` で元に戻せます",
+    )
+    .await
+}
+
+async fn reply(
+    ctx: &Context,
+    command: &CommandInteraction,
+    content: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    command
+        .create_response(
+            &ctx.http,
+            CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::new()
+                    .content(content)
+                    .ephemeral(true),
+            ),
+        )
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{dictionary::Rule, schedule::Schedule};
+
+    fn schedule(id: u32, phrase: &str, interval_minutes: u64) -> Schedule {
+        Schedule {
+            id,
+            phrase: phrase.to_string(),
+            interval_minutes,
+        }
+    }
+
+    fn validate(config: &ServerConfig) -> Vec<String> {
+        validate_snapshot(config, &HashSet::new(), &HashSet::new(), &[])
+    }
+
+    #[test]
+    fn default_config_is_valid() {
+        assert!(validate(&ServerConfig::default()).is_empty());
+    }
+
+    #[test]
+    fn schedules_are_validated() {
+        let mut config = ServerConfig {
+            schedules: vec![schedule(1, "休憩", 30)],
+            ..Default::default()
+        };
+        assert!(validate(&config).is_empty());
+
+        config.schedules = vec![
+            schedule(1, "休憩", MIN_SCHEDULE_INTERVAL_MINUTES - 1),
+            schedule(2, "休憩", MAX_SCHEDULE_INTERVAL_MINUTES + 1),
+            schedule(3, " ", 30),
+            schedule(4, &"あ".repeat(MAX_SCHEDULE_PHRASE_LENGTH + 1), 30),
+            schedule(4, "休憩", 30),
+        ];
+        assert_eq!(validate(&config).len(), 5);
+
+        config.schedules = (1..=MAX_SCHEDULES as u32 + 1)
+            .map(|id| schedule(id, "休憩", 30))
+            .collect();
+        assert_eq!(validate(&config).len(), 1);
+    }
+
+    #[test]
+    fn missing_channels_and_roles_are_reported() {
+        let config = ServerConfig {
+            ignored_text_channels: vec![1],
+            ssml_allowed_roles: vec![2],
+            ..Default::default()
+        };
+        assert_eq!(validate(&config).len(), 2);
+        assert!(
+            validate_snapshot(&config, &HashSet::from([1]), &HashSet::from([2]), &[]).is_empty()
+        );
+    }
+
+    #[test]
+    fn numbers_out_of_range_are_reported() {
+        let mut config = ServerConfig {
+            chunk_length: Some(5),
+            fade_ms: Some(u64::MAX),
+            ..Default::default()
+        };
+        assert_eq!(validate(&config).len(), 2);
+
+        config.chunk_length = Some(200);
+        config.fade_ms = None;
+        assert!(validate(&config).is_empty());
+    }
+
+    #[test]
+    fn snapshot_round_trips() {
+        let mut config = ServerConfig {
+            autostart_channel_id: Some(1),
+            ignored_text_channels: vec![2],
+            ignore_prefixes: vec![String::from("!")],
+            schedules: vec![schedule(1, "休憩", 30)],
+            last_schedule_id: Some(1),
+            read_spoilers: Some(false),
+            ..Default::default()
+        };
+        config.set_number("chunk_length", Some(200));
+        config.dictionary.rules.push(Rule {
+            id: String::from("w"),
+            is_regex: false,
+            rule: String::from("w"),
+            to: String::from("わら"),
+        });
+
+        // The same calls as `/serverconfig export` and `import`.
+        let snapshot = serde_json::to_vec_pretty(&config).unwrap();
+        assert_eq!(
+            serde_json::from_slice::<ServerConfig>(&snapshot).unwrap(),
+            config
+        );
+    }
+}
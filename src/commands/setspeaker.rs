@@ -0,0 +1,133 @@
+use serenity::{
+    builder::{CreateInteractionResponse, CreateInteractionResponseMessage},
+    model::prelude::CommandInteraction,
+    prelude::Context,
+};
+
+use crate::{
+    data::{DatabaseClientData, TTSClientData},
+    tts::voicevox::voicevox::find_style,
+};
+
+/// Number of valid ids listed when the id is unknown.
+const EXAMPLE_SPEAKERS: usize = 5;
+
+/// Reply for an id that is not in the style list, with a few valid ids.
+fn unknown_speaker_message(id: i64, styles: &[(String, i64)]) -> String {
+    let examples = styles
+        .iter()
+        .take(EXAMPLE_SPEAKERS)
+        .map(|(name, id)| format!("{}: {}", id, name))
+        .collect::<Vec<_>>()
+        .join("\n");
+    format!(
+        "ID {} のSpeakerは存在しません\n指定できるIDの例:\n{}",
+        id, examples
+    )
+}
+
+pub async fn setspeaker_command(
+    ctx: &Context,
+    command: &CommandInteraction,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let id = command
+        .data
+        .options
+        .first()
+        .map(|option| option.value.clone())
+        .and_then(|value| value.as_i64())
+        .unwrap_or_default();
+
+    let styles = {
+        let data_read = ctx.data.read().await;
+        let tts_client = data_read
+            .get::<TTSClientData>()
+            .expect("Cannot get TTSClientData")
+            .clone();
+        let voicevox = tts_client.lock().await.1.clone();
+        voicevox.get_styles().await
+    };
+
+    let name = match find_style(&styles, id) {
+        Some(name) => name.clone(),
+        None => {
+            command
+                .create_response(
+                    &ctx.http,
+                    CreateInteractionResponse::Message(
+                        CreateInteractionResponseMessage::new()
+                            .content(unknown_speaker_message(id, &styles))
+                            .ephemeral(true),
+                    ),
+                )
+                .await?;
+            return Ok(());
+        }
+    };
+
+    {
+        let data_read = ctx.data.read().await;
+        let database = data_read
+            .get::<DatabaseClientData>()
+            .expect("Cannot get DatabaseClientData")
+            .clone();
+        let mut database = database.lock().await;
+        let mut config = database
+            .get_user_config_or_guild_default(
+                command.user.id.get(),
+                command.guild_id.map(|guild_id| guild_id.get()),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        config.voicevox_speaker = Some(id);
+        database
+            .set_user_config(command.user.id.get(), config)
+            .await
+            .unwrap();
+    }
+
+    command
+        .create_response(
+            &ctx.http,
+            CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::new()
+                    .content(format!("VOICEVOX Speakerを {} に設定しました", name))
+                    .ephemeral(true),
+            ),
+        )
+        .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn styles() -> Vec<(String, i64)> {
+        (0..8)
+            .map(|id| (format!("話者{} - ノーマル", id), id * 2))
+            .collect()
+    }
+
+    #[test]
+    fn only_listed_ids_are_valid() {
+        let styles = styles();
+        assert_eq!(
+            find_style(&styles, 4),
+            Some(&"話者2 - ノーマル".to_string())
+        );
+        assert_eq!(find_style(&styles, 3), None);
+        assert_eq!(find_style(&styles, 100), None);
+    }
+
+    #[test]
+    fn unknown_id_lists_a_few_valid_ids() {
+        let message = unknown_speaker_message(3, &styles());
+        assert!(message.starts_with("ID 3 のSpeakerは存在しません"));
+        assert!(message.contains("0: 話者0 - ノーマル"));
+        assert!(message.contains("8: 話者4 - ノーマル"));
+        assert!(!message.contains("話者5"));
+    }
+}
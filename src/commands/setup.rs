@@ -1,71 +1,69 @@
+use std::sync::Arc;
+
 use serenity::{
-    model::prelude::{
-        interaction::{application_command::ApplicationCommandInteraction, MessageFlags},
-        UserId,
+    builder::{
+        CreateEmbed, CreateInteractionResponse, CreateInteractionResponseMessage, CreateMessage,
+        CreateThread,
     },
+    futures::lock::Mutex,
+    model::prelude::{AutoArchiveDuration, CommandInteraction},
     prelude::Context,
 };
 
 use crate::{
-    data::{TTSClientData, TTSData},
-    tts::instance::TTSInstance,
+    commands::utils::{resolve_voice_channel, where_to_type_hint},
+    data::{ConfigData, TTSClientData, TTSData},
+    tts::{
+        instance::{at_capacity, TTSInstance},
+        voicevox::voicevox::speaker_credits,
+    },
 };
 
 pub async fn setup_command(
     ctx: &Context,
-    command: &ApplicationCommandInteraction,
+    command: &CommandInteraction,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    println!("Received event");
-    if let None = command.guild_id {
-        command
-            .create_interaction_response(&ctx.http, |f| {
-                f.interaction_response_data(|d| {
-                    d.content("このコマンドはサーバーでのみ使用可能です．")
-                        .flags(MessageFlags::EPHEMERAL)
-                })
-            })
-            .await?;
-        return Ok(());
-    }
+    let mode = command
+        .data
+        .options
+        .first()
+        .map(|mode| mode.value.clone())
+        .and_then(|value| value.as_str().map(String::from));
 
-    println!("Fetching guild cache");
-    let guild = command.guild_id.unwrap().to_guild_cached(&ctx.cache);
-    if let None = guild {
-        command
-            .create_interaction_response(&ctx.http, |f| {
-                f.interaction_response_data(|d| {
-                    d.content("ギルドキャッシュを取得できませんでした．")
-                        .flags(MessageFlags::EPHEMERAL)
-                })
-            })
-            .await?;
-        return Ok(());
-    }
-    let guild = guild.unwrap();
-
-    let channel_id = guild
-        .voice_states
-        .get(&UserId(command.user.id.0))
-        .and_then(|state| state.channel_id);
-
-    if let None = channel_id {
-        command
-            .create_interaction_response(&ctx.http, |f| {
-                f.interaction_response_data(|d| {
-                    d.content("ボイスチャンネルに参加してから実行してください．")
-                        .flags(MessageFlags::EPHEMERAL)
-                })
-            })
-            .await?;
-        return Ok(());
-    }
+    start_tts(ctx, command, mode.as_deref().unwrap_or("VOICE_CHANNEL")).await
+}
 
-    let channel_id = channel_id.unwrap();
+/// Join the caller's voice channel and register a TTSInstance.
+/// `mode` selects the text channel: TEXT_CHANNEL, NEW_THREAD or VOICE_CHANNEL.
+pub async fn start_tts(
+    ctx: &Context,
+    command: &CommandInteraction,
+    mode: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    println!("Received event");
+    let (guild_id, channel_id) = match resolve_voice_channel(ctx, command).await? {
+        Some(resolved) => resolved,
+        None => return Ok(()),
+    };
 
-    let manager = songbird::get(ctx)
-        .await
-        .expect("Cannot get songbird client.")
-        .clone();
+    let manager = match songbird::get(ctx).await {
+        Some(manager) => manager,
+        None => {
+            command
+                .create_response(
+                    &ctx.http,
+                    CreateInteractionResponse::Message(
+                        CreateInteractionResponseMessage::new()
+                            .content(
+                                "音声接続を利用できません．しばらくしてから再試行してください．",
+                            )
+                            .ephemeral(true),
+                    ),
+                )
+                .await?;
+            return Ok(());
+        }
+    };
 
     let storage_lock = {
         let data_read = ctx.data.read().await;
@@ -75,71 +73,91 @@ pub async fn setup_command(
             .clone()
     };
 
+    let max_active_instances = ctx
+        .data
+        .read()
+        .await
+        .get::<ConfigData>()
+        .expect("Cannot get ConfigData")
+        .max_active_instances;
+
     let text_channel_id = {
         let mut storage = storage_lock.write().await;
-        if storage.contains_key(&guild.id) {
+        if storage.contains_key(&guild_id) {
+            command
+                .create_response(
+                    &ctx.http,
+                    CreateInteractionResponse::Message(
+                        CreateInteractionResponseMessage::new()
+                            .content("すでにセットアップしています．")
+                            .ephemeral(true),
+                    ),
+                )
+                .await?;
+            return Ok(());
+        }
+
+        if at_capacity(storage.len(), max_active_instances) {
             command
-                .create_interaction_response(&ctx.http, |f| {
-                    f.interaction_response_data(|d| {
-                        d.content("すでにセットアップしています．")
-                            .flags(MessageFlags::EPHEMERAL)
-                    })
-                })
+                .create_response(
+                    &ctx.http,
+                    CreateInteractionResponse::Message(
+                        CreateInteractionResponseMessage::new()
+                            .content(
+                                "読み上げ中のサーバー数が上限に達しています．しばらくしてから再試行してください．",
+                            )
+                            .ephemeral(true),
+                    ),
+                )
                 .await?;
             return Ok(());
         }
 
-        let text_channel_id = {
-            if let Some(mode) = command.data.options.get(0) {
-                let mode = mode.clone();
-                let value = mode.value.unwrap();
-                let value = value.as_str().unwrap();
-                match value {
-                    "TEXT_CHANNEL" => command.channel_id,
-                    "NEW_THREAD" => {
-                        let message = command
-                            .channel_id
-                            .send_message(&ctx.http, |f| f.content("TTS thread"))
-                            .await
-                            .unwrap();
-                        command
-                            .channel_id
-                            .create_public_thread(&ctx.http, message, |f| {
-                                f.name("TTS").auto_archive_duration(60)
-                            })
-                            .await
-                            .unwrap()
-                            .id
-                    }
-                    "VOICE_CHANNEL" => channel_id,
-                    _ => channel_id,
-                }
-            } else {
-                channel_id
+        let text_channel_id = match mode {
+            "TEXT_CHANNEL" => command.channel_id,
+            "NEW_THREAD" => {
+                let message = command
+                    .channel_id
+                    .send_message(&ctx.http, CreateMessage::new().content("TTS thread"))
+                    .await
+                    .unwrap();
+                command
+                    .channel_id
+                    .create_thread_from_message(
+                        &ctx.http,
+                        message.id,
+                        CreateThread::new("TTS")
+                            .auto_archive_duration(AutoArchiveDuration::OneHour),
+                    )
+                    .await
+                    .unwrap()
+                    .id
             }
+            _ => channel_id,
         };
 
         storage.insert(
-            guild.id,
-            TTSInstance {
-                before_message: None,
-                guild: guild.id,
-                text_channel: text_channel_id,
-                voice_channel: channel_id,
-            },
+            guild_id,
+            Arc::new(Mutex::new(TTSInstance::new(
+                guild_id,
+                text_channel_id,
+                channel_id,
+            ))),
         );
 
         text_channel_id
     };
 
     command
-        .create_interaction_response(&ctx.http, |f| {
-            f.interaction_response_data(|d| {
-                d.content(format!("TTS Channel: <#{}>{}", text_channel_id, if text_channel_id == channel_id { "\nボイスチャンネルを右クリックし `チャットを開く` を押して開くことが出来ます。" } else { "" }))
-            })
-        })
+        .create_response(
+            &ctx.http,
+            CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::new()
+                    .content(where_to_type_hint(text_channel_id, channel_id)),
+            ),
+        )
         .await?;
-    let _handler = manager.join(guild.id.0, channel_id.0).await;
+    let _handler = manager.join(guild_id, channel_id).await;
 
     let tts_client = ctx
         .data
@@ -148,22 +166,37 @@ pub async fn setup_command(
         .get::<TTSClientData>()
         .expect("Cannot get TTSClientData")
         .clone();
-    let voicevox_speakers = tts_client.lock().await.1.get_speakers().await;
+    let voicevox = tts_client.lock().await.1.clone();
+    let voicevox_speakers = voicevox.get_speakers().await;
+    if let Err(err) = &voicevox_speakers {
+        println!("Cannot get speaker list. {err:?}");
+    }
+
+    let feedback_url = ctx
+        .data
+        .read()
+        .await
+        .get::<ConfigData>()
+        .expect("Cannot get ConfigData")
+        .feedback_url()
+        .to_string();
 
     text_channel_id
-        .send_message(&ctx.http, |f| {
-            f.embed(|e| {
-                e.title("読み上げ (Serenity)")
-                    .field(
-                        "VOICEVOXクレジット",
-                        format!("```\n{}\n```", voicevox_speakers.join("\n")),
-                        false,
-                    )
-                    .field("設定コマンド", "`/config`", false)
-                    .field("フィードバック", "https://feedback.mii.codes/", false)
-            })
+        .send_message(&ctx.http, {
+            let mut embed = CreateEmbed::new().title("読み上げ (Serenity)");
+            if let Some(credits) = speaker_credits(&voicevox_speakers) {
+                embed = embed.field("VOICEVOXクレジット", credits, false);
+            }
+            embed = embed.field("設定コマンド", "`/config`", false).field(
+                "フィードバック",
+                feedback_url,
+                false,
+            );
+            CreateMessage::new().embed(embed)
         })
         .await?;
 
+    TTSInstance::check_speak_permission(ctx, channel_id, text_channel_id).await;
+
     Ok(())
 }
@@ -0,0 +1,127 @@
+use serenity::{
+    builder::{
+        CreateInteractionResponse, CreateInteractionResponseMessage, EditInteractionResponse,
+    },
+    model::prelude::CommandInteraction,
+    prelude::Context,
+};
+
+use crate::{
+    data::{DatabaseClientData, TTSClientData},
+    tts::gcp_tts::structs::voice::resolve_voice,
+};
+
+pub async fn setvoice_command(
+    ctx: &Context,
+    command: &CommandInteraction,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let name = command
+        .data
+        .options
+        .first()
+        .map(|option| option.value.clone())
+        .and_then(|value| value.as_str().map(|value| value.to_string()))
+        .unwrap_or_default();
+
+    if name.trim().is_empty() {
+        command
+            .create_response(
+                &ctx.http,
+                CreateInteractionResponse::Message(
+                    CreateInteractionResponseMessage::new()
+                        .content("音声名を指定してください")
+                        .ephemeral(true),
+                ),
+            )
+            .await?;
+        return Ok(());
+    }
+
+    // The voice list may have to be fetched first.
+    command
+        .create_response(
+            &ctx.http,
+            CreateInteractionResponse::Defer(
+                CreateInteractionResponseMessage::new().ephemeral(true),
+            ),
+        )
+        .await?;
+
+    let voices = {
+        let data_read = ctx.data.read().await;
+        let tts_client = data_read
+            .get::<TTSClientData>()
+            .expect("Cannot get TTSClientData")
+            .clone();
+        let voices = tts_client.lock().await.0.get_voices().await;
+        // Drop the error here; it is not Send and must not be held across an await.
+        voices
+            .map_err(|err| println!("Cannot get GCP voices: {:?}", err))
+            .ok()
+    };
+    let voices = match voices {
+        Some(voices) => voices,
+        None => {
+            command
+                .edit_response(
+                    &ctx.http,
+                    EditInteractionResponse::new().content(
+                        "音声一覧を取得できませんでした．しばらくしてから再試行してください．",
+                    ),
+                )
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let voice = match resolve_voice(&voices, &name) {
+        Ok(voice) => voice,
+        Err(suggestions) => {
+            let content = if suggestions.is_empty() {
+                format!("音声 {} は存在しません", name)
+            } else {
+                format!(
+                    "音声 {} は存在しません\n候補:\n{}",
+                    name,
+                    suggestions.join("\n")
+                )
+            };
+            command
+                .edit_response(&ctx.http, EditInteractionResponse::new().content(content))
+                .await?;
+            return Ok(());
+        }
+    };
+
+    {
+        let data_read = ctx.data.read().await;
+        let database = data_read
+            .get::<DatabaseClientData>()
+            .expect("Cannot get DatabaseClientData")
+            .clone();
+        let mut database = database.lock().await;
+        let mut config = database
+            .get_user_config_or_guild_default(
+                command.user.id.get(),
+                command.guild_id.map(|guild_id| guild_id.get()),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        config.gcp_tts_voice = Some(voice.clone());
+        database
+            .set_user_config(command.user.id.get(), config)
+            .await
+            .unwrap();
+    }
+
+    command
+        .edit_response(
+            &ctx.http,
+            EditInteractionResponse::new()
+                .content(format!("Google TTSの音声を {} に設定しました", voice.name)),
+        )
+        .await?;
+
+    Ok(())
+}
@@ -1,59 +1,19 @@
 use serenity::{
-    model::prelude::{
-        interaction::{application_command::ApplicationCommandInteraction, MessageFlags},
-        UserId,
-    },
+    builder::{CreateInteractionResponse, CreateInteractionResponseMessage},
+    model::prelude::CommandInteraction,
     prelude::Context,
 };
 
-use crate::data::TTSData;
+use crate::{commands::utils::resolve_voice_channel, data::TTSData};
 
 pub async fn skip_command(
     ctx: &Context,
-    command: &ApplicationCommandInteraction,
+    command: &CommandInteraction,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    if let None = command.guild_id {
-        command
-            .create_interaction_response(&ctx.http, |f| {
-                f.interaction_response_data(|d| {
-                    d.content("このコマンドはサーバーでのみ使用可能です．")
-                        .flags(MessageFlags::EPHEMERAL)
-                })
-            })
-            .await?;
-        return Ok(());
-    }
-
-    let guild = command.guild_id.unwrap().to_guild_cached(&ctx.cache);
-    if let None = guild {
-        command
-            .create_interaction_response(&ctx.http, |f| {
-                f.interaction_response_data(|d| {
-                    d.content("ギルドキャッシュを取得できませんでした．")
-                        .flags(MessageFlags::EPHEMERAL)
-                })
-            })
-            .await?;
-        return Ok(());
-    }
-    let guild = guild.unwrap();
-
-    let channel_id = guild
-        .voice_states
-        .get(&UserId(command.user.id.0))
-        .and_then(|state| state.channel_id);
-
-    if let None = channel_id {
-        command
-            .create_interaction_response(&ctx.http, |f| {
-                f.interaction_response_data(|d| {
-                    d.content("ボイスチャンネルに参加してから実行してください．")
-                        .flags(MessageFlags::EPHEMERAL)
-                })
-            })
-            .await?;
-        return Ok(());
-    }
+    let (guild_id, _) = match resolve_voice_channel(ctx, command).await? {
+        Some(resolved) => resolved,
+        None => return Ok(()),
+    };
 
     let storage_lock = {
         let data_read = ctx.data.read().await;
@@ -64,26 +24,31 @@ pub async fn skip_command(
     };
 
     {
-        let mut storage = storage_lock.write().await;
-        if !storage.contains_key(&guild.id) {
+        let storage = storage_lock.read().await;
+        if !storage.contains_key(&guild_id) {
             command
-                .create_interaction_response(&ctx.http, |f| {
-                    f.interaction_response_data(|d| {
-                        d.content("読み上げしていません")
-                            .flags(MessageFlags::EPHEMERAL)
-                    })
-                })
+                .create_response(
+                    &ctx.http,
+                    CreateInteractionResponse::Message(
+                        CreateInteractionResponseMessage::new()
+                            .content("読み上げしていません")
+                            .ephemeral(true),
+                    ),
+                )
                 .await?;
             return Ok(());
         }
 
-        storage.get_mut(&guild.id).unwrap().skip(&ctx).await;
+        storage.get(&guild_id).unwrap().lock().await.skip(ctx).await;
     }
 
     command
-        .create_interaction_response(&ctx.http, |f| {
-            f.interaction_response_data(|d| d.content("スキップしました"))
-        })
+        .create_response(
+            &ctx.http,
+            CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::new().content("スキップしました"),
+            ),
+        )
         .await?;
 
     Ok(())
@@ -0,0 +1,63 @@
+use serenity::{
+    builder::{CreateInteractionResponse, CreateInteractionResponseMessage},
+    model::prelude::CommandInteraction,
+    prelude::Context,
+};
+
+use crate::{commands::utils::resolve_voice_channel, data::TTSData};
+
+pub async fn skipall_command(
+    ctx: &Context,
+    command: &CommandInteraction,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (guild_id, _) = match resolve_voice_channel(ctx, command).await? {
+        Some(resolved) => resolved,
+        None => return Ok(()),
+    };
+
+    let storage_lock = {
+        let data_read = ctx.data.read().await;
+        data_read
+            .get::<TTSData>()
+            .expect("Cannot get TTSStorage")
+            .clone()
+    };
+
+    let cleared = {
+        let storage = storage_lock.read().await;
+        if !storage.contains_key(&guild_id) {
+            command
+                .create_response(
+                    &ctx.http,
+                    CreateInteractionResponse::Message(
+                        CreateInteractionResponseMessage::new()
+                            .content("読み上げしていません")
+                            .ephemeral(true),
+                    ),
+                )
+                .await?;
+            return Ok(());
+        }
+
+        let cleared = storage
+            .get(&guild_id)
+            .unwrap()
+            .lock()
+            .await
+            .skip_all(ctx)
+            .await;
+        cleared
+    };
+
+    command
+        .create_response(
+            &ctx.http,
+            CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::new()
+                    .content(format!("{}件の読み上げをスキップしました", cleared)),
+            ),
+        )
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,251 @@
+use serenity::{
+    builder::{
+        CreateInteractionResponse, CreateInteractionResponseFollowup,
+        CreateInteractionResponseMessage,
+    },
+    model::prelude::{Channel, ChannelId, ChannelType, CommandInteraction, Guild, GuildId, UserId},
+    prelude::Context,
+};
+
+use crate::tts::instance::CallError;
+
+/// Short Japanese description of a command error for the user.
+pub fn error_summary(err: &(dyn std::error::Error + 'static)) -> String {
+    match err.downcast_ref::<CallError>() {
+        Some(CallError::ManagerNotFound) => String::from("音声接続を利用できません"),
+        Some(CallError::CallNotFound) => String::from("ボイスチャンネルに接続していません"),
+        None => err.to_string(),
+    }
+}
+
+/// Log a command error and build the message shown to the user.
+pub fn error_response(
+    command: &CommandInteraction,
+    err: &(dyn std::error::Error + 'static),
+) -> String {
+    println!("Command {} failed: {:?}", command.data.name, err);
+    format!("エラーが発生しました: {}", error_summary(err))
+}
+
+/// Tell the user about a command error ephemerally.
+/// Uses a followup message when the command already responded.
+pub async fn respond_error(ctx: &Context, command: &CommandInteraction, content: &str) {
+    let responded = command
+        .create_response(
+            &ctx.http,
+            CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::new()
+                    .content(content)
+                    .ephemeral(true),
+            ),
+        )
+        .await;
+    if responded.is_err() {
+        if let Err(err) = command
+            .create_followup(
+                &ctx.http,
+                CreateInteractionResponseFollowup::new()
+                    .content(content)
+                    .ephemeral(true),
+            )
+            .await
+        {
+            println!("Cannot send error response: {:?}", err);
+        }
+    }
+}
+
+/// Tell users which channel is read, with how to open it when it is the voice channel chat.
+pub fn where_to_type_hint(text_channel: ChannelId, voice_channel: ChannelId) -> String {
+    if text_channel == voice_channel {
+        format!(
+            "TTS Channel: <#{}>\nボイスチャンネルを右クリックし `チャットを開く` を押して開くことが出来ます。",
+            text_channel
+        )
+    } else {
+        format!("TTS Channel: <#{}>", text_channel)
+    }
+}
+
+/// Check whether the user owns this bot application.
+///
+/// Example:
+/// ```rust
+/// if !is_owner(ctx, command.user.id).await {
+///     return Ok(());
+/// }
+/// ```
+pub async fn is_owner(ctx: &Context, user_id: UserId) -> bool {
+    match ctx.http.get_current_application_info().await {
+        Ok(info) => info.owner.is_some_and(|owner| owner.id == user_id),
+        Err(err) => {
+            println!("Cannot get application info: {:?}", err);
+            false
+        }
+    }
+}
+
+/// Check whether the channel exists and messages can be read from it.
+pub async fn is_text_channel(ctx: &Context, channel_id: ChannelId) -> bool {
+    match channel_id.to_channel(ctx).await {
+        Ok(Channel::Guild(channel)) => matches!(
+            channel.kind,
+            ChannelType::Text | ChannelType::News | ChannelType::Voice
+        ),
+        _ => false,
+    }
+}
+
+/// Voice channel the user is connected to in the guild.
+pub fn user_voice_channel(guild: &Guild, user_id: UserId) -> Option<ChannelId> {
+    guild
+        .voice_states
+        .get(&user_id)
+        .and_then(|state| state.channel_id)
+}
+
+/// Guild and voice channel of the user, or the message telling why they cannot be resolved.
+/// `guild` is None when the guild is not cached.
+fn resolve_in_guild(
+    guild_id: Option<GuildId>,
+    guild: Option<&Guild>,
+    user_id: UserId,
+) -> Result<(GuildId, ChannelId), &'static str> {
+    match (guild_id, guild) {
+        (None, _) => Err("このコマンドはサーバーでのみ使用可能です．"),
+        (Some(_), None) => {
+            Err("サーバー情報を取得できませんでした．しばらくしてから再試行してください．")
+        }
+        (Some(guild_id), Some(guild)) => match user_voice_channel(guild, user_id) {
+            Some(channel_id) => Ok((guild_id, channel_id)),
+            None => Err("ボイスチャンネルに参加してから実行してください．"),
+        },
+    }
+}
+
+/// Resolve the guild and the voice channel of the user who ran the command.
+/// Replies with an ephemeral message and returns None when either cannot be resolved.
+///
+/// Example:
+/// ```rust
+/// let (guild_id, channel_id) = match resolve_voice_channel(ctx, command).await? {
+///     Some(resolved) => resolved,
+///     None => return Ok(()),
+/// };
+/// ```
+pub async fn resolve_voice_channel(
+    ctx: &Context,
+    command: &CommandInteraction,
+) -> serenity::Result<Option<(GuildId, ChannelId)>> {
+    let resolved = {
+        let guild = command
+            .guild_id
+            .and_then(|guild_id| guild_id.to_guild_cached(&ctx.cache));
+        resolve_in_guild(command.guild_id, guild.as_deref(), command.user.id)
+    };
+    let message = match resolved {
+        Ok(resolved) => return Ok(Some(resolved)),
+        Err(message) => message,
+    };
+
+    command
+        .create_response(
+            &ctx.http,
+            CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::new()
+                    .content(message)
+                    .ephemeral(true),
+            ),
+        )
+        .await?;
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn guild_with_voice_state(user_id: UserId, channel_id: ChannelId) -> Guild {
+        let state = serde_json::from_value(serde_json::json!({
+            "channel_id": channel_id.to_string(),
+            "deaf": false,
+            "mute": false,
+            "self_deaf": false,
+            "self_mute": false,
+            "self_video": false,
+            "session_id": "session",
+            "suppress": false,
+            "user_id": user_id.to_string(),
+            "request_to_speak_timestamp": null,
+        }))
+        .unwrap();
+        let mut guild = Guild::default();
+        guild.voice_states.insert(user_id, state);
+        guild
+    }
+
+    #[test]
+    fn call_errors_have_japanese_summaries() {
+        assert_eq!(
+            error_summary(&CallError::ManagerNotFound),
+            "音声接続を利用できません"
+        );
+        assert_eq!(
+            error_summary(&CallError::CallNotFound),
+            "ボイスチャンネルに接続していません"
+        );
+    }
+
+    #[test]
+    fn other_errors_are_summarized_by_their_message() {
+        let err: Box<dyn std::error::Error> = "Missing Access".into();
+        assert_eq!(error_summary(err.as_ref()), "Missing Access");
+    }
+
+    #[test]
+    fn hint_explains_how_to_open_the_voice_channel_chat() {
+        let voice = ChannelId::new(1);
+        assert_eq!(
+            where_to_type_hint(voice, voice),
+            "TTS Channel: <#1>\nボイスチャンネルを右クリックし `チャットを開く` を押して開くことが出来ます。"
+        );
+        assert_eq!(
+            where_to_type_hint(ChannelId::new(2), voice),
+            "TTS Channel: <#2>"
+        );
+    }
+
+    #[test]
+    fn unresolved_guild_or_channel_is_reported() {
+        let user = UserId::new(1);
+        let guild_id = GuildId::new(2);
+        assert_eq!(
+            resolve_in_guild(None, None, user),
+            Err("このコマンドはサーバーでのみ使用可能です．")
+        );
+        assert_eq!(
+            resolve_in_guild(Some(guild_id), None, user),
+            Err("サーバー情報を取得できませんでした．しばらくしてから再試行してください．")
+        );
+        assert_eq!(
+            resolve_in_guild(Some(guild_id), Some(&Guild::default()), user),
+            Err("ボイスチャンネルに参加してから実行してください．")
+        );
+    }
+
+    #[test]
+    fn voice_channel_of_the_user_is_resolved() {
+        let user = UserId::new(1);
+        let guild_id = GuildId::new(2);
+        let channel = ChannelId::new(3);
+        let guild = guild_with_voice_state(user, channel);
+        assert_eq!(
+            resolve_in_guild(Some(guild_id), Some(&guild), user),
+            Ok((guild_id, channel))
+        );
+        assert_eq!(
+            resolve_in_guild(Some(guild_id), Some(&guild), UserId::new(4)),
+            Err("ボイスチャンネルに参加してから実行してください．")
+        );
+    }
+}
@@ -1,10 +1,169 @@
 use serde::Deserialize;
 
-#[derive(Deserialize)]
+use crate::database::server_config::ServerConfig;
+
+/// Feedback URL used when the config does not set one.
+pub const DEFAULT_FEEDBACK_URL: &str = "https://feedback.mii.codes/";
+
+#[derive(Clone, Deserialize)]
 pub struct Config {
-    pub prefix: String,
     pub token: String,
     pub application_id: u64,
     pub redis_url: String,
     pub voicevox_key: String,
+    pub dictionary_blacklist: Option<Vec<String>>,
+    pub default_server_config: Option<ServerConfig>,
+    pub daily_char_limit: Option<u64>,
+    pub redis_key_prefix: Option<String>,
+    pub azure_region: Option<String>,
+    pub azure_key: Option<String>,
+    pub feedback_url: Option<String>,
+    pub max_active_instances: Option<usize>,
+}
+
+impl Config {
+    /// Feedback URL shown by /feedback and the join embeds.
+    pub fn feedback_url(&self) -> &str {
+        self.feedback_url.as_deref().unwrap_or(DEFAULT_FEEDBACK_URL)
+    }
+
+    /// Config a server gets when it is first used or reset from the server menu.
+    /// Settings missing from `default_server_config` keep their defaults, including the
+    /// default dictionary.
+    pub fn server_config_default(&self) -> ServerConfig {
+        self.default_server_config.clone().unwrap_or_default()
+    }
+
+    /// Check values that can be validated without network access.
+    /// Returns a description of each problem found.
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = vec![];
+        if self.token.is_empty() {
+            problems.push(String::from("token is empty"));
+        }
+        if self.application_id == 0 {
+            problems.push(String::from("application_id is not set"));
+        }
+        if self.voicevox_key.is_empty() {
+            problems.push(String::from("voicevox_key is empty"));
+        }
+        if let Err(err) = redis::Client::open(self.redis_url.clone()) {
+            problems.push(format!("redis_url is invalid: {}", err));
+        }
+        problems
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::dictionary::Dictionary;
+
+    fn valid_config() -> Config {
+        Config {
+            token: String::from("token"),
+            application_id: 1,
+            redis_url: String::from("redis://127.0.0.1/"),
+            voicevox_key: String::from("key"),
+            dictionary_blacklist: None,
+            default_server_config: None,
+            daily_char_limit: None,
+            redis_key_prefix: None,
+            azure_region: None,
+            azure_key: None,
+            feedback_url: None,
+            max_active_instances: None,
+        }
+    }
+
+    #[test]
+    fn valid_config_has_no_problems() {
+        assert!(valid_config().validate().is_empty());
+    }
+
+    #[test]
+    fn reports_each_missing_value() {
+        let config = Config {
+            token: String::new(),
+            application_id: 0,
+            voicevox_key: String::new(),
+            ..valid_config()
+        };
+        assert_eq!(
+            config.validate(),
+            vec![
+                "token is empty",
+                "application_id is not set",
+                "voicevox_key is empty"
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_an_invalid_redis_url() {
+        let config = Config {
+            redis_url: String::from("not a url"),
+            ..valid_config()
+        };
+        let problems = config.validate();
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].starts_with("redis_url is invalid"));
+    }
+
+    #[test]
+    fn feedback_url_falls_back_to_the_default() {
+        assert_eq!(valid_config().feedback_url(), DEFAULT_FEEDBACK_URL);
+        let config = Config {
+            feedback_url: Some(String::from("https://example.com/")),
+            ..valid_config()
+        };
+        assert_eq!(config.feedback_url(), "https://example.com/");
+    }
+
+    #[test]
+    fn server_config_default_comes_from_the_config_file() {
+        assert_eq!(
+            valid_config().server_config_default(),
+            ServerConfig::default()
+        );
+
+        let config = toml::from_str::<Config>(
+            r#"
+            token = "token"
+            application_id = 1
+            redis_url = "redis://127.0.0.1/"
+            voicevox_key = "key"
+
+            [default_server_config]
+            read_bots = true
+            "#,
+        )
+        .unwrap();
+        let default = config.server_config_default();
+        assert_eq!(default.read_bots, Some(true));
+        assert_eq!(default.dictionary, Dictionary::new());
+    }
+
+    #[test]
+    fn seed_dictionary_replaces_the_default_rules() {
+        let config = toml::from_str::<Config>(
+            r#"
+            token = "token"
+            application_id = 1
+            redis_url = "redis://127.0.0.1/"
+            voicevox_key = "key"
+
+            [[default_server_config.dictionary.rules]]
+            id = "ncb"
+            is_regex = false
+            rule = "ncb"
+            to = "エヌシービー"
+            "#,
+        )
+        .unwrap();
+        let default = config.server_config_default();
+        assert_eq!(default.dictionary.rules.len(), 1);
+        assert_eq!(default.dictionary.rules[0].to, "エヌシービー");
+        assert_eq!(default.read_bots, None);
+    }
 }
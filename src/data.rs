@@ -1,28 +1,33 @@
 use crate::{
+    config::Config,
     database::database::Database,
-    tts::{gcp_tts::gcp_tts::TTS, voicevox::voicevox::VOICEVOX},
+    tts::{azure::azure::AzureTTS, gcp_tts::gcp_tts::TTS, voicevox::voicevox::VOICEVOX},
 };
 use serenity::{
     futures::lock::Mutex,
+    gateway::ShardManager,
     model::id::GuildId,
     prelude::{RwLock, TypeMapKey},
 };
 
 use crate::tts::instance::TTSInstance;
-use std::{collections::HashMap, sync::Arc};
+use std::{collections::HashMap, sync::Arc, time::Instant};
 
 /// TTSInstance data
 pub struct TTSData;
 
 impl TypeMapKey for TTSData {
-    type Value = Arc<RwLock<HashMap<GuildId, TTSInstance>>>;
+    /// Each instance has its own lock so synthesis in one guild does not block the others.
+    type Value = Arc<RwLock<HashMap<GuildId, Arc<Mutex<TTSInstance>>>>>;
 }
 
 /// TTS client data
 pub struct TTSClientData;
 
 impl TypeMapKey for TTSClientData {
-    type Value = Arc<Mutex<(TTS, VOICEVOX)>>;
+    /// The Azure client is None when it is not configured.
+    /// Synthesis runs on copies taken with `engine_clients`, so the lock is held only briefly.
+    type Value = Arc<Mutex<(TTS, VOICEVOX, Option<AzureTTS>)>>;
 }
 
 /// Database client data
@@ -31,3 +36,24 @@ pub struct DatabaseClientData;
 impl TypeMapKey for DatabaseClientData {
     type Value = Arc<Mutex<Database>>;
 }
+
+/// Config data
+pub struct ConfigData;
+
+impl TypeMapKey for ConfigData {
+    type Value = Arc<Config>;
+}
+
+/// Shard manager, used to read gateway latency
+pub struct ShardManagerData;
+
+impl TypeMapKey for ShardManagerData {
+    type Value = Arc<ShardManager>;
+}
+
+/// Time of the first ready event
+pub struct StartTimeData;
+
+impl TypeMapKey for StartTimeData {
+    type Value = Instant;
+}
@@ -2,25 +2,70 @@ use crate::tts::{
     gcp_tts::structs::voice_selection_params::VoiceSelectionParams, tts_type::TTSType,
 };
 
-use super::{dictionary::Dictionary, server_config::ServerConfig, user_config::UserConfig};
+use super::{server_config::ServerConfig, user_config::UserConfig};
 use redis::Commands;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Seconds a daily character counter is kept after it is created.
+const DAILY_CHARS_TTL: i64 = 2 * 24 * 60 * 60;
+
+/// Days since the Unix epoch, so daily counters start over at UTC midnight.
+pub fn utc_day(now: SystemTime) -> u64 {
+    now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() / (24 * 60 * 60)
+}
 
 pub struct Database {
     pub client: redis::Client,
+    pub default_server_config: ServerConfig,
+    /// Prepended to every key, followed by ":", so several bot instances can share one Redis.
+    pub key_prefix: String,
 }
 
 impl Database {
-    pub fn new(client: redis::Client) -> Self {
-        Self { client }
+    pub fn new(
+        client: redis::Client,
+        default_server_config: ServerConfig,
+        key_prefix: String,
+    ) -> Self {
+        Self {
+            client,
+            default_server_config,
+            key_prefix,
+        }
     }
 
+    /// `key` with the key prefix and a ":" in front, or `key` itself without a prefix.
+    fn prefixed(&self, key: String) -> String {
+        if self.key_prefix.is_empty() {
+            key
+        } else {
+            format!("{}:{}", self.key_prefix, key)
+        }
+    }
+
+    /// Key of a server config: `{prefix}:discord_server:{server_id}`.
+    pub fn server_key(&self, server_id: u64) -> String {
+        self.prefixed(format!("discord_server:{}", server_id))
+    }
+
+    /// Key of the server config before its last change: `{server_key}:previous`.
+    pub fn server_backup_key(&self, server_id: u64) -> String {
+        format!("{}:previous", self.server_key(server_id))
+    }
+
+    /// Key of a user config: `{prefix}:discord_user:{user_id}`.
+    pub fn user_key(&self, user_id: u64) -> String {
+        self.prefixed(format!("discord_user:{}", user_id))
+    }
+
+    /// Uses `server_key`.
     pub async fn get_server_config(
         &mut self,
         server_id: u64,
     ) -> redis::RedisResult<Option<ServerConfig>> {
         if let Ok(mut connection) = self.client.get_connection() {
             let config: String = connection
-                .get(format!("discord_server:{}", server_id))
+                .get(self.server_key(server_id))
                 .unwrap_or_default();
 
             match serde_json::from_str(&config) {
@@ -32,14 +77,13 @@ impl Database {
         }
     }
 
+    /// Uses `user_key`.
     pub async fn get_user_config(
         &mut self,
         user_id: u64,
     ) -> redis::RedisResult<Option<UserConfig>> {
         if let Ok(mut connection) = self.client.get_connection() {
-            let config: String = connection
-                .get(format!("discord_user:{}", user_id))
-                .unwrap_or_default();
+            let config: String = connection.get(self.user_key(user_id)).unwrap_or_default();
 
             match serde_json::from_str(&config) {
                 Ok(config) => Ok(Some(config)),
@@ -50,20 +94,43 @@ impl Database {
         }
     }
 
+    /// Keeps the replaced config for `undo_server_config`.
+    /// Uses `server_key` and `server_backup_key`.
     pub async fn set_server_config(
         &mut self,
         server_id: u64,
         config: ServerConfig,
     ) -> redis::RedisResult<()> {
         let config = serde_json::to_string(&config).unwrap();
-        self.client
-            .get_connection()
-            .unwrap()
-            .set::<String, String, ()>(format!("discord_server:{}", server_id), config)
+        let mut connection = self.client.get_connection().unwrap();
+        let previous: Option<String> = connection.get(self.server_key(server_id))?;
+        if let Some(previous) = previous.filter(|previous| *previous != config) {
+            connection.set::<String, String, ()>(self.server_backup_key(server_id), previous)?;
+        }
+        connection
+            .set::<String, String, ()>(self.server_key(server_id), config)
             .unwrap();
         Ok(())
     }
 
+    /// Restore the server config saved before its last change.
+    /// The current config becomes the new backup, so a second undo redoes the change.
+    /// Returns false when there is nothing to restore.
+    /// Uses `server_backup_key`.
+    pub async fn undo_server_config(&mut self, server_id: u64) -> redis::RedisResult<bool> {
+        let previous: Option<String> = self
+            .client
+            .get_connection()?
+            .get(self.server_backup_key(server_id))?;
+        let previous = match previous.and_then(|previous| serde_json::from_str(&previous).ok()) {
+            Some(previous) => previous,
+            None => return Ok(false),
+        };
+        self.set_server_config(server_id, previous).await?;
+        Ok(true)
+    }
+
+    /// Uses `user_key`.
     pub async fn set_user_config(
         &mut self,
         user_id: u64,
@@ -73,26 +140,20 @@ impl Database {
         self.client
             .get_connection()
             .unwrap()
-            .set::<String, String, ()>(format!("discord_user:{}", user_id), config)
+            .set::<String, String, ()>(self.user_key(user_id), config)
             .unwrap();
         Ok(())
     }
 
+    /// Goes through `set_server_config`, so a reset can be undone.
+    /// Uses `server_key`.
     pub async fn set_default_server_config(&mut self, server_id: u64) -> redis::RedisResult<()> {
-        let config = ServerConfig {
-            dictionary: Dictionary::new(),
-            autostart_channel_id: None,
-        };
-
-        self.client.get_connection().unwrap().set(
-            format!("discord_server:{}", server_id),
-            serde_json::to_string(&config).unwrap(),
-        )?;
-
-        Ok(())
+        let config = self.default_server_config.clone();
+        self.set_server_config(server_id, config).await
     }
 
-    pub async fn set_default_user_config(&mut self, user_id: u64) -> redis::RedisResult<()> {
+    /// Config given to users who have not configured anything.
+    pub fn default_user_config() -> UserConfig {
         let voice_selection = VoiceSelectionParams {
             languageCode: String::from("ja-JP"),
             name: String::from("ja-JP-Wavenet-B"),
@@ -101,18 +162,55 @@ impl Database {
 
         let voice_type = TTSType::GCP;
 
-        let config = UserConfig {
+        UserConfig {
             tts_type: Some(voice_type),
             gcp_tts_voice: Some(voice_selection),
             voicevox_speaker: Some(1),
-        };
+            opt_out: None,
+            read_own_name: None,
+            speaking_rate: None,
+            pitch: None,
+            azure_voice: None,
+            custom_join_message: None,
+            gcp_effects_profile: None,
+        }
+    }
 
-        self.client.get_connection().unwrap().set(
-            format!("discord_user:{}", user_id),
-            serde_json::to_string(&config).unwrap(),
-        )?;
+    /// Key of a guild's character counter for a UTC day: `{server_key}:chars:{day}`.
+    pub fn daily_chars_key(&self, server_id: u64, day: u64) -> String {
+        format!("{}:chars:{}", self.server_key(server_id), day)
+    }
 
-        Ok(())
+    /// Characters synthesized in the guild on the UTC day.
+    /// Uses `daily_chars_key`.
+    pub async fn get_guild_daily_chars(
+        &mut self,
+        server_id: u64,
+        day: u64,
+    ) -> redis::RedisResult<u64> {
+        let total: Option<u64> = self
+            .client
+            .get_connection()?
+            .get(self.daily_chars_key(server_id, day))?;
+        Ok(total.unwrap_or(0))
+    }
+
+    /// Add synthesized characters to the guild's counter for the UTC day
+    /// and return the new total. Counters expire on their own after two days.
+    /// Uses `daily_chars_key`.
+    pub async fn incr_guild_daily_chars(
+        &mut self,
+        server_id: u64,
+        day: u64,
+        chars: u64,
+    ) -> redis::RedisResult<u64> {
+        let key = self.daily_chars_key(server_id, day);
+
+        let mut connection = self.client.get_connection()?;
+        let total: u64 = connection.incr(&key, chars)?;
+        connection.expire::<&String, ()>(&key, DAILY_CHARS_TTL)?;
+
+        Ok(total)
     }
 
     pub async fn get_server_config_or_default(
@@ -129,17 +227,352 @@ impl Database {
         }
     }
 
-    pub async fn get_user_config_or_default(
+    /// Get the user's config, creating it for a new user.
+    /// A new user's config starts with the server's `default_user_voice` when the server
+    /// has one; pass None outside servers.
+    pub async fn get_user_config_or_guild_default(
         &mut self,
         user_id: u64,
+        server_id: Option<u64>,
     ) -> redis::RedisResult<Option<UserConfig>> {
         let config = self.get_user_config(user_id).await?;
-        match config {
-            Some(_) => Ok(config),
-            None => {
-                self.set_default_user_config(user_id).await?;
-                self.get_user_config(user_id).await
+        if config.is_some() {
+            return Ok(config);
+        }
+
+        let config = self.guild_default_user_config(server_id).await?;
+        self.set_user_config(user_id, config).await?;
+        self.get_user_config(user_id).await
+    }
+
+    /// Replace the user's config with the one a new user gets in the server.
+    pub async fn reset_user_config(
+        &mut self,
+        user_id: u64,
+        server_id: Option<u64>,
+    ) -> redis::RedisResult<()> {
+        let config = self.guild_default_user_config(server_id).await?;
+        self.set_user_config(user_id, config).await
+    }
+
+    /// Config for a new user in the server, using its `default_user_voice` if set.
+    async fn guild_default_user_config(
+        &mut self,
+        server_id: Option<u64>,
+    ) -> redis::RedisResult<UserConfig> {
+        let voice = match server_id {
+            Some(server_id) => self
+                .get_server_config(server_id)
+                .await?
+                .and_then(|config| config.default_user_voice),
+            None => None,
+        };
+        let mut config = Self::default_user_config();
+        if let Some(voice) = voice {
+            config.tts_type = Some(voice.tts_type);
+            if voice.gcp_tts_voice.is_some() {
+                config.gcp_tts_voice = voice.gcp_tts_voice;
+            }
+            if voice.voicevox_speaker.is_some() {
+                config.voicevox_speaker = voice.voicevox_speaker;
             }
+            config.azure_voice = voice.azure_voice;
+        }
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{dictionary::Dictionary, server_config::VoiceConfig};
+
+    /// Database on the Redis at `NCB_TEST_REDIS_URL`; the test is skipped when it is unset.
+    /// Each test uses its own key prefix.
+    fn test_database(name: &str) -> Option<Database> {
+        let url = std::env::var("NCB_TEST_REDIS_URL").ok()?;
+        Some(Database::new(
+            redis::Client::open(url).unwrap(),
+            ServerConfig::default(),
+            format!("ncb-test-{}-{}", name, std::process::id()),
+        ))
+    }
+
+    fn database(key_prefix: &str) -> Database {
+        Database::new(
+            redis::Client::open("redis://127.0.0.1/").unwrap(),
+            ServerConfig::default(),
+            key_prefix.to_string(),
+        )
+    }
+
+    #[test]
+    fn keys_without_a_prefix_are_unchanged() {
+        let database = database("");
+        assert_eq!(database.server_key(1), "discord_server:1");
+        assert_eq!(database.server_backup_key(1), "discord_server:1:previous");
+        assert_eq!(database.user_key(2), "discord_user:2");
+        assert_eq!(database.daily_chars_key(1, 3), "discord_server:1:chars:3");
+    }
+
+    #[test]
+    fn prefix_is_separated_by_a_colon() {
+        let database = database("ncb");
+        assert_eq!(database.server_key(1), "ncb:discord_server:1");
+        assert_eq!(
+            database.server_backup_key(1),
+            "ncb:discord_server:1:previous"
+        );
+        assert_eq!(database.user_key(2), "ncb:discord_user:2");
+        assert_eq!(
+            database.daily_chars_key(1, 3),
+            "ncb:discord_server:1:chars:3"
+        );
+    }
+
+    #[test]
+    fn default_user_voice_is_gcp_wavenet_b() {
+        let config = Database::default_user_config();
+        assert_eq!(config.tts_type, Some(TTSType::GCP));
+        assert_eq!(config.gcp_tts_voice.unwrap().name, "ja-JP-Wavenet-B");
+        assert_eq!(config.voicevox_speaker, Some(1));
+        assert_eq!(config.opt_out, None);
+    }
+
+    fn delete(database: &Database, keys: &[String]) {
+        let mut connection = database.client.get_connection().unwrap();
+        for key in keys {
+            connection.del::<&str, ()>(key).unwrap();
         }
     }
+
+    #[tokio::test]
+    async fn undo_restores_and_swaps_the_backup() {
+        let Some(mut database) = test_database("undo") else {
+            return;
+        };
+        let keys = [database.server_key(1), database.server_backup_key(1)];
+        delete(&database, &keys);
+
+        let first = ServerConfig {
+            read_bots: Some(true),
+            ..Default::default()
+        };
+        let second = ServerConfig {
+            read_bots: Some(false),
+            ..Default::default()
+        };
+        assert!(!database.undo_server_config(1).await.unwrap());
+
+        database.set_server_config(1, first.clone()).await.unwrap();
+        database.set_server_config(1, second.clone()).await.unwrap();
+        let backup: String = database
+            .client
+            .get_connection()
+            .unwrap()
+            .get(database.server_backup_key(1))
+            .unwrap();
+        assert_eq!(
+            serde_json::from_str::<ServerConfig>(&backup).unwrap(),
+            first
+        );
+
+        assert!(database.undo_server_config(1).await.unwrap());
+        assert_eq!(database.get_server_config(1).await.unwrap(), Some(first));
+        // The undone config became the backup, so a second undo redoes the change.
+        assert!(database.undo_server_config(1).await.unwrap());
+        assert_eq!(database.get_server_config(1).await.unwrap(), Some(second));
+
+        delete(&database, &keys);
+    }
+
+    #[tokio::test]
+    async fn saving_the_same_config_keeps_the_backup() {
+        let Some(mut database) = test_database("same") else {
+            return;
+        };
+        let keys = [database.server_key(1), database.server_backup_key(1)];
+        delete(&database, &keys);
+
+        let first = ServerConfig::default();
+        let second = ServerConfig {
+            read_bots: Some(true),
+            ..Default::default()
+        };
+        database.set_server_config(1, first.clone()).await.unwrap();
+        database.set_server_config(1, second.clone()).await.unwrap();
+        database.set_server_config(1, second).await.unwrap();
+
+        assert!(database.undo_server_config(1).await.unwrap());
+        assert_eq!(database.get_server_config(1).await.unwrap(), Some(first));
+
+        delete(&database, &keys);
+    }
+
+    #[tokio::test]
+    async fn reset_writes_the_configured_default() {
+        let Some(mut database) = test_database("reset") else {
+            return;
+        };
+        database.default_server_config = ServerConfig {
+            read_bots: Some(true),
+            ..Default::default()
+        };
+        let keys = [database.server_key(1), database.server_backup_key(1)];
+        delete(&database, &keys);
+
+        let mut changed = database.default_server_config.clone();
+        changed.dictionary.clear();
+        changed.mention_hint = Some(true);
+        database
+            .set_server_config(1, changed.clone())
+            .await
+            .unwrap();
+
+        database.set_default_server_config(1).await.unwrap();
+        let reset = database.get_server_config(1).await.unwrap().unwrap();
+        assert_eq!(reset, database.default_server_config);
+        assert_eq!(reset.dictionary, Dictionary::new());
+
+        assert!(database.undo_server_config(1).await.unwrap());
+        assert_eq!(database.get_server_config(1).await.unwrap(), Some(changed));
+
+        delete(&database, &keys);
+    }
+
+    #[test]
+    fn utc_day_changes_at_midnight() {
+        let midnight = UNIX_EPOCH + std::time::Duration::from_secs(20_000 * 24 * 60 * 60);
+        assert_eq!(utc_day(midnight), 20_000);
+        assert_eq!(
+            utc_day(midnight - std::time::Duration::from_secs(1)),
+            19_999
+        );
+        assert_eq!(utc_day(UNIX_EPOCH), 0);
+    }
+
+    #[tokio::test]
+    async fn daily_chars_accumulate_within_a_day() {
+        let Some(mut database) = test_database("chars") else {
+            return;
+        };
+        let keys = [
+            database.daily_chars_key(1, 100),
+            database.daily_chars_key(1, 101),
+        ];
+        delete(&database, &keys);
+
+        assert_eq!(database.get_guild_daily_chars(1, 100).await.unwrap(), 0);
+        assert_eq!(
+            database.incr_guild_daily_chars(1, 100, 10).await.unwrap(),
+            10
+        );
+        assert_eq!(
+            database.incr_guild_daily_chars(1, 100, 5).await.unwrap(),
+            15
+        );
+        assert_eq!(database.get_guild_daily_chars(1, 100).await.unwrap(), 15);
+
+        // The next UTC day has its own counter.
+        assert_eq!(database.get_guild_daily_chars(1, 101).await.unwrap(), 0);
+        assert_eq!(database.incr_guild_daily_chars(1, 101, 3).await.unwrap(), 3);
+        assert_eq!(database.get_guild_daily_chars(1, 100).await.unwrap(), 15);
+
+        delete(&database, &keys);
+    }
+
+    #[tokio::test]
+    async fn new_servers_get_the_configured_default() {
+        let Some(mut database) = test_database("default") else {
+            return;
+        };
+        database.default_server_config = ServerConfig {
+            mention_hint: Some(true),
+            ..Default::default()
+        };
+        let keys = [database.server_key(1), database.server_backup_key(1)];
+        delete(&database, &keys);
+
+        assert_eq!(database.get_server_config(1).await.unwrap(), None);
+        let created = database.get_server_config_or_default(1).await.unwrap();
+        assert_eq!(created, Some(database.default_server_config.clone()));
+        assert_eq!(database.get_server_config(1).await.unwrap(), created);
+
+        delete(&database, &keys);
+    }
+
+    #[tokio::test]
+    async fn reset_user_config_restores_the_default_voice() {
+        let Some(mut database) = test_database("user-reset") else {
+            return;
+        };
+        let keys = [database.user_key(2)];
+        delete(&database, &keys);
+
+        // A user without a config can reset too.
+        database.reset_user_config(2, None).await.unwrap();
+        assert_eq!(
+            database.get_user_config(2).await.unwrap(),
+            Some(Database::default_user_config())
+        );
+
+        let mut changed = Database::default_user_config();
+        changed.tts_type = Some(TTSType::VOICEVOX);
+        changed.voicevox_speaker = Some(8);
+        changed.opt_out = Some(true);
+        database.set_user_config(2, changed).await.unwrap();
+
+        database.reset_user_config(2, None).await.unwrap();
+        let config = database.get_user_config(2).await.unwrap().unwrap();
+        assert_eq!(config.tts_type, Some(TTSType::GCP));
+        assert_eq!(config.gcp_tts_voice.unwrap().name, "ja-JP-Wavenet-B");
+        assert_eq!(config.voicevox_speaker, Some(1));
+        assert_eq!(config.opt_out, None);
+
+        delete(&database, &keys);
+    }
+
+    #[tokio::test]
+    async fn new_users_get_the_guild_default_voice() {
+        let Some(mut database) = test_database("guild-voice") else {
+            return;
+        };
+        let keys = [
+            database.server_key(1),
+            database.server_backup_key(1),
+            database.user_key(2),
+            database.user_key(3),
+        ];
+        delete(&database, &keys);
+
+        let server = ServerConfig {
+            default_user_voice: Some(VoiceConfig {
+                tts_type: TTSType::VOICEVOX,
+                gcp_tts_voice: None,
+                voicevox_speaker: Some(3),
+                azure_voice: None,
+            }),
+            ..Default::default()
+        };
+        database.set_server_config(1, server).await.unwrap();
+
+        let config = database
+            .get_user_config_or_guild_default(2, Some(1))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(config.tts_type, Some(TTSType::VOICEVOX));
+        assert_eq!(config.voicevox_speaker, Some(3));
+        // Settings the server voice leaves unset keep the global defaults.
+        assert_eq!(config.gcp_tts_voice.unwrap().name, "ja-JP-Wavenet-B");
+
+        // Outside the guild the global default is used.
+        let config = database
+            .get_user_config_or_guild_default(3, None)
+            .await
+            .unwrap();
+        assert_eq!(config, Some(Database::default_user_config()));
+
+        delete(&database, &keys);
+    }
 }
@@ -1,5 +1,24 @@
+use regex::{NoExpand, Regex, RegexBuilder};
 use serde::{Deserialize, Serialize};
 
+/// Compiled size limit for user regex rules, in bytes.
+const REGEX_SIZE_LIMIT: usize = 1 << 20;
+/// Lazy DFA cache limit for user regex rules, in bytes.
+const REGEX_DFA_SIZE_LIMIT: usize = 1 << 20;
+/// Maximum number of rules added by one import.
+pub const MAX_IMPORT_RULES: usize = 100;
+/// Maximum length of an imported pattern or replacement, in characters.
+pub const MAX_IMPORT_RULE_CHARS: usize = 100;
+
+/// Compile a user regex rule with bounded program and DFA sizes.
+/// Patterns that would need more memory fail to compile instead of slowing down every message.
+pub fn build_rule_regex(pattern: &str) -> Result<Regex, regex::Error> {
+    RegexBuilder::new(pattern)
+        .size_limit(REGEX_SIZE_LIMIT)
+        .dfa_size_limit(REGEX_DFA_SIZE_LIMIT)
+        .build()
+}
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Rule {
     pub id: String,
@@ -31,4 +50,264 @@ impl Dictionary {
         ];
         Self { rules }
     }
+
+    /// Apply all rules to the text in order.
+    ///
+    /// Example:
+    /// ```rust
+    /// let text = config.dictionary.apply(&message.content);
+    /// ```
+    pub fn apply(&self, text: &str) -> String {
+        let mut text = text.to_string();
+        for rule in &self.rules {
+            if is_ascii_word(&rule.rule) {
+                // Latin words only match as a whole word, so "AI" does not hit "SAID".
+                let regex =
+                    Regex::new(&format!(r"(?-u:\b){}(?-u:\b)", regex::escape(&rule.rule))).unwrap();
+                text = regex
+                    .replace_all(&text, NoExpand(rule.to.as_str()))
+                    .to_string();
+            } else if rule.is_regex {
+                let regex = match build_rule_regex(&rule.rule) {
+                    Ok(regex) => regex,
+                    Err(err) => {
+                        println!("Skipping dictionary rule {}: {}", rule.id, err);
+                        continue;
+                    }
+                };
+                text = regex.replace_all(&text, rule.to.as_str()).to_string();
+            } else {
+                text = text.replace(&rule.rule, &rule.to);
+            }
+        }
+        text
+    }
+
+    /// Remove every rule including the default ones.
+    pub fn clear(&mut self) {
+        self.rules.clear();
+    }
+
+    /// Replace all rules with the default rules.
+    pub fn reset_to_defaults(&mut self) {
+        self.rules = Self::new().rules;
+    }
+
+    /// Find the first blacklisted entry contained in the rule pattern.
+    ///
+    /// Example:
+    /// ```rust
+    /// if let Some(entry) = Dictionary::find_blacklisted(&from, &blacklist) {
+    ///     println!("{} is not allowed", entry);
+    /// }
+    /// ```
+    pub fn find_blacklisted<'a>(rule: &str, blacklist: &'a [String]) -> Option<&'a String> {
+        blacklist
+            .iter()
+            .find(|entry| !entry.is_empty() && rule.contains(entry.as_str()))
+    }
+}
+
+/// Parse a plain-text dictionary with one `from=to` rule per line.
+/// Blank lines and lines starting with `#` are ignored, as are lines without `=`
+/// or with an empty `from`. Every rule is literal and, like rules added from the menu,
+/// is named by its pattern.
+///
+/// Example:
+/// ```rust
+/// let rules = parse_dictionary_txt("# comment\nncb=エヌシービー\n");
+/// assert_eq!(rules[0].rule, "ncb");
+/// ```
+pub fn parse_dictionary_txt(contents: &str) -> Vec<Rule> {
+    contents
+        .lines()
+        .filter_map(|line| {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                return None;
+            }
+            let (from, to) = line.split_once('=')?;
+            let from = from.trim();
+            if from.is_empty() {
+                return None;
+            }
+            Some(Rule {
+                id: from.to_string(),
+                is_regex: false,
+                rule: from.to_string(),
+                to: to.trim().to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Rules from an import, sorted by what happens to them.
+#[derive(Debug, Default, PartialEq)]
+pub struct DictionaryImport {
+    pub added: Vec<Rule>,
+    pub blacklisted: usize,
+    pub invalid: usize,
+    pub duplicate: usize,
+    pub over_limit: usize,
+}
+
+/// Decide which parsed rules to add to the dictionary.
+/// Rules containing a blacklisted entry, longer than `MAX_IMPORT_RULE_CHARS`, failing to
+/// compile or already in the dictionary are skipped, and at most `MAX_IMPORT_RULES` are added.
+///
+/// Example:
+/// ```rust
+/// let import = plan_import(&config.dictionary, parse_dictionary_txt(&contents), &blacklist);
+/// config.dictionary.rules.extend(import.added.clone());
+/// ```
+pub fn plan_import(
+    dictionary: &Dictionary,
+    rules: Vec<Rule>,
+    blacklist: &[String],
+) -> DictionaryImport {
+    let mut import = DictionaryImport::default();
+    for rule in rules {
+        if Dictionary::find_blacklisted(&rule.rule, blacklist).is_some() {
+            import.blacklisted += 1;
+        } else if rule.rule.chars().count() > MAX_IMPORT_RULE_CHARS
+            || rule.to.chars().count() > MAX_IMPORT_RULE_CHARS
+            || build_rule_regex(&regex::escape(&rule.rule)).is_err()
+        {
+            import.invalid += 1;
+        } else if dictionary
+            .rules
+            .iter()
+            .chain(import.added.iter())
+            .any(|existing| existing.id == rule.id || existing.rule == rule.rule)
+        {
+            import.duplicate += 1;
+        } else if import.added.len() >= MAX_IMPORT_RULES {
+            import.over_limit += 1;
+        } else {
+            import.added.push(rule);
+        }
+    }
+    import
+}
+
+/// Whether the pattern is a single ASCII word such as "AI".
+/// Such patterns are matched on word boundaries in `Dictionary::apply`.
+pub fn is_ascii_word(pattern: &str) -> bool {
+    !pattern.is_empty()
+        && pattern
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn literal(from: &str, to: &str) -> Rule {
+        Rule {
+            id: from.to_string(),
+            is_regex: false,
+            rule: from.to_string(),
+            to: to.to_string(),
+        }
+    }
+
+    #[test]
+    fn clear_removes_the_default_rules_and_reset_restores_them() {
+        let mut dictionary = Dictionary::new();
+        dictionary.rules.push(literal("ncb", "エヌシービー"));
+
+        let mut cleared = dictionary.clone();
+        cleared.clear();
+        assert!(cleared.rules.is_empty());
+
+        dictionary.reset_to_defaults();
+        assert_eq!(dictionary, Dictionary::new());
+        assert!(!dictionary.rules.is_empty());
+        assert!(!dictionary.rules.contains(&literal("ncb", "エヌシービー")));
+    }
+
+    #[test]
+    fn parse_dictionary_txt_skips_comments_and_bad_lines() {
+        let rules = parse_dictionary_txt(
+            "# comment\n\nncb = エヌシービー\nno separator\n=empty\nw=わら=\nempty=\n",
+        );
+        assert_eq!(
+            rules,
+            vec![
+                literal("ncb", "エヌシービー"),
+                literal("w", "わら="),
+                literal("empty", ""),
+            ]
+        );
+    }
+
+    #[test]
+    fn plan_import_skips_blacklisted_invalid_and_duplicates() {
+        let dictionary = Dictionary::new();
+        let long = "あ".repeat(MAX_IMPORT_RULE_CHARS + 1);
+        let import = plan_import(
+            &dictionary,
+            vec![
+                literal("ncb", "エヌシービー"),
+                literal("ncb", "えぬしーびー"),
+                literal("bad", "x"),
+                literal(&long, "x"),
+                literal("url", "x"),
+            ],
+            &[String::from("bad")],
+        );
+        assert_eq!(import.added, vec![literal("ncb", "エヌシービー")]);
+        assert_eq!(import.blacklisted, 1);
+        assert_eq!(import.invalid, 1);
+        assert_eq!(import.duplicate, 2);
+        assert_eq!(import.over_limit, 0);
+    }
+
+    #[test]
+    fn plan_import_caps_the_number_of_rules() {
+        let rules = (0..MAX_IMPORT_RULES + 5)
+            .map(|i| literal(&format!("word{}", i), "x"))
+            .collect();
+        let import = plan_import(&Dictionary::new(), rules, &[]);
+        assert_eq!(import.added.len(), MAX_IMPORT_RULES);
+        assert_eq!(import.over_limit, 5);
+    }
+
+    #[test]
+    fn ascii_words_match_whole_words() {
+        let dictionary = Dictionary {
+            rules: vec![literal("AI", "エーアイ")],
+        };
+        assert_eq!(dictionary.apply("AI SAID"), "エーアイ SAID");
+    }
+
+    fn regex_rule(pattern: &str, to: &str) -> Rule {
+        Rule {
+            id: pattern.to_string(),
+            is_regex: true,
+            rule: pattern.to_string(),
+            to: to.to_string(),
+        }
+    }
+
+    #[test]
+    fn oversized_regex_rules_are_skipped() {
+        let pattern = r"(\w{100}){100}";
+        assert!(build_rule_regex(pattern).is_err());
+
+        let dictionary = Dictionary {
+            rules: vec![regex_rule(pattern, "x"), literal("ncb", "エヌシービー")],
+        };
+        assert_eq!(dictionary.apply("ncb"), "エヌシービー");
+    }
+
+    #[test]
+    fn backtracking_patterns_run_on_pathological_input() {
+        let dictionary = Dictionary {
+            rules: vec![regex_rule(r"(a+)+$", "x")],
+        };
+        let input = format!("{}!", "a".repeat(10_000));
+        assert_eq!(dictionary.apply(&input), input);
+    }
 }
@@ -1,4 +1,5 @@
 pub mod database;
 pub mod dictionary;
+pub mod schedule;
 pub mod server_config;
 pub mod user_config;
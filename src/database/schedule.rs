@@ -0,0 +1,81 @@
+use serde::{Deserialize, Serialize};
+
+/// Shortest interval a schedule may use, in minutes.
+pub const MIN_SCHEDULE_INTERVAL_MINUTES: u64 = 5;
+/// Longest interval a schedule may use, in minutes (one week).
+pub const MAX_SCHEDULE_INTERVAL_MINUTES: u64 = 7 * 24 * 60;
+/// Maximum number of schedules per server.
+pub const MAX_SCHEDULES: usize = 10;
+/// Maximum length of a scheduled phrase, in characters.
+pub const MAX_SCHEDULE_PHRASE_LENGTH: usize = 100;
+
+/// A phrase announced in every connected voice channel of the server at a fixed interval.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Schedule {
+    pub id: u32,
+    pub phrase: String,
+    pub interval_minutes: u64,
+}
+
+/// Whether a schedule last run at `last_run` is due at `now`.
+/// Times are unix seconds.
+///
+/// Example:
+/// ```rust
+/// assert!(!is_due(0, 5, 299));
+/// assert!(is_due(0, 5, 300));
+/// ```
+pub fn is_due(last_run: u64, interval_minutes: u64, now: u64) -> bool {
+    now.saturating_sub(last_run) >= interval_minutes * 60
+}
+
+/// Id for a new schedule: one more than the largest id handed out so far.
+/// Ids of removed schedules are never reused, since the scheduler tracks runs by id.
+///
+/// Example:
+/// ```rust
+/// let id = next_schedule_id(&config.schedules, config.last_schedule_id);
+/// config.last_schedule_id = Some(id);
+/// ```
+pub fn next_schedule_id(schedules: &[Schedule], last_id: Option<u32>) -> u32 {
+    schedules
+        .iter()
+        .map(|s| s.id)
+        .chain(last_id)
+        .max()
+        .map_or(1, |id| id + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schedule(id: u32) -> Schedule {
+        Schedule {
+            id,
+            phrase: String::from("休憩しましょう"),
+            interval_minutes: 30,
+        }
+    }
+
+    #[test]
+    fn is_due_after_the_interval() {
+        assert!(!is_due(1_000, 5, 1_000));
+        assert!(!is_due(1_000, 5, 1_299));
+        assert!(is_due(1_000, 5, 1_300));
+        assert!(is_due(1_000, 5, 10_000));
+    }
+
+    #[test]
+    fn is_due_ignores_clock_going_back() {
+        assert!(!is_due(1_000, 5, 500));
+    }
+
+    #[test]
+    fn next_schedule_id_never_reuses_ids() {
+        assert_eq!(next_schedule_id(&[], None), 1);
+        assert_eq!(next_schedule_id(&[schedule(1), schedule(4)], None), 5);
+        assert_eq!(next_schedule_id(&[schedule(1)], Some(4)), 5);
+        assert_eq!(next_schedule_id(&[], Some(4)), 5);
+    }
+}
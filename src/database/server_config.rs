@@ -1,13 +1,333 @@
-use super::dictionary::Dictionary;
+use super::{dictionary::Dictionary, schedule::Schedule};
 use serde::{Deserialize, Serialize};
 
+use crate::tts::{
+    gcp_tts::structs::voice_selection_params::VoiceSelectionParams, tts_type::TTSType,
+};
+
+#[allow(dead_code)]
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct DictionaryOnlyServerConfig {
     pub dictionary: Dictionary,
 }
 
+/// Engine and voice used for server-side speech such as announcements.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct VoiceConfig {
+    pub tts_type: TTSType,
+    pub gcp_tts_voice: Option<VoiceSelectionParams>,
+    pub voicevox_speaker: Option<i64>,
+    pub azure_voice: Option<String>,
+}
+
+impl VoiceConfig {
+    /// Voice used for announcements when the server has not configured one.
+    pub fn announce_default() -> Self {
+        Self {
+            tts_type: TTSType::GCP,
+            gcp_tts_voice: Some(VoiceSelectionParams {
+                languageCode: String::from("ja-JP"),
+                name: String::from("ja-JP-Wavenet-B"),
+                ssmlGender: String::from("neutral"),
+            }),
+            voicevox_speaker: None,
+            azure_voice: None,
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct ServerConfig {
     pub dictionary: Dictionary,
     pub autostart_channel_id: Option<u64>,
+    pub allow_inline_speed: Option<bool>,
+    pub mention_hint: Option<bool>,
+    pub fade_ms: Option<u64>,
+    pub normalize_numbers: Option<bool>,
+    pub read_bots: Option<bool>,
+    pub read_spoilers: Option<bool>,
+    pub ssml_allowed_roles: Vec<u64>,
+    pub read_first_line_only: Option<bool>,
+    pub announce_voice: Option<VoiceConfig>,
+    pub read_thread_names: Option<bool>,
+    pub chunk_long_messages: Option<bool>,
+    pub chunk_length: Option<usize>,
+    pub read_news: Option<bool>,
+    pub news_first_sentence_only: Option<bool>,
+    pub daily_char_limit: Option<u64>,
+    pub autostart_text_channel_id: Option<u64>,
+    pub max_reads_per_minute: Option<u32>,
+    pub react_on_throttle: Option<bool>,
+    pub react_on_queue: Option<bool>,
+    pub post_join_embed: Option<bool>,
+    pub read_stickers: Option<bool>,
+    pub read_role_title: Option<bool>,
+    pub announce_debounce_secs: Option<u64>,
+    pub allow_custom_join: Option<bool>,
+    pub read_edits: Option<bool>,
+    pub ignored_text_channels: Vec<u64>,
+    pub ignore_prefixes: Vec<String>,
+    pub auto_speed: Option<bool>,
+    pub announce_mute_changes: Option<bool>,
+    pub read_timestamp: Option<bool>,
+    pub timezone_offset_minutes: Option<i32>,
+    pub default_user_voice: Option<VoiceConfig>,
+    pub schedules: Vec<Schedule>,
+    pub read_attachment_names: Option<bool>,
+    /// Largest schedule id handed out so far, so removed ids are not reused.
+    pub last_schedule_id: Option<u32>,
+}
+
+/// Boolean settings shown in the server config menu.
+/// (key, label, default)
+pub const SERVER_CONFIG_TOGGLES: &[(&str, &str, bool)] = &[
+    ("allow_inline_speed", "インライン速度指定", false),
+    ("mention_hint", "メンション時に使い方を返信", false),
+    ("normalize_numbers", "数字を漢数字で読む", false),
+    ("read_bots", "Botのメッセージを読み上げ", false),
+    ("read_spoilers", "ネタバレを読み上げ", true),
+    ("read_first_line_only", "最初の行のみ読み上げ", false),
+    ("read_thread_names", "スレッド名を読み上げ", false),
+    ("chunk_long_messages", "長文を分割して読み上げ", false),
+    ("read_news", "お知らせを読み上げ", false),
+    ("news_first_sentence_only", "お知らせは最初の文のみ", false),
+    ("react_on_throttle", "読み上げ制限時にリアクション", false),
+    ("react_on_queue", "読み上げ待ちにリアクション", false),
+    ("post_join_embed", "自動参加時に案内を投稿", true),
+    ("read_stickers", "スタンプ・投票を読み上げ", false),
+    ("read_role_title", "ロール名を肩書きとして読み上げ", false),
+    ("allow_custom_join", "個人の参加メッセージを許可", false),
+    ("read_edits", "編集されたメッセージを読み上げ", false),
+    ("auto_speed", "待ち行列に応じて読み上げを速く", false),
+    (
+        "announce_mute_changes",
+        "ミュート・スピーカーミュートを読み上げ",
+        false,
+    ),
+    ("read_timestamp", "時刻を読み上げ", false),
+    ("read_attachment_names", "添付ファイル名を読み上げ", false),
+];
+
+/// Numeric settings shown in the server config menu.
+/// (key, label, min, max)
+pub const SERVER_CONFIG_NUMBERS: &[(&str, &str, i64, i64)] = &[
+    ("chunk_length", "長文を分割する文字数", 20, 1000),
+    ("max_reads_per_minute", "1分あたりの最大読み上げ数", 1, 120),
+    (
+        "announce_debounce_secs",
+        "入退室読み上げの間隔 (秒)",
+        0,
+        600,
+    ),
+    (
+        "timezone_offset_minutes",
+        "読み上げ時刻のUTCからの時差 (分)",
+        -720,
+        840,
+    ),
+    (
+        "daily_char_limit",
+        "1日あたりの最大読み上げ文字数",
+        100,
+        10000000,
+    ),
+    ("fade_ms", "フェードイン・アウトの長さ (ミリ秒)", 0, 1000),
+];
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            dictionary: Dictionary::new(),
+            autostart_channel_id: None,
+            allow_inline_speed: None,
+            mention_hint: None,
+            fade_ms: None,
+            normalize_numbers: None,
+            read_bots: None,
+            read_spoilers: None,
+            ssml_allowed_roles: Vec::new(),
+            read_first_line_only: None,
+            announce_voice: None,
+            read_thread_names: None,
+            chunk_long_messages: None,
+            chunk_length: None,
+            read_news: None,
+            news_first_sentence_only: None,
+            daily_char_limit: None,
+            autostart_text_channel_id: None,
+            max_reads_per_minute: None,
+            react_on_throttle: None,
+            react_on_queue: None,
+            post_join_embed: None,
+            read_stickers: None,
+            read_role_title: None,
+            announce_debounce_secs: None,
+            allow_custom_join: None,
+            read_edits: None,
+            ignored_text_channels: Vec::new(),
+            ignore_prefixes: Vec::new(),
+            auto_speed: None,
+            announce_mute_changes: None,
+            read_timestamp: None,
+            timezone_offset_minutes: None,
+            default_user_voice: None,
+            schedules: Vec::new(),
+            read_attachment_names: None,
+            last_schedule_id: None,
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Current values of the boolean settings.
+    /// Returns (key, label, enabled).
+    pub fn toggles(&self) -> Vec<(&'static str, &'static str, bool)> {
+        SERVER_CONFIG_TOGGLES
+            .iter()
+            .map(|(key, label, default)| {
+                let value = match *key {
+                    "allow_inline_speed" => self.allow_inline_speed,
+                    "mention_hint" => self.mention_hint,
+                    "normalize_numbers" => self.normalize_numbers,
+                    "read_bots" => self.read_bots,
+                    "read_spoilers" => self.read_spoilers,
+                    "read_first_line_only" => self.read_first_line_only,
+                    "read_thread_names" => self.read_thread_names,
+                    "chunk_long_messages" => self.chunk_long_messages,
+                    "read_news" => self.read_news,
+                    "news_first_sentence_only" => self.news_first_sentence_only,
+                    "react_on_throttle" => self.react_on_throttle,
+                    "react_on_queue" => self.react_on_queue,
+                    "post_join_embed" => self.post_join_embed,
+                    "read_stickers" => self.read_stickers,
+                    "read_role_title" => self.read_role_title,
+                    "allow_custom_join" => self.allow_custom_join,
+                    "read_edits" => self.read_edits,
+                    "auto_speed" => self.auto_speed,
+                    "announce_mute_changes" => self.announce_mute_changes,
+                    "read_timestamp" => self.read_timestamp,
+                    "read_attachment_names" => self.read_attachment_names,
+                    _ => None,
+                };
+                (*key, *label, value.unwrap_or(*default))
+            })
+            .collect()
+    }
+
+    /// Current value of a numeric setting.
+    /// Returns None when the setting is unset or the key is unknown.
+    pub fn number(&self, key: &str) -> Option<i64> {
+        match key {
+            "chunk_length" => self.chunk_length.map(|value| value as i64),
+            "max_reads_per_minute" => self.max_reads_per_minute.map(|value| value as i64),
+            "announce_debounce_secs" => self.announce_debounce_secs.map(|value| value as i64),
+            "timezone_offset_minutes" => self.timezone_offset_minutes.map(|value| value as i64),
+            "daily_char_limit" => self.daily_char_limit.map(|value| value as i64),
+            "fade_ms" => self.fade_ms.map(|value| value as i64),
+            _ => None,
+        }
+    }
+
+    /// Set a numeric setting; None resets it to the default.
+    /// Returns false when the key is unknown or the value is out of range.
+    pub fn set_number(&mut self, key: &str, value: Option<i64>) -> bool {
+        let (min, max) = match SERVER_CONFIG_NUMBERS.iter().find(|(k, _, _, _)| *k == key) {
+            Some((_, _, min, max)) => (*min, *max),
+            None => return false,
+        };
+        if value.is_some_and(|value| value < min || value > max) {
+            return false;
+        }
+        match key {
+            "chunk_length" => self.chunk_length = value.map(|value| value as usize),
+            "max_reads_per_minute" => self.max_reads_per_minute = value.map(|value| value as u32),
+            "announce_debounce_secs" => {
+                self.announce_debounce_secs = value.map(|value| value as u64)
+            }
+            "timezone_offset_minutes" => {
+                self.timezone_offset_minutes = value.map(|value| value as i32)
+            }
+            "daily_char_limit" => self.daily_char_limit = value.map(|value| value as u64),
+            "fade_ms" => self.fade_ms = value.map(|value| value as u64),
+            _ => return false,
+        }
+        true
+    }
+
+    /// Flip a boolean setting and return the new value.
+    /// Returns None if the key is unknown.
+    pub fn toggle(&mut self, key: &str) -> Option<bool> {
+        let default = SERVER_CONFIG_TOGGLES
+            .iter()
+            .find(|(k, _, _)| *k == key)
+            .map(|(_, _, default)| *default)?;
+        let field = match key {
+            "allow_inline_speed" => &mut self.allow_inline_speed,
+            "mention_hint" => &mut self.mention_hint,
+            "normalize_numbers" => &mut self.normalize_numbers,
+            "read_bots" => &mut self.read_bots,
+            "read_spoilers" => &mut self.read_spoilers,
+            "read_first_line_only" => &mut self.read_first_line_only,
+            "read_thread_names" => &mut self.read_thread_names,
+            "chunk_long_messages" => &mut self.chunk_long_messages,
+            "read_news" => &mut self.read_news,
+            "news_first_sentence_only" => &mut self.news_first_sentence_only,
+            "react_on_throttle" => &mut self.react_on_throttle,
+            "react_on_queue" => &mut self.react_on_queue,
+            "post_join_embed" => &mut self.post_join_embed,
+            "read_stickers" => &mut self.read_stickers,
+            "read_role_title" => &mut self.read_role_title,
+            "allow_custom_join" => &mut self.allow_custom_join,
+            "read_edits" => &mut self.read_edits,
+            "auto_speed" => &mut self.auto_speed,
+            "announce_mute_changes" => &mut self.announce_mute_changes,
+            "read_timestamp" => &mut self.read_timestamp,
+            "read_attachment_names" => &mut self.read_attachment_names,
+            _ => return None,
+        };
+        let value = !field.unwrap_or(default);
+        *field = Some(value);
+        Some(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_number_checks_range() {
+        let mut config = ServerConfig::default();
+        assert!(config.set_number("chunk_length", Some(200)));
+        assert_eq!(config.chunk_length, Some(200));
+        assert_eq!(config.number("chunk_length"), Some(200));
+
+        assert!(!config.set_number("chunk_length", Some(5)));
+        assert!(!config.set_number("chunk_length", Some(100_000)));
+        assert_eq!(config.chunk_length, Some(200));
+
+        assert!(config.set_number("chunk_length", None));
+        assert_eq!(config.chunk_length, None);
+    }
+
+    #[test]
+    fn every_listed_number_can_be_set() {
+        let mut config = ServerConfig::default();
+        for (key, _, min, max) in SERVER_CONFIG_NUMBERS {
+            assert!(config.set_number(key, Some(*min)), "{}", key);
+            assert_eq!(config.number(key), Some(*min), "{}", key);
+            assert!(config.set_number(key, Some(*max)), "{}", key);
+            assert_eq!(config.number(key), Some(*max), "{}", key);
+            assert!(config.set_number(key, None), "{}", key);
+            assert_eq!(config.number(key), None, "{}", key);
+        }
+    }
+
+    #[test]
+    fn set_number_rejects_unknown_keys() {
+        let mut config = ServerConfig::default();
+        assert!(!config.set_number("unknown", Some(1)));
+        assert_eq!(config.number("unknown"), None);
+    }
 }
@@ -4,9 +4,49 @@ use crate::tts::{
     gcp_tts::structs::voice_selection_params::VoiceSelectionParams, tts_type::TTSType,
 };
 
+/// GCP speaking rate used when the user has not set one.
+pub const DEFAULT_SPEAKING_RATE: f32 = 1.2;
+/// GCP pitch used when the user has not set one.
+pub const DEFAULT_PITCH: f32 = 1.0;
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct UserConfig {
     pub tts_type: Option<TTSType>,
     pub gcp_tts_voice: Option<VoiceSelectionParams>,
     pub voicevox_speaker: Option<i64>,
+    pub opt_out: Option<bool>,
+    pub read_own_name: Option<bool>,
+    pub speaking_rate: Option<f32>,
+    pub pitch: Option<f32>,
+    pub azure_voice: Option<String>,
+    pub custom_join_message: Option<String>,
+    pub gcp_effects_profile: Option<String>,
+}
+
+impl UserConfig {
+    /// Flip the opt-out flag and return the new value.
+    pub fn toggle_opt_out(&mut self) -> bool {
+        let opt_out = !self.opt_out.unwrap_or(false);
+        self.opt_out = Some(opt_out);
+        opt_out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::database::Database;
+
+    #[test]
+    fn opt_out_survives_a_round_trip() {
+        let mut config = Database::default_user_config();
+        assert!(config.toggle_opt_out());
+
+        let stored = serde_json::to_string(&config).unwrap();
+        let mut loaded = serde_json::from_str::<UserConfig>(&stored).unwrap();
+        assert_eq!(loaded.opt_out, Some(true));
+
+        assert!(!loaded.toggle_opt_out());
+        assert_eq!(loaded.opt_out, Some(false));
+    }
 }
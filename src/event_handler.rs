@@ -1,22 +1,62 @@
 use crate::{
     commands::{
-        config::config_command, setup::setup_command, skip::skip_command, stop::stop_command,
+        broadcast::broadcast_command,
+        channel::{addchannel_command, removechannel_command},
+        cleanup::cleanup_command,
+        config::{config_command, describe_user_config, engine_available},
+        diag::diag_command,
+        feedback::feedback_command,
+        gcp::{describe_token, gcp_command},
+        ignoreme::ignoreme_command,
+        join::join_command,
+        mute::{mute_command, unmute_command},
+        ping::ping_command,
+        resetconfig::resetconfig_command,
+        schedule::schedule_command,
+        serverconfig::serverconfig_command,
+        setspeaker::setspeaker_command,
+        setup::setup_command,
+        setvoice::setvoice_command,
+        skip::skip_command,
+        skipall::skipall_command,
+        stop::stop_command,
+        utils::{error_response, is_owner, is_text_channel, respond_error},
+    },
+    data::{ConfigData, DatabaseClientData, TTSClientData, TTSData},
+    database::{
+        dictionary::{
+            build_rule_regex, parse_dictionary_txt, plan_import, Dictionary, Rule,
+            MAX_IMPORT_RULES, MAX_IMPORT_RULE_CHARS,
+        },
+        server_config::{ServerConfig, VoiceConfig, SERVER_CONFIG_NUMBERS, SERVER_CONFIG_TOGGLES},
+        user_config::{DEFAULT_PITCH, DEFAULT_SPEAKING_RATE},
     },
-    data::DatabaseClientData,
-    database::dictionary::Rule,
     events,
-    tts::tts_type::TTSType,
+    tts::{
+        azure::azure::{is_valid_voice_name, DEFAULT_AZURE_VOICE, MAX_AZURE_VOICE_LENGTH},
+        gcp_tts::structs::audio_config::next_effects_profile,
+        instance::TTSInstance,
+        message::{join_announcement, AnnounceMessage, MAX_CUSTOM_JOIN_LENGTH},
+        tts_type::TTSType,
+        voicevox::voicevox::find_style,
+    },
+    utils::truncate_chars,
 };
 use serenity::{
     async_trait,
+    builder::{
+        CreateActionRow, CreateAllowedMentions, CreateButton, CreateEmbed, CreateEmbedFooter,
+        CreateInputText, CreateInteractionResponse, CreateInteractionResponseMessage, CreateModal,
+        CreateSelectMenu, CreateSelectMenuKind, CreateSelectMenuOption,
+    },
     client::{Context, EventHandler},
     model::{
         channel::Message,
+        event::MessageUpdateEvent,
         gateway::Ready,
         prelude::{
-            component::{ActionRowComponent, ButtonStyle, InputTextStyle},
-            interaction::{Interaction, InteractionResponseType, MessageFlags},
-            ChannelType,
+            ActionRowComponent, ButtonStyle, ChannelId, ChannelType, ComponentInteractionDataKind,
+            InputTextStyle, Interaction, Member,
         },
         voice::VoiceState,
     },
@@ -24,28 +64,568 @@ use serenity::{
 
 pub struct Handler;
 
+/// Components of the server settings menu.
+fn server_menu(config: &ServerConfig) -> Vec<CreateActionRow> {
+    let toggles = config
+        .toggles()
+        .into_iter()
+        .map(|(key, label, enabled)| {
+            CreateSelectMenuOption::new(label, key).description(if enabled {
+                "有効"
+            } else {
+                "無効"
+            })
+        })
+        .collect();
+
+    vec![
+        CreateActionRow::Buttons(vec![
+            CreateButton::new("TTS_CONFIG_SERVER_ADD_DICTIONARY_BUTTON")
+                .label("辞書を追加")
+                .style(ButtonStyle::Primary),
+            CreateButton::new("TTS_CONFIG_SERVER_REMOVE_DICTIONARY_BUTTON")
+                .label("辞書を削除")
+                .style(ButtonStyle::Danger),
+            CreateButton::new("TTS_CONFIG_SERVER_SHOW_DICTIONARY_BUTTON")
+                .label("辞書一覧")
+                .style(ButtonStyle::Primary),
+            CreateButton::new("TTS_CONFIG_SERVER_SET_AUTOSTART_CHANNEL")
+                .label("自動参加チャンネル")
+                .style(ButtonStyle::Primary),
+            CreateButton::new("TTS_CONFIG_SERVER_SET_SSML_ROLES")
+                .label("SSML許可ロール")
+                .style(ButtonStyle::Primary),
+        ]),
+        CreateActionRow::SelectMenu(
+            CreateSelectMenu::new(
+                "TTS_CONFIG_SERVER_ANNOUNCE_VOICE",
+                CreateSelectMenuKind::String {
+                    options: vec![
+                        CreateSelectMenuOption::new("標準 (Google TTS)", "ANNOUNCE_VOICE_DEFAULT")
+                            .default_selection(config.announce_voice.is_none()),
+                        CreateSelectMenuOption::new("自分の音声設定を使用", "ANNOUNCE_VOICE_MINE")
+                            .default_selection(config.announce_voice.is_some()),
+                    ],
+                },
+            )
+            .placeholder("アナウンス音声を選択"),
+        ),
+        CreateActionRow::SelectMenu(
+            CreateSelectMenu::new(
+                "TTS_CONFIG_SERVER_TOGGLE",
+                CreateSelectMenuKind::String { options: toggles },
+            )
+            .placeholder("切り替える設定を選択"),
+        ),
+        CreateActionRow::Buttons(vec![
+            CreateButton::new("TTS_CONFIG_SERVER_CLEAR_DICTIONARY_BUTTON")
+                .label("辞書を全削除")
+                .style(ButtonStyle::Danger),
+            CreateButton::new("TTS_CONFIG_SERVER_IMPORT_DICTIONARY_BUTTON")
+                .label("辞書をインポート")
+                .style(ButtonStyle::Primary),
+            CreateButton::new("TTS_CONFIG_SERVER_SET_AUTOSTART_TEXT_CHANNEL")
+                .label("自動参加テキストチャンネル")
+                .style(ButtonStyle::Primary),
+            CreateButton::new("TTS_CONFIG_SERVER_PREVIEW_ANNOUNCE")
+                .label("参加読み上げを試聴")
+                .style(ButtonStyle::Secondary),
+            CreateButton::new("TTS_CONFIG_SERVER_SET_IGNORED_CHANNELS")
+                .label("読み上げ除外チャンネル")
+                .style(ButtonStyle::Primary),
+        ]),
+        CreateActionRow::Buttons(vec![
+            CreateButton::new("TTS_CONFIG_SERVER_IGNORE_PREFIXES_BUTTON")
+                .label("無視する接頭辞")
+                .style(ButtonStyle::Primary),
+            CreateButton::new("TTS_CONFIG_SERVER_DEFAULT_USER_VOICE_BUTTON")
+                .label("新規ユーザーの音声")
+                .style(ButtonStyle::Primary),
+            CreateButton::new("TTS_CONFIG_SERVER_NUMBERS_BUTTON")
+                .label("数値設定")
+                .style(ButtonStyle::Primary),
+            CreateButton::new("TTS_CONFIG_SERVER_RESET_BUTTON")
+                .label("初期化")
+                .style(ButtonStyle::Danger),
+        ]),
+    ]
+}
+
+/// Shown when a member without the Manage Server permission presses a destructive button.
+const MANAGE_GUILD_REQUIRED: &str = "この操作には「サーバー管理」権限が必要です";
+
+/// Whether the member who pressed a component may manage the server.
+/// Interaction members carry their resolved permissions.
+fn can_manage_guild(member: Option<&Member>) -> bool {
+    member
+        .and_then(|member| member.permissions)
+        .is_some_and(|permissions| permissions.manage_guild())
+}
+
+/// Selected values of a select menu as strings, whatever the menu kind.
+fn selected_values(kind: &ComponentInteractionDataKind) -> Vec<String> {
+    match kind {
+        ComponentInteractionDataKind::StringSelect { values } => values.clone(),
+        ComponentInteractionDataKind::RoleSelect { values } => {
+            values.iter().map(|id| id.get().to_string()).collect()
+        }
+        ComponentInteractionDataKind::ChannelSelect { values } => {
+            values.iter().map(|id| id.get().to_string()).collect()
+        }
+        ComponentInteractionDataKind::UserSelect { values } => {
+            values.iter().map(|id| id.get().to_string()).collect()
+        }
+        _ => vec![],
+    }
+}
+
+/// Name used in the announcement preview.
+const ANNOUNCE_PREVIEW_NAME: &str = "サンプル";
+
+/// Join announcement read by the preview button.
+fn announce_preview_text() -> String {
+    join_announcement(ANNOUNCE_PREVIEW_NAME)
+}
+
+/// Reply to the preview button, with the rendered text so it is shown even when nothing is played.
+fn announce_preview_reply(played: bool, text: &str) -> String {
+    if played {
+        format!("試聴を再生しました\n{}", text)
+    } else {
+        format!("読み上げ中ではないため再生できません\n{}", text)
+    }
+}
+
+/// Maximum number of fields in a Discord embed.
+const EMBED_MAX_FIELDS: usize = 25;
+/// Total characters allowed in an embed, leaving room for the title and footer.
+const EMBED_MAX_CHARS: usize = 5500;
+
+/// Split dictionary rules into pages of embed fields (name, value)
+/// that fit within Discord's embed limits.
+fn dictionary_pages(rules: &[Rule]) -> Vec<Vec<(String, String)>> {
+    let mut pages = vec![];
+    let mut page: Vec<(String, String)> = vec![];
+    let mut page_chars = 0;
+    for rule in rules {
+        let name = truncate_chars(&rule.id, 100, "…");
+        let value = truncate_chars(&format!("{} -> {}", rule.rule, rule.to), 200, "…");
+        let chars = name.chars().count() + value.chars().count();
+        if page.len() >= EMBED_MAX_FIELDS || page_chars + chars > EMBED_MAX_CHARS {
+            pages.push(std::mem::take(&mut page));
+            page_chars = 0;
+        }
+        page.push((name, value));
+        page_chars += chars;
+    }
+    if !page.is_empty() {
+        pages.push(page);
+    }
+    pages
+}
+
+/// Parse a SET_AUTOSTART_TEXT_CHANNEL select value.
+/// Returns None for the clear option or an unknown value.
+fn parse_autostart_text_channel(value: &str) -> Option<u64> {
+    value
+        .strip_prefix("SET_AUTOSTART_TEXT_CHANNEL_")
+        .and_then(|id| id.parse::<u64>().ok())
+}
+
+/// Parse the ignore prefixes modal input, one prefix per line.
+fn parse_ignore_prefixes(input: &str) -> Vec<String> {
+    input
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty())
+        .map(|line| line.to_string())
+        .collect()
+}
+
 #[async_trait]
 impl EventHandler for Handler {
     async fn message(&self, ctx: Context, message: Message) {
         events::message_receive::message(ctx, message).await
     }
 
+    async fn message_update(
+        &self,
+        ctx: Context,
+        old_if_available: Option<Message>,
+        new: Option<Message>,
+        event: MessageUpdateEvent,
+    ) {
+        events::message_update::message_update(ctx, old_if_available, new, event).await
+    }
+
     async fn ready(&self, ctx: Context, ready: Ready) {
         events::ready::ready(ctx, ready).await
     }
 
     async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
-        if let Interaction::ApplicationCommand(command) = interaction.clone() {
+        if let Interaction::Command(command) = interaction.clone() {
             let name = &*command.data.name;
-            match name {
-                "setup" => setup_command(&ctx, &command).await.unwrap(),
-                "stop" => stop_command(&ctx, &command).await.unwrap(),
-                "config" => config_command(&ctx, &command).await.unwrap(),
-                "skip" => skip_command(&ctx, &command).await.unwrap(),
-                _ => {}
+            let content = {
+                let result = match name {
+                    "setup" => setup_command(&ctx, &command).await,
+                    "stop" => stop_command(&ctx, &command).await,
+                    "config" => config_command(&ctx, &command).await,
+                    "skip" => skip_command(&ctx, &command).await,
+                    "skipall" => skipall_command(&ctx, &command).await,
+                    "diag" => diag_command(&ctx, &command).await,
+                    "ignoreme" => ignoreme_command(&ctx, &command).await,
+                    "join" => join_command(&ctx, &command).await,
+                    "broadcast" => broadcast_command(&ctx, &command).await,
+                    "cleanup" => cleanup_command(&ctx, &command).await,
+                    "setspeaker" => setspeaker_command(&ctx, &command).await,
+                    "setvoice" => setvoice_command(&ctx, &command).await,
+                    "mute" => mute_command(&ctx, &command).await,
+                    "unmute" => unmute_command(&ctx, &command).await,
+                    "resetconfig" => resetconfig_command(&ctx, &command).await,
+                    "feedback" => feedback_command(&ctx, &command).await,
+                    "ping" => ping_command(&ctx, &command).await,
+                    "gcp" => gcp_command(&ctx, &command).await,
+                    "serverconfig" => serverconfig_command(&ctx, &command).await,
+                    "schedule" => schedule_command(&ctx, &command).await,
+                    "addchannel" => addchannel_command(&ctx, &command).await,
+                    "removechannel" => removechannel_command(&ctx, &command).await,
+                    _ => Ok(()),
+                };
+                result
+                    .err()
+                    .map(|err| error_response(&command, err.as_ref()))
+            };
+            if let Some(content) = content {
+                respond_error(&ctx, &command, &content).await;
             }
         }
-        if let Interaction::ModalSubmit(modal) = interaction.clone() {
+        if let Interaction::Modal(modal) = interaction.clone() {
+            if modal.data.custom_id == "TTS_CONFIG_CUSTOM_JOIN" {
+                let rows = modal.data.components.clone();
+                let message =
+                    if let ActionRowComponent::InputText(text) = rows[0].components[0].clone() {
+                        text.value.unwrap_or_default()
+                    } else {
+                        panic!("Cannot get join message");
+                    };
+                let message = truncate_chars(message.trim(), MAX_CUSTOM_JOIN_LENGTH, "");
+
+                {
+                    let data_read = ctx.data.read().await;
+                    let database = data_read
+                        .get::<DatabaseClientData>()
+                        .expect("Cannot get DatabaseClientData")
+                        .clone();
+                    let mut database = database.lock().await;
+                    let mut config = database
+                        .get_user_config_or_guild_default(
+                            modal.user.id.get(),
+                            modal.guild_id.map(|guild_id| guild_id.get()),
+                        )
+                        .await
+                        .unwrap()
+                        .unwrap();
+                    config.custom_join_message = if message.is_empty() {
+                        None
+                    } else {
+                        Some(message.clone())
+                    };
+                    database
+                        .set_user_config(modal.user.id.get(), config)
+                        .await
+                        .unwrap();
+                }
+
+                modal
+                    .create_response(
+                        &ctx.http,
+                        CreateInteractionResponse::Message(
+                            CreateInteractionResponseMessage::new()
+                                .content(if message.is_empty() {
+                                    String::from("参加メッセージを解除しました")
+                                } else {
+                                    format!("参加メッセージを設定しました\n{}", message)
+                                })
+                                .ephemeral(true),
+                        ),
+                    )
+                    .await
+                    .unwrap();
+                return;
+            }
+
+            if modal.data.custom_id == "TTS_CONFIG_AZURE_VOICE" {
+                let rows = modal.data.components.clone();
+                let voice =
+                    if let ActionRowComponent::InputText(text) = rows[0].components[0].clone() {
+                        text.value.unwrap_or_default()
+                    } else {
+                        panic!("Cannot get Azure voice");
+                    };
+                let voice = voice.trim().to_string();
+
+                if !voice.is_empty() && !is_valid_voice_name(&voice) {
+                    modal
+                        .create_response(
+                            &ctx.http,
+                            CreateInteractionResponse::Message(
+                                CreateInteractionResponseMessage::new()
+                                    .content(format!(
+                                        "音声名の形式が正しくありません\n例: {}",
+                                        DEFAULT_AZURE_VOICE
+                                    ))
+                                    .ephemeral(true),
+                            ),
+                        )
+                        .await
+                        .unwrap();
+                    return;
+                }
+
+                {
+                    let data_read = ctx.data.read().await;
+                    let database = data_read
+                        .get::<DatabaseClientData>()
+                        .expect("Cannot get DatabaseClientData")
+                        .clone();
+                    let mut database = database.lock().await;
+                    let mut config = database
+                        .get_user_config_or_guild_default(
+                            modal.user.id.get(),
+                            modal.guild_id.map(|guild_id| guild_id.get()),
+                        )
+                        .await
+                        .unwrap()
+                        .unwrap();
+                    config.azure_voice = if voice.is_empty() {
+                        None
+                    } else {
+                        Some(voice.clone())
+                    };
+                    database
+                        .set_user_config(modal.user.id.get(), config)
+                        .await
+                        .unwrap();
+                }
+
+                modal
+                    .create_response(
+                        &ctx.http,
+                        CreateInteractionResponse::Message(
+                            CreateInteractionResponseMessage::new()
+                                .content(if voice.is_empty() {
+                                    format!(
+                                        "Azure音声を既定値 ({}) に戻しました",
+                                        DEFAULT_AZURE_VOICE
+                                    )
+                                } else {
+                                    format!("Azure音声を {} に設定しました", voice)
+                                })
+                                .ephemeral(true),
+                        ),
+                    )
+                    .await
+                    .unwrap();
+                return;
+            }
+
+            if let Some(key) = modal
+                .data
+                .custom_id
+                .strip_prefix("TTS_CONFIG_SERVER_NUMBER_FORM:")
+            {
+                let rows = modal.data.components.clone();
+                let input =
+                    if let ActionRowComponent::InputText(text) = rows[0].components[0].clone() {
+                        text.value.unwrap_or_default()
+                    } else {
+                        panic!("Cannot get number");
+                    };
+                let label = SERVER_CONFIG_NUMBERS
+                    .iter()
+                    .find(|(k, _, _, _)| *k == key)
+                    .map(|(_, label, _, _)| *label)
+                    .unwrap_or("不明な設定");
+
+                let value = match input.trim() {
+                    "" => Ok(None),
+                    input => input.parse::<i64>().map(Some),
+                };
+                let saved = match value {
+                    Ok(value) => {
+                        let data_read = ctx.data.read().await;
+                        let database = data_read
+                            .get::<DatabaseClientData>()
+                            .expect("Cannot get DatabaseClientData")
+                            .clone();
+                        let mut database = database.lock().await;
+                        let mut config = database
+                            .get_server_config_or_default(modal.guild_id.unwrap().get())
+                            .await
+                            .unwrap()
+                            .unwrap();
+                        let saved = config.set_number(key, value);
+                        if saved {
+                            database
+                                .set_server_config(modal.guild_id.unwrap().get(), config)
+                                .await
+                                .unwrap();
+                        }
+                        saved.then_some(value)
+                    }
+                    Err(_) => None,
+                };
+
+                modal
+                    .create_response(
+                        &ctx.http,
+                        CreateInteractionResponse::Message(
+                            CreateInteractionResponseMessage::new()
+                                .content(match saved {
+                                    Some(Some(value)) => {
+                                        format!("{}を{}に設定しました", label, value)
+                                    }
+                                    Some(None) => format!("{}を既定値に戻しました", label),
+                                    None => format!("{}に設定できない値です", label),
+                                })
+                                .ephemeral(true),
+                        ),
+                    )
+                    .await
+                    .unwrap();
+                return;
+            }
+
+            if modal.data.custom_id == "TTS_CONFIG_SERVER_IGNORE_PREFIXES" {
+                let rows = modal.data.components.clone();
+                let prefixes =
+                    if let ActionRowComponent::InputText(text) = rows[0].components[0].clone() {
+                        text.value.unwrap_or_default()
+                    } else {
+                        panic!("Cannot get ignore prefixes");
+                    };
+                let prefixes = parse_ignore_prefixes(&prefixes);
+
+                {
+                    let data_read = ctx.data.read().await;
+                    let database = data_read
+                        .get::<DatabaseClientData>()
+                        .expect("Cannot get DatabaseClientData")
+                        .clone();
+                    let mut database = database.lock().await;
+                    let mut config = database
+                        .get_server_config_or_default(modal.guild_id.unwrap().get())
+                        .await
+                        .unwrap()
+                        .unwrap();
+                    config.ignore_prefixes = prefixes.clone();
+                    database
+                        .set_server_config(modal.guild_id.unwrap().get(), config)
+                        .await
+                        .unwrap();
+                }
+
+                modal
+                    .create_response(
+                        &ctx.http,
+                        CreateInteractionResponse::Message(
+                            CreateInteractionResponseMessage::new()
+                                .content(if prefixes.is_empty() {
+                                    String::from("無視する接頭辞を解除しました")
+                                } else {
+                                    format!("無視する接頭辞を設定しました\n{}", prefixes.join(" "))
+                                })
+                                .ephemeral(true),
+                        ),
+                    )
+                    .await
+                    .unwrap();
+                return;
+            }
+
+            if modal.data.custom_id == "TTS_CONFIG_SERVER_IMPORT_DICTIONARY" {
+                let rows = modal.data.components.clone();
+                let contents =
+                    if let ActionRowComponent::InputText(text) = rows[0].components[0].clone() {
+                        text.value.unwrap_or_default()
+                    } else {
+                        panic!("Cannot get dictionary");
+                    };
+
+                let data_read = ctx.data.read().await;
+
+                let blacklist = {
+                    let config = data_read
+                        .get::<ConfigData>()
+                        .expect("Cannot get ConfigData")
+                        .clone();
+                    config.dictionary_blacklist.clone().unwrap_or_default()
+                };
+
+                let import = {
+                    let database = data_read
+                        .get::<DatabaseClientData>()
+                        .expect("Cannot get DatabaseClientData")
+                        .clone();
+                    let mut database = database.lock().await;
+                    let mut config = database
+                        .get_server_config_or_default(modal.guild_id.unwrap().get())
+                        .await
+                        .unwrap()
+                        .unwrap();
+                    let import = plan_import(
+                        &config.dictionary,
+                        parse_dictionary_txt(&contents),
+                        &blacklist,
+                    );
+                    config.dictionary.rules.extend(import.added.clone());
+                    database
+                        .set_server_config(modal.guild_id.unwrap().get(), config)
+                        .await
+                        .unwrap();
+                    import
+                };
+
+                let mut content = format!("{}件の辞書をインポートしました", import.added.len());
+                if import.blacklisted > 0 {
+                    content.push_str(&format!(
+                        "\n禁止パターンを含むため{}件をスキップしました",
+                        import.blacklisted
+                    ));
+                }
+                if import.invalid > 0 {
+                    content.push_str(&format!(
+                        "\n{}文字を超えるなど使用できないため{}件をスキップしました",
+                        MAX_IMPORT_RULE_CHARS, import.invalid
+                    ));
+                }
+                if import.duplicate > 0 {
+                    content.push_str(&format!(
+                        "\n登録済みのため{}件をスキップしました",
+                        import.duplicate
+                    ));
+                }
+                if import.over_limit > 0 {
+                    content.push_str(&format!(
+                        "\n一度にインポートできるのは{}件までのため{}件をスキップしました",
+                        MAX_IMPORT_RULES, import.over_limit
+                    ));
+                }
+
+                modal
+                    .create_response(
+                        &ctx.http,
+                        CreateInteractionResponse::UpdateMessage(
+                            CreateInteractionResponseMessage::new()
+                                .content(content)
+                                .components(vec![]),
+                        ),
+                    )
+                    .await
+                    .unwrap();
+                return;
+            }
+
             if modal.data.custom_id != "TTS_CONFIG_SERVER_ADD_DICTIONARY" {
                 return;
             }
@@ -53,23 +633,64 @@ impl EventHandler for Handler {
             let rows = modal.data.components.clone();
             let rule_name =
                 if let ActionRowComponent::InputText(text) = rows[0].components[0].clone() {
-                    text.value
+                    text.value.unwrap_or_default()
                 } else {
                     panic!("Cannot get rule name");
                 };
 
             let from = if let ActionRowComponent::InputText(text) = rows[1].components[0].clone() {
-                text.value
+                text.value.unwrap_or_default()
             } else {
                 panic!("Cannot get from");
             };
 
             let to = if let ActionRowComponent::InputText(text) = rows[2].components[0].clone() {
-                text.value
+                text.value.unwrap_or_default()
             } else {
                 panic!("Cannot get to");
             };
 
+            let data_read = ctx.data.read().await;
+
+            let blacklisted = {
+                let config = data_read
+                    .get::<ConfigData>()
+                    .expect("Cannot get ConfigData")
+                    .clone();
+                let blacklist = config.dictionary_blacklist.clone().unwrap_or_default();
+                Dictionary::find_blacklisted(&from, &blacklist).cloned()
+            };
+
+            if let Some(entry) = blacklisted {
+                modal
+                    .create_response(
+                        &ctx.http,
+                        CreateInteractionResponse::UpdateMessage(
+                            CreateInteractionResponseMessage::new().content(format!(
+                                "この変換元は使用できません\n禁止パターン: {}",
+                                entry
+                            )),
+                        ),
+                    )
+                    .await
+                    .unwrap();
+                return;
+            }
+
+            if let Err(err) = build_rule_regex(&from) {
+                modal
+                    .create_response(
+                        &ctx.http,
+                        CreateInteractionResponse::UpdateMessage(
+                            CreateInteractionResponseMessage::new()
+                                .content(format!("この変換元は使用できません\n{}", err)),
+                        ),
+                    )
+                    .await
+                    .unwrap();
+                return;
+            }
+
             let rule = Rule {
                 id: rule_name.clone(),
                 is_regex: true,
@@ -77,8 +698,6 @@ impl EventHandler for Handler {
                 to: to.clone(),
             };
 
-            let data_read = ctx.data.read().await;
-
             let mut config = {
                 let database = data_read
                     .get::<DatabaseClientData>()
@@ -86,7 +705,7 @@ impl EventHandler for Handler {
                     .clone();
                 let mut database = database.lock().await;
                 database
-                    .get_server_config_or_default(modal.guild_id.unwrap().0)
+                    .get_server_config_or_default(modal.guild_id.unwrap().get())
                     .await
                     .unwrap()
                     .unwrap()
@@ -100,127 +719,1060 @@ impl EventHandler for Handler {
                     .clone();
                 let mut database = database.lock().await;
                 database
-                    .set_server_config(modal.guild_id.unwrap().0, config)
+                    .set_server_config(modal.guild_id.unwrap().get(), config)
                     .await
                     .unwrap();
                 modal
-                    .create_interaction_response(&ctx.http, |f| {
-                        f.kind(InteractionResponseType::UpdateMessage)
-                            .interaction_response_data(|d| {
-                                d.custom_id("TTS_CONFIG_SERVER_ADD_DICTIONARY_RESPONSE")
+                    .create_response(
+                        &ctx.http,
+                        CreateInteractionResponse::UpdateMessage(
+                            CreateInteractionResponseMessage::new().content(format!(
+                                "辞書を追加しました\n名前: {}\n変換元: {}\n変換後: {}",
+                                rule_name, from, to
+                            )),
+                        ),
+                    )
+                    .await
+                    .unwrap();
+            }
+        }
+        if let Some(message_component) = interaction.message_component() {
+            let values = selected_values(&message_component.data.kind);
+            match &*message_component.data.custom_id {
+                "TTS_CONFIG_SERVER_REMOVE_DICTIONARY_MENU" => {
+                    let i = match values.first().and_then(|value| value.parse::<usize>().ok()) {
+                        Some(i) => i,
+                        None => return,
+                    };
+                    let data_read = ctx.data.read().await;
+
+                    let mut config = {
+                        let database = data_read
+                            .get::<DatabaseClientData>()
+                            .expect("Cannot get DatabaseClientData")
+                            .clone();
+                        let mut database = database.lock().await;
+                        database
+                            .get_server_config_or_default(message_component.guild_id.unwrap().get())
+                            .await
+                            .unwrap()
+                            .unwrap()
+                    };
+
+                    if i >= config.dictionary.rules.len() {
+                        return;
+                    }
+                    config.dictionary.rules.remove(i);
+                    {
+                        let database = data_read
+                            .get::<DatabaseClientData>()
+                            .expect("Cannot get DatabaseClientData")
+                            .clone();
+                        let mut database = database.lock().await;
+                        database
+                            .set_server_config(message_component.guild_id.unwrap().get(), config)
+                            .await
+                            .unwrap();
+                    }
+
+                    message_component
+                        .create_response(
+                            &ctx.http,
+                            CreateInteractionResponse::UpdateMessage(
+                                CreateInteractionResponseMessage::new()
+                                    .content("辞書を削除しました")
+                                    .components(vec![]),
+                            ),
+                        )
+                        .await
+                        .unwrap();
+                }
+                id if id == "TTS_CONFIG_SERVER_REMOVE_DICTIONARY_BUTTON"
+                    || id.starts_with("TTS_CONFIG_SERVER_REMOVE_DICTIONARY_PAGE_") =>
+                {
+                    let page = id
+                        .strip_prefix("TTS_CONFIG_SERVER_REMOVE_DICTIONARY_PAGE_")
+                        .and_then(|page| page.parse::<usize>().ok())
+                        .unwrap_or(0);
+
+                    let data_read = ctx.data.read().await;
+
+                    let config = {
+                        let database = data_read
+                            .get::<DatabaseClientData>()
+                            .expect("Cannot get DatabaseClientData")
+                            .clone();
+                        let mut database = database.lock().await;
+                        database
+                            .get_server_config_or_default(message_component.guild_id.unwrap().get())
+                            .await
+                            .unwrap()
+                            .unwrap()
+                    };
+
+                    let pages = dictionary_pages(&config.dictionary.rules);
+                    if pages.is_empty() {
+                        message_component
+                            .create_response(
+                                &ctx.http,
+                                CreateInteractionResponse::UpdateMessage(
+                                    CreateInteractionResponseMessage::new()
+                                        .content("削除できる辞書がありません")
+                                        .components(vec![]),
+                                ),
+                            )
+                            .await
+                            .unwrap();
+                        return;
+                    }
+                    let page = page.min(pages.len() - 1);
+                    let offset: usize = pages[..page].iter().map(|page| page.len()).sum();
+                    let fields = pages[page].clone();
+                    let page_count = pages.len();
+
+                    let options = fields
+                        .into_iter()
+                        .enumerate()
+                        .map(|(i, (name, value))| {
+                            CreateSelectMenuOption::new(name, (offset + i).to_string())
+                                .description(truncate_chars(&value, 100, "…"))
+                        })
+                        .collect();
+                    let mut components = vec![CreateActionRow::SelectMenu(
+                        CreateSelectMenu::new(
+                            "TTS_CONFIG_SERVER_REMOVE_DICTIONARY_MENU",
+                            CreateSelectMenuKind::String { options },
+                        )
+                        .max_values(1)
+                        .min_values(0),
+                    )];
+                    if page_count > 1 {
+                        components.push(CreateActionRow::Buttons(vec![
+                            CreateButton::new(format!(
+                                "TTS_CONFIG_SERVER_REMOVE_DICTIONARY_PAGE_{}",
+                                page.saturating_sub(1)
+                            ))
+                            .label("前へ")
+                            .style(ButtonStyle::Secondary)
+                            .disabled(page == 0),
+                            CreateButton::new(format!(
+                                "TTS_CONFIG_SERVER_REMOVE_DICTIONARY_PAGE_{}",
+                                page + 1
+                            ))
+                            .label("次へ")
+                            .style(ButtonStyle::Secondary)
+                            .disabled(page + 1 >= page_count),
+                        ]));
+                    }
+
+                    message_component
+                        .create_response(
+                            &ctx.http,
+                            CreateInteractionResponse::UpdateMessage(
+                                CreateInteractionResponseMessage::new()
+                                    .content(format!(
+                                        "削除する辞書内容を選択してください ({}/{})",
+                                        page + 1,
+                                        page_count
+                                    ))
+                                    .components(components),
+                            ),
+                        )
+                        .await
+                        .unwrap();
+                }
+                "TTS_CONFIG_SERVER_CLEAR_DICTIONARY_BUTTON" => {
+                    message_component
+                        .create_response(
+                            &ctx.http,
+                            CreateInteractionResponse::UpdateMessage(
+                                CreateInteractionResponseMessage::new()
+                                    .content("辞書を全て削除しますか？この操作は取り消せません．")
+                                    .components(vec![CreateActionRow::Buttons(vec![
+                                        CreateButton::new(
+                                            "TTS_CONFIG_SERVER_CLEAR_DICTIONARY_CONFIRM",
+                                        )
+                                        .label("全て削除")
+                                        .style(ButtonStyle::Danger),
+                                        CreateButton::new(
+                                            "TTS_CONFIG_SERVER_RESET_DICTIONARY_CONFIRM",
+                                        )
+                                        .label("初期辞書に戻す")
+                                        .style(ButtonStyle::Danger),
+                                        CreateButton::new(
+                                            "TTS_CONFIG_SERVER_CLEAR_DICTIONARY_CANCEL",
+                                        )
+                                        .label("キャンセル")
+                                        .style(ButtonStyle::Secondary),
+                                    ])]),
+                            ),
+                        )
+                        .await
+                        .unwrap();
+                }
+                "TTS_CONFIG_SERVER_CLEAR_DICTIONARY_CONFIRM"
+                | "TTS_CONFIG_SERVER_RESET_DICTIONARY_CONFIRM" => {
+                    if !can_manage_guild(message_component.member.as_ref()) {
+                        message_component
+                            .create_response(
+                                &ctx.http,
+                                CreateInteractionResponse::Message(
+                                    CreateInteractionResponseMessage::new()
+                                        .content(MANAGE_GUILD_REQUIRED)
+                                        .ephemeral(true),
+                                ),
+                            )
+                            .await
+                            .unwrap();
+                        return;
+                    }
+
+                    let reset = message_component.data.custom_id
+                        == "TTS_CONFIG_SERVER_RESET_DICTIONARY_CONFIRM";
+                    {
+                        let data_read = ctx.data.read().await;
+                        let database = data_read
+                            .get::<DatabaseClientData>()
+                            .expect("Cannot get DatabaseClientData")
+                            .clone();
+                        let mut database = database.lock().await;
+                        let mut config = database
+                            .get_server_config_or_default(message_component.guild_id.unwrap().get())
+                            .await
+                            .unwrap()
+                            .unwrap();
+                        if reset {
+                            config.dictionary.reset_to_defaults();
+                        } else {
+                            config.dictionary.clear();
+                        }
+                        database
+                            .set_server_config(message_component.guild_id.unwrap().get(), config)
+                            .await
+                            .unwrap();
+                    }
+
+                    message_component
+                        .create_response(
+                            &ctx.http,
+                            CreateInteractionResponse::UpdateMessage(
+                                CreateInteractionResponseMessage::new()
+                                    .content(if reset {
+                                        "辞書を初期状態に戻しました"
+                                    } else {
+                                        "辞書を全て削除しました"
+                                    })
+                                    .components(vec![]),
+                            ),
+                        )
+                        .await
+                        .unwrap();
+                }
+                "GCP_REFRESH_TOKEN" => {
+                    if !is_owner(&ctx, message_component.user.id).await {
+                        return;
+                    }
+
+                    let (result, status) = {
+                        let data_read = ctx.data.read().await;
+                        let tts_client = data_read
+                            .get::<TTSClientData>()
+                            .expect("Cannot get TTSClientData")
+                            .clone();
+                        let mut tts_client = tts_client.lock().await;
+                        let result = tts_client.0.refresh_token().await;
+                        (result, describe_token(&tts_client.0))
+                    };
+
+                    let content = match result {
+                        Ok(()) => String::from("トークンを更新しました"),
+                        Err(err) => {
+                            println!("Cannot refresh GCP token: {:?}", err);
+                            format!("トークンを更新できませんでした: {}", err)
+                        }
+                    };
+
+                    message_component
+                        .create_response(
+                            &ctx.http,
+                            CreateInteractionResponse::UpdateMessage(
+                                CreateInteractionResponseMessage::new()
+                                    .content(content)
+                                    .embed(
+                                        CreateEmbed::new().title("GCP 認証").description(status),
+                                    ),
+                            ),
+                        )
+                        .await
+                        .unwrap();
+                }
+                "TTS_CONFIG_SERVER_DEFAULT_USER_VOICE_BUTTON" => {
+                    message_component
+                        .create_response(
+                            &ctx.http,
+                            CreateInteractionResponse::UpdateMessage(
+                                CreateInteractionResponseMessage::new()
+                                    .content("このサーバーで初めて読み上げるユーザーの音声")
+                                    .components(vec![CreateActionRow::Buttons(vec![
+                                        CreateButton::new(
+                                            "TTS_CONFIG_SERVER_DEFAULT_USER_VOICE_MINE",
+                                        )
+                                        .label("自分の音声設定を使用")
+                                        .style(ButtonStyle::Primary),
+                                        CreateButton::new(
+                                            "TTS_CONFIG_SERVER_DEFAULT_USER_VOICE_CLEAR",
+                                        )
+                                        .label("標準に戻す")
+                                        .style(ButtonStyle::Secondary),
+                                    ])]),
+                            ),
+                        )
+                        .await
+                        .unwrap();
+                }
+                "TTS_CONFIG_SERVER_DEFAULT_USER_VOICE_MINE"
+                | "TTS_CONFIG_SERVER_DEFAULT_USER_VOICE_CLEAR" => {
+                    {
+                        let data_read = ctx.data.read().await;
+                        let database = data_read
+                            .get::<DatabaseClientData>()
+                            .expect("Cannot get DatabaseClientData")
+                            .clone();
+                        let mut database = database.lock().await;
+
+                        let default_user_voice = if message_component.data.custom_id
+                            == "TTS_CONFIG_SERVER_DEFAULT_USER_VOICE_MINE"
+                        {
+                            let user_config = database
+                                .get_user_config_or_guild_default(
+                                    message_component.user.id.get(),
+                                    message_component.guild_id.map(|guild_id| guild_id.get()),
+                                )
+                                .await
+                                .unwrap()
+                                .unwrap();
+                            Some(VoiceConfig {
+                                tts_type: user_config.tts_type.unwrap_or(TTSType::GCP),
+                                gcp_tts_voice: user_config.gcp_tts_voice,
+                                voicevox_speaker: user_config.voicevox_speaker,
+                                azure_voice: user_config.azure_voice,
+                            })
+                        } else {
+                            None
+                        };
+
+                        let mut config = database
+                            .get_server_config_or_default(message_component.guild_id.unwrap().get())
+                            .await
+                            .unwrap()
+                            .unwrap();
+                        config.default_user_voice = default_user_voice;
+                        database
+                            .set_server_config(message_component.guild_id.unwrap().get(), config)
+                            .await
+                            .unwrap();
+                    }
+
+                    message_component
+                        .create_response(
+                            &ctx.http,
+                            CreateInteractionResponse::UpdateMessage(
+                                CreateInteractionResponseMessage::new()
+                                    .content("新規ユーザーの音声を設定しました")
+                                    .components(vec![]),
+                            ),
+                        )
+                        .await
+                        .unwrap();
+                }
+                "TTS_CONFIG_SERVER_RESET_BUTTON" => {
+                    message_component
+                        .create_response(
+                            &ctx.http,
+                            CreateInteractionResponse::UpdateMessage(
+                                CreateInteractionResponseMessage::new()
+                                    .content(
+                                        "サーバー設定を全て初期化しますか？辞書も初期状態に戻ります．",
+                                    )
+                                    .components(vec![CreateActionRow::Buttons(vec![
+                                        CreateButton::new("TTS_CONFIG_SERVER_RESET_CONFIRM")
+                                            .label("初期化")
+                                            .style(ButtonStyle::Danger),
+                                        CreateButton::new("TTS_CONFIG_SERVER_RESET_CANCEL")
+                                            .label("キャンセル")
+                                            .style(ButtonStyle::Secondary),
+                                    ])]),
+                            ),
+                        )
+                        .await
+                        .unwrap();
+                }
+                "TTS_CONFIG_SERVER_RESET_CONFIRM" => {
+                    if !can_manage_guild(message_component.member.as_ref()) {
+                        message_component
+                            .create_response(
+                                &ctx.http,
+                                CreateInteractionResponse::Message(
+                                    CreateInteractionResponseMessage::new()
+                                        .content(MANAGE_GUILD_REQUIRED)
+                                        .ephemeral(true),
+                                ),
+                            )
+                            .await
+                            .unwrap();
+                        return;
+                    }
+
+                    let config = {
+                        let data_read = ctx.data.read().await;
+                        let database = data_read
+                            .get::<DatabaseClientData>()
+                            .expect("Cannot get DatabaseClientData")
+                            .clone();
+                        let mut database = database.lock().await;
+                        database
+                            .set_default_server_config(message_component.guild_id.unwrap().get())
+                            .await
+                            .unwrap();
+                        database.default_server_config.clone()
+                    };
+
+                    message_component
+                        .create_response(
+                            &ctx.http,
+                            CreateInteractionResponse::UpdateMessage(
+                                CreateInteractionResponseMessage::new()
+                                    .content(
+                                        "サーバー設定を初期化しました\n`/serverconfig undo` で元に戻せます",
+                                    )
+                                    .components(server_menu(&config)),
+                            ),
+                        )
+                        .await
+                        .unwrap();
+                }
+                "TTS_CONFIG_SERVER_CLEAR_DICTIONARY_CANCEL" | "TTS_CONFIG_SERVER_RESET_CANCEL" => {
+                    message_component
+                        .create_response(
+                            &ctx.http,
+                            CreateInteractionResponse::UpdateMessage(
+                                CreateInteractionResponseMessage::new()
+                                    .content("キャンセルしました")
+                                    .components(vec![]),
+                            ),
+                        )
+                        .await
+                        .unwrap();
+                }
+                id if id == "TTS_CONFIG_SERVER_SHOW_DICTIONARY_BUTTON"
+                    || id.starts_with("TTS_CONFIG_SERVER_SHOW_DICTIONARY_PAGE_") =>
+                {
+                    let page = id
+                        .strip_prefix("TTS_CONFIG_SERVER_SHOW_DICTIONARY_PAGE_")
+                        .and_then(|page| page.parse::<usize>().ok())
+                        .unwrap_or(0);
+
+                    let config = {
+                        let data_read = ctx.data.read().await;
+                        let database = data_read
+                            .get::<DatabaseClientData>()
+                            .expect("Cannot get DatabaseClientData")
+                            .clone();
+                        let mut database = database.lock().await;
+                        database
+                            .get_server_config_or_default(message_component.guild_id.unwrap().get())
+                            .await
+                            .unwrap()
+                            .unwrap()
+                    };
+
+                    let pages = dictionary_pages(&config.dictionary.rules);
+                    let page = page.min(pages.len().saturating_sub(1));
+                    let fields = pages.get(page).cloned().unwrap_or_default();
+                    let page_count = pages.len().max(1);
+
+                    let embed = CreateEmbed::new()
+                        .title("辞書一覧")
+                        .footer(CreateEmbedFooter::new(format!(
+                            "{}/{}",
+                            page + 1,
+                            page_count
+                        )))
+                        .fields(fields.into_iter().map(|(name, value)| (name, value, true)));
+                    let mut components = vec![];
+                    if page_count > 1 {
+                        components.push(CreateActionRow::Buttons(vec![
+                            CreateButton::new(format!(
+                                "TTS_CONFIG_SERVER_SHOW_DICTIONARY_PAGE_{}",
+                                page.saturating_sub(1)
+                            ))
+                            .label("前へ")
+                            .style(ButtonStyle::Secondary)
+                            .disabled(page == 0),
+                            CreateButton::new(format!(
+                                "TTS_CONFIG_SERVER_SHOW_DICTIONARY_PAGE_{}",
+                                page + 1
+                            ))
+                            .label("次へ")
+                            .style(ButtonStyle::Secondary)
+                            .disabled(page + 1 >= page_count),
+                        ]));
+                    }
+
+                    message_component
+                        .create_response(
+                            &ctx.http,
+                            CreateInteractionResponse::UpdateMessage(
+                                CreateInteractionResponseMessage::new()
+                                    .content("")
+                                    .embed(embed)
+                                    .components(components),
+                            ),
+                        )
+                        .await
+                        .unwrap();
+                }
+                "TTS_CONFIG_SERVER_ADD_DICTIONARY_BUTTON" => {
+                    message_component
+                        .create_response(
+                            &ctx.http,
+                            CreateInteractionResponse::Modal(
+                                CreateModal::new("TTS_CONFIG_SERVER_ADD_DICTIONARY", "辞書追加")
+                                    .components(vec![
+                                        CreateActionRow::InputText(
+                                            CreateInputText::new(
+                                                InputTextStyle::Short,
+                                                "Rule name",
+                                                "rule_name",
+                                            )
+                                            .required(true),
+                                        ),
+                                        CreateActionRow::InputText(
+                                            CreateInputText::new(
+                                                InputTextStyle::Paragraph,
+                                                "From",
+                                                "from",
+                                            )
+                                            .required(true),
+                                        ),
+                                        CreateActionRow::InputText(
+                                            CreateInputText::new(InputTextStyle::Short, "To", "to")
+                                                .required(true),
+                                        ),
+                                    ]),
+                            ),
+                        )
+                        .await
+                        .unwrap();
+                }
+                "TTS_CONFIG_AZURE_VOICE_BUTTON" => {
+                    message_component
+                        .create_response(
+                            &ctx.http,
+                            CreateInteractionResponse::Modal(
+                                CreateModal::new("TTS_CONFIG_AZURE_VOICE", "Azure音声").components(
+                                    vec![CreateActionRow::InputText(
+                                        CreateInputText::new(
+                                            InputTextStyle::Short,
+                                            format!("音声名 (空欄で {})", DEFAULT_AZURE_VOICE),
+                                            "azure_voice",
+                                        )
+                                        .placeholder(DEFAULT_AZURE_VOICE)
+                                        .max_length(MAX_AZURE_VOICE_LENGTH as u16)
+                                        .required(false),
+                                    )],
+                                ),
+                            ),
+                        )
+                        .await
+                        .unwrap();
+                }
+                "TTS_CONFIG_CUSTOM_JOIN_BUTTON" => {
+                    message_component
+                        .create_response(
+                            &ctx.http,
+                            CreateInteractionResponse::Modal(
+                                CreateModal::new("TTS_CONFIG_CUSTOM_JOIN", "参加メッセージ")
+                                    .components(vec![CreateActionRow::InputText(
+                                        CreateInputText::new(
+                                            InputTextStyle::Short,
+                                            "{name} が名前に置き換わります (空欄で解除)",
+                                            "join_message",
+                                        )
+                                        .max_length(MAX_CUSTOM_JOIN_LENGTH as u16)
+                                        .required(false),
+                                    )]),
+                            ),
+                        )
+                        .await
+                        .unwrap();
+                }
+                "TTS_CONFIG_SERVER_IGNORE_PREFIXES_BUTTON" => {
+                    let config = {
+                        let data_read = ctx.data.read().await;
+                        let database = data_read
+                            .get::<DatabaseClientData>()
+                            .expect("Cannot get DatabaseClientData")
+                            .clone();
+                        let mut database = database.lock().await;
+                        database
+                            .get_server_config_or_default(message_component.guild_id.unwrap().get())
+                            .await
+                            .unwrap()
+                            .unwrap()
+                    };
+
+                    message_component
+                        .create_response(
+                            &ctx.http,
+                            CreateInteractionResponse::Modal(
+                                CreateModal::new(
+                                    "TTS_CONFIG_SERVER_IGNORE_PREFIXES",
+                                    "無視する接頭辞",
+                                )
+                                .components(vec![
+                                    CreateActionRow::InputText(
+                                        CreateInputText::new(
+                                            InputTextStyle::Paragraph,
+                                            "1行に1つ (空欄で解除)",
+                                            "ignore_prefixes",
+                                        )
+                                        .value(config.ignore_prefixes.join("\n"))
+                                        .required(false),
+                                    ),
+                                ]),
+                            ),
+                        )
+                        .await
+                        .unwrap();
+                }
+                "TTS_CONFIG_SERVER_IMPORT_DICTIONARY_BUTTON" => {
+                    message_component
+                        .create_response(
+                            &ctx.http,
+                            CreateInteractionResponse::Modal(
+                                CreateModal::new(
+                                    "TTS_CONFIG_SERVER_IMPORT_DICTIONARY",
+                                    "辞書インポート",
+                                )
+                                .components(vec![
+                                    CreateActionRow::InputText(
+                                        CreateInputText::new(
+                                            InputTextStyle::Paragraph,
+                                            "1行に1つ 変換元=変換後 (#でコメント)",
+                                            "dictionary",
+                                        )
+                                        .required(true),
+                                    ),
+                                ]),
+                            ),
+                        )
+                        .await
+                        .unwrap();
+                }
+                "SET_AUTOSTART_CHANNEL" => {
+                    let autostart_channel_id = if values.is_empty() {
+                        None
+                    } else {
+                        let ch = values[0].strip_prefix("SET_AUTOSTART_CHANNEL_").unwrap();
+                        Some(ch.parse::<u64>().unwrap())
+                    };
+                    {
+                        let data_read = ctx.data.read().await;
+                        let database = data_read
+                            .get::<DatabaseClientData>()
+                            .expect("Cannot get DatabaseClientData")
+                            .clone();
+                        let mut database = database.lock().await;
+                        let mut config = database
+                            .get_server_config_or_default(message_component.guild_id.unwrap().get())
+                            .await
+                            .unwrap()
+                            .unwrap();
+                        config.autostart_channel_id = autostart_channel_id;
+                        database
+                            .set_server_config(message_component.guild_id.unwrap().get(), config)
+                            .await
+                            .unwrap();
+                    };
+
+                    message_component
+                        .create_response(
+                            &ctx.http,
+                            CreateInteractionResponse::UpdateMessage(
+                                CreateInteractionResponseMessage::new()
+                                    .content("自動参加チャンネルを設定しました。")
+                                    .components(vec![]),
+                            ),
+                        )
+                        .await
+                        .unwrap();
+                }
+                "SET_AUTOSTART_TEXT_CHANNEL" => {
+                    let autostart_text_channel_id = values
+                        .first()
+                        .and_then(|value| parse_autostart_text_channel(value));
+
+                    if let Some(channel_id) = autostart_text_channel_id {
+                        if !is_text_channel(&ctx, ChannelId::new(channel_id)).await {
+                            message_component
+                                .create_response(
+                                    &ctx.http,
+                                    CreateInteractionResponse::UpdateMessage(
+                                        CreateInteractionResponseMessage::new()
+                                            .content("このチャンネルは読み上げに使用できません。")
+                                            .components(vec![]),
+                                    ),
+                                )
+                                .await
+                                .unwrap();
+                            return;
+                        }
+                    }
+
+                    {
+                        let data_read = ctx.data.read().await;
+                        let database = data_read
+                            .get::<DatabaseClientData>()
+                            .expect("Cannot get DatabaseClientData")
+                            .clone();
+                        let mut database = database.lock().await;
+                        let mut config = database
+                            .get_server_config_or_default(message_component.guild_id.unwrap().get())
+                            .await
+                            .unwrap()
+                            .unwrap();
+                        config.autostart_text_channel_id = autostart_text_channel_id;
+                        database
+                            .set_server_config(message_component.guild_id.unwrap().get(), config)
+                            .await
+                            .unwrap();
+                    };
+
+                    message_component
+                        .create_response(
+                            &ctx.http,
+                            CreateInteractionResponse::UpdateMessage(
+                                CreateInteractionResponseMessage::new()
+                                    .content(if autostart_text_channel_id.is_some() {
+                                        "自動参加時の読み上げチャンネルを設定しました。"
+                                    } else {
+                                        "自動参加時の読み上げチャンネルを解除しました。"
+                                    })
+                                    .components(vec![]),
+                            ),
+                        )
+                        .await
+                        .unwrap();
+                }
+                "SET_SSML_ROLES" => {
+                    let roles: Vec<u64> = values
+                        .iter()
+                        .filter_map(|id| id.parse::<u64>().ok())
+                        .collect();
+                    {
+                        let data_read = ctx.data.read().await;
+                        let database = data_read
+                            .get::<DatabaseClientData>()
+                            .expect("Cannot get DatabaseClientData")
+                            .clone();
+                        let mut database = database.lock().await;
+                        let mut config = database
+                            .get_server_config_or_default(message_component.guild_id.unwrap().get())
+                            .await
+                            .unwrap()
+                            .unwrap();
+                        config.ssml_allowed_roles = roles;
+                        database
+                            .set_server_config(message_component.guild_id.unwrap().get(), config)
+                            .await
+                            .unwrap();
+                    };
+
+                    message_component
+                        .create_response(
+                            &ctx.http,
+                            CreateInteractionResponse::UpdateMessage(
+                                CreateInteractionResponseMessage::new()
+                                    .content("SSML許可ロールを設定しました。")
+                                    .components(vec![]),
+                            ),
+                        )
+                        .await
+                        .unwrap();
+                }
+                "TTS_CONFIG_SERVER_SET_SSML_ROLES" => {
+                    let config = {
+                        let data_read = ctx.data.read().await;
+                        let database = data_read
+                            .get::<DatabaseClientData>()
+                            .expect("Cannot get DatabaseClientData")
+                            .clone();
+                        let mut database = database.lock().await;
+                        database
+                            .get_server_config_or_default(message_component.guild_id.unwrap().get())
+                            .await
+                            .unwrap()
+                            .unwrap()
+                    };
+
+                    let current = if config.ssml_allowed_roles.is_empty() {
+                        String::from("なし")
+                    } else {
+                        config
+                            .ssml_allowed_roles
+                            .iter()
+                            .map(|id| format!("<@&{}>", id))
+                            .collect::<Vec<_>>()
+                            .join(" ")
+                    };
+
+                    message_component
+                        .create_response(
+                            &ctx.http,
+                            CreateInteractionResponse::UpdateMessage(
+                                CreateInteractionResponseMessage::new()
                                     .content(format!(
-                                        "辞書を追加しました\n名前: {}\n変換元: {}\n変換後: {}",
-                                        rule_name, from, to
+                                        "SSMLタグの使用を許可するロール\n現在: {}\n選択したロールで置き換えます。",
+                                        current
                                     ))
-                            })
-                    })
-                    .await
-                    .unwrap();
-            }
-        }
-        if let Some(message_component) = interaction.message_component() {
-            match &*message_component.data.custom_id {
-                "TTS_CONFIG_SERVER_REMOVE_DICTIONARY_MENU" => {
-                    let i = usize::from_str_radix(&message_component.data.values[0], 10).unwrap();
-                    let data_read = ctx.data.read().await;
+                                    .allowed_mentions(CreateAllowedMentions::new())
+                                    .components(vec![CreateActionRow::SelectMenu(
+                                        CreateSelectMenu::new(
+                                            "SET_SSML_ROLES",
+                                            CreateSelectMenuKind::Role {
+                                                default_roles: None,
+                                            },
+                                        )
+                                        .min_values(0)
+                                        .max_values(25),
+                                    )]),
+                            ),
+                        )
+                        .await
+                        .unwrap();
+                }
+                "TTS_CONFIG_SERVER_SET_AUTOSTART_CHANNEL" => {
+                    let config = {
+                        let data_read = ctx.data.read().await;
+                        let database = data_read
+                            .get::<DatabaseClientData>()
+                            .expect("Cannot get DatabaseClientData")
+                            .clone();
+                        let mut database = database.lock().await;
+                        database
+                            .get_server_config_or_default(message_component.guild_id.unwrap().get())
+                            .await
+                            .unwrap()
+                            .unwrap()
+                    };
 
-                    let mut config = {
+                    let autostart_channel_id = config.autostart_channel_id.unwrap_or(0);
+
+                    let channels = message_component
+                        .guild_id
+                        .unwrap()
+                        .channels(&ctx.http)
+                        .await
+                        .unwrap();
+
+                    // Create channel list
+                    let options = channels
+                        .into_iter()
+                        .filter(|(_, channel)| channel.kind == ChannelType::Voice)
+                        .map(|(id, channel)| {
+                            CreateSelectMenuOption::new(
+                                channel.name,
+                                format!("SET_AUTOSTART_CHANNEL_{}", id.get()),
+                            )
+                            .description(
+                                channel.topic.unwrap_or(String::from("No topic provided.")),
+                            )
+                            .default_selection(channel.id.get() == autostart_channel_id)
+                        })
+                        .collect();
+
+                    message_component
+                        .create_response(
+                            &ctx.http,
+                            CreateInteractionResponse::UpdateMessage(
+                                CreateInteractionResponseMessage::new()
+                                    .content("自動参加チャンネル設定")
+                                    .components(vec![CreateActionRow::SelectMenu(
+                                        CreateSelectMenu::new(
+                                            "SET_AUTOSTART_CHANNEL",
+                                            CreateSelectMenuKind::String { options },
+                                        )
+                                        .min_values(0)
+                                        .max_values(1)
+                                        .disabled(false),
+                                    )]),
+                            ),
+                        )
+                        .await
+                        .unwrap();
+                }
+                "TTS_CONFIG_SERVER_SET_AUTOSTART_TEXT_CHANNEL" => {
+                    let config = {
+                        let data_read = ctx.data.read().await;
                         let database = data_read
                             .get::<DatabaseClientData>()
                             .expect("Cannot get DatabaseClientData")
                             .clone();
                         let mut database = database.lock().await;
                         database
-                            .get_server_config_or_default(message_component.guild_id.unwrap().0)
+                            .get_server_config_or_default(message_component.guild_id.unwrap().get())
                             .await
                             .unwrap()
                             .unwrap()
                     };
 
-                    config.dictionary.rules.remove(i);
+                    let autostart_text_channel_id = config.autostart_text_channel_id.unwrap_or(0);
+
+                    let channels = message_component
+                        .guild_id
+                        .unwrap()
+                        .channels(&ctx.http)
+                        .await
+                        .unwrap();
+
+                    let mut options = vec![CreateSelectMenuOption::new(
+                        "解除",
+                        "SET_AUTOSTART_TEXT_CHANNEL_CLEAR",
+                    )
+                    .description("ボイスチャンネルのチャットを使用")];
+                    for (id, channel) in channels
+                        .into_iter()
+                        .filter(|(_, channel)| channel.kind == ChannelType::Text)
+                        .take(24)
+                    {
+                        options.push(
+                            CreateSelectMenuOption::new(
+                                channel.name,
+                                format!("SET_AUTOSTART_TEXT_CHANNEL_{}", id.get()),
+                            )
+                            .default_selection(id.get() == autostart_text_channel_id),
+                        );
+                    }
+
+                    message_component
+                        .create_response(
+                            &ctx.http,
+                            CreateInteractionResponse::UpdateMessage(
+                                CreateInteractionResponseMessage::new()
+                                    .content("自動参加時の読み上げチャンネル設定\n未設定の場合はボイスチャンネルのチャットを読み上げます")
+                                    .components(vec![CreateActionRow::SelectMenu(
+                                        CreateSelectMenu::new(
+                                            "SET_AUTOSTART_TEXT_CHANNEL",
+                                            CreateSelectMenuKind::String { options },
+                                        )
+                                        .min_values(1)
+                                        .max_values(1),
+                                    )]),
+                            ),
+                        )
+                        .await
+                        .unwrap();
+                }
+                "SET_IGNORED_CHANNELS" => {
+                    let channels: Vec<u64> = values
+                        .iter()
+                        .filter_map(|id| id.parse::<u64>().ok())
+                        .collect();
                     {
+                        let data_read = ctx.data.read().await;
                         let database = data_read
                             .get::<DatabaseClientData>()
                             .expect("Cannot get DatabaseClientData")
                             .clone();
                         let mut database = database.lock().await;
+                        let mut config = database
+                            .get_server_config_or_default(message_component.guild_id.unwrap().get())
+                            .await
+                            .unwrap()
+                            .unwrap();
+                        config.ignored_text_channels = channels;
                         database
-                            .set_server_config(message_component.guild_id.unwrap().0, config)
+                            .set_server_config(message_component.guild_id.unwrap().get(), config)
                             .await
                             .unwrap();
-                    }
+                    };
 
                     message_component
-                        .create_interaction_response(&ctx, |f| {
-                            f.kind(InteractionResponseType::UpdateMessage)
-                                .interaction_response_data(|d| {
-                                    d.custom_id("DICTIONARY_REMOVED")
-                                        .content("辞書を削除しました")
-                                        .components(|c| c)
-                                })
-                        })
+                        .create_response(
+                            &ctx.http,
+                            CreateInteractionResponse::UpdateMessage(
+                                CreateInteractionResponseMessage::new()
+                                    .content("読み上げ除外チャンネルを設定しました。")
+                                    .components(vec![]),
+                            ),
+                        )
                         .await
                         .unwrap();
                 }
-                "TTS_CONFIG_SERVER_REMOVE_DICTIONARY_BUTTON" => {
-                    let data_read = ctx.data.read().await;
-
+                "TTS_CONFIG_SERVER_SET_IGNORED_CHANNELS" => {
                     let config = {
+                        let data_read = ctx.data.read().await;
                         let database = data_read
                             .get::<DatabaseClientData>()
                             .expect("Cannot get DatabaseClientData")
                             .clone();
                         let mut database = database.lock().await;
                         database
-                            .get_server_config_or_default(message_component.guild_id.unwrap().0)
-                            .await
-                            .unwrap()
-                            .unwrap()
-                    };
-
-                    message_component
-                        .create_interaction_response(&ctx.http, |f| {
-                            f.kind(InteractionResponseType::UpdateMessage)
-                                .interaction_response_data(|d| {
-                                    d.custom_id("TTS_CONFIG_SERVER_REMOVE_DICTIONARY")
-                                        .content("削除する辞書内容を選択してください")
-                                        .components(|c| {
-                                            c.create_action_row(|a| {
-                                                a.create_select_menu(|s| {
-                                                    s.custom_id(
-                                                        "TTS_CONFIG_SERVER_REMOVE_DICTIONARY_MENU",
-                                                    )
-                                                    .options(|o| {
-                                                        let mut o = o;
-                                                        for (i, rule) in config
-                                                            .dictionary
-                                                            .rules
-                                                            .iter()
-                                                            .enumerate()
-                                                        {
-                                                            o = o.create_option(|c| {
-                                                                c.label(rule.id.clone())
-                                                                    .value(i)
-                                                                    .description(format!(
-                                                                        "{} -> {}",
-                                                                        rule.rule.clone(),
-                                                                        rule.to.clone()
-                                                                    ))
-                                                            });
-                                                        }
-                                                        o
-                                                    })
-                                                    .max_values(1)
-                                                    .min_values(0)
-                                                })
-                                            })
-                                        })
-                                })
-                        })
+                            .get_server_config_or_default(message_component.guild_id.unwrap().get())
+                            .await
+                            .unwrap()
+                            .unwrap()
+                    };
+
+                    let current = if config.ignored_text_channels.is_empty() {
+                        String::from("なし")
+                    } else {
+                        config
+                            .ignored_text_channels
+                            .iter()
+                            .map(|id| format!("<#{}>", id))
+                            .collect::<Vec<_>>()
+                            .join(" ")
+                    };
+
+                    message_component
+                        .create_response(
+                            &ctx.http,
+                            CreateInteractionResponse::UpdateMessage(
+                                CreateInteractionResponseMessage::new()
+                                    .content(format!(
+                                        "読み上げないチャンネル\n現在: {}\n選択したチャンネルで置き換えます。",
+                                        current
+                                    ))
+                                    .components(vec![CreateActionRow::SelectMenu(
+                                        CreateSelectMenu::new(
+                                            "SET_IGNORED_CHANNELS",
+                                            CreateSelectMenuKind::Channel {
+                                                channel_types: Some(vec![
+                                                    ChannelType::Text,
+                                                    ChannelType::News,
+                                                    ChannelType::Voice,
+                                                    ChannelType::Stage,
+                                                    ChannelType::PublicThread,
+                                                    ChannelType::PrivateThread,
+                                                    ChannelType::NewsThread,
+                                                ]),
+                                                default_channels: None,
+                                            },
+                                        )
+                                        .min_values(0)
+                                        .max_values(25),
+                                    )]),
+                            ),
+                        )
                         .await
                         .unwrap();
                 }
-                "TTS_CONFIG_SERVER_SHOW_DICTIONARY_BUTTON" => {
+                "TTS_CONFIG_RATE_DOWN"
+                | "TTS_CONFIG_RATE_UP"
+                | "TTS_CONFIG_PITCH_DOWN"
+                | "TTS_CONFIG_PITCH_UP" => {
                     let config = {
                         let data_read = ctx.data.read().await;
                         let database = data_read
@@ -228,80 +1780,196 @@ impl EventHandler for Handler {
                             .expect("Cannot get DatabaseClientData")
                             .clone();
                         let mut database = database.lock().await;
-                        database
-                            .get_server_config_or_default(message_component.guild_id.unwrap().0)
+                        let mut config = database
+                            .get_user_config_or_guild_default(
+                                message_component.user.id.get(),
+                                message_component.guild_id.map(|guild_id| guild_id.get()),
+                            )
                             .await
                             .unwrap()
-                            .unwrap()
+                            .unwrap();
+                        match &*message_component.data.custom_id {
+                            "TTS_CONFIG_RATE_DOWN" | "TTS_CONFIG_RATE_UP" => {
+                                let step = if message_component.data.custom_id.ends_with("UP") {
+                                    0.1
+                                } else {
+                                    -0.1
+                                };
+                                let rate = config.speaking_rate.unwrap_or(DEFAULT_SPEAKING_RATE);
+                                config.speaking_rate = Some((rate + step).clamp(0.25, 4.0));
+                            }
+                            _ => {
+                                let step = if message_component.data.custom_id.ends_with("UP") {
+                                    1.0
+                                } else {
+                                    -1.0
+                                };
+                                let pitch = config.pitch.unwrap_or(DEFAULT_PITCH);
+                                config.pitch = Some((pitch + step).clamp(-20.0, 20.0));
+                            }
+                        }
+                        database
+                            .set_user_config(message_component.user.id.get(), config.clone())
+                            .await
+                            .unwrap();
+                        config
                     };
 
                     message_component
-                        .create_interaction_response(&ctx.http, |f| {
-                            f.kind(InteractionResponseType::UpdateMessage)
-                                .interaction_response_data(|d| {
-                                    d.custom_id("DICTIONARY_LIST").content("").embed(|e| {
-                                        e.title("辞書一覧");
-                                        for rule in config.dictionary.rules {
-                                            e.field(
-                                                rule.id,
-                                                format!("{} -> {}", rule.rule, rule.to),
-                                                true,
-                                            );
-                                        }
-                                        e
-                                    })
-                                })
-                        })
+                        .create_response(
+                            &ctx.http,
+                            CreateInteractionResponse::UpdateMessage(
+                                CreateInteractionResponseMessage::new().embed(
+                                    CreateEmbed::new()
+                                        .title("現在の設定")
+                                        .description(describe_user_config(&config)),
+                                ),
+                            ),
+                        )
                         .await
                         .unwrap();
                 }
-                "TTS_CONFIG_SERVER_ADD_DICTIONARY_BUTTON" => {
+                "TTS_CONFIG_SERVER_PREVIEW_ANNOUNCE" => {
+                    let text = announce_preview_text();
+
+                    let storage_lock = {
+                        let data_read = ctx.data.read().await;
+                        data_read
+                            .get::<TTSData>()
+                            .expect("Cannot get TTSStorage")
+                            .clone()
+                    };
+
+                    let instance = storage_lock
+                        .read()
+                        .await
+                        .get(&message_component.guild_id.unwrap())
+                        .cloned();
+                    let played = match instance {
+                        Some(instance) => {
+                            let config = {
+                                let data_read = ctx.data.read().await;
+                                let database = data_read
+                                    .get::<DatabaseClientData>()
+                                    .expect("Cannot get DatabaseClientData")
+                                    .clone();
+                                let mut database = database.lock().await;
+                                database
+                                    .get_server_config_or_default(
+                                        message_component.guild_id.unwrap().get(),
+                                    )
+                                    .await
+                                    .unwrap()
+                                    .unwrap()
+                            };
+                            TTSInstance::read(
+                                &instance,
+                                AnnounceMessage {
+                                    message: text.clone(),
+                                },
+                                &ctx,
+                                &config,
+                            )
+                            .await;
+                            true
+                        }
+                        None => false,
+                    };
+
                     message_component
-                        .create_interaction_response(&ctx.http, |f| {
-                            f.kind(InteractionResponseType::Modal)
-                                .interaction_response_data(|d| {
-                                    d.custom_id("TTS_CONFIG_SERVER_ADD_DICTIONARY")
-                                        .title("辞書追加")
-                                        .components(|c| {
-                                            c.create_action_row(|a| {
-                                                a.create_input_text(|i| {
-                                                    i.style(InputTextStyle::Short)
-                                                        .label("Rule name")
-                                                        .custom_id("rule_name")
-                                                        .required(true)
-                                                })
-                                            })
-                                            .create_action_row(|a| {
-                                                a.create_input_text(|i| {
-                                                    i.style(InputTextStyle::Paragraph)
-                                                        .label("From")
-                                                        .custom_id("from")
-                                                        .required(true)
-                                                })
-                                            })
-                                            .create_action_row(|a| {
-                                                a.create_input_text(|i| {
-                                                    i.style(InputTextStyle::Short)
-                                                        .label("To")
-                                                        .custom_id("to")
-                                                        .required(true)
-                                                })
-                                            })
-                                        })
-                                })
-                        })
+                        .create_response(
+                            &ctx.http,
+                            CreateInteractionResponse::Message(
+                                CreateInteractionResponseMessage::new()
+                                    .content(announce_preview_reply(played, &text))
+                                    .ephemeral(true),
+                            ),
+                        )
                         .await
                         .unwrap();
                 }
-                "SET_AUTOSTART_CHANNEL" => {
-                    let autostart_channel_id = if message_component.data.values.len() == 0 {
-                        None
-                    } else {
-                        let ch = message_component.data.values[0]
-                            .strip_prefix("SET_AUTOSTART_CHANNEL_")
+                "TTS_CONFIG_EFFECTS_PROFILE" => {
+                    let profile = {
+                        let data_read = ctx.data.read().await;
+                        let database = data_read
+                            .get::<DatabaseClientData>()
+                            .expect("Cannot get DatabaseClientData")
+                            .clone();
+                        let mut database = database.lock().await;
+                        let mut config = database
+                            .get_user_config_or_guild_default(
+                                message_component.user.id.get(),
+                                message_component.guild_id.map(|guild_id| guild_id.get()),
+                            )
+                            .await
+                            .unwrap()
+                            .unwrap();
+                        config.gcp_effects_profile =
+                            next_effects_profile(config.gcp_effects_profile.as_deref());
+                        database
+                            .set_user_config(message_component.user.id.get(), config.clone())
+                            .await
+                            .unwrap();
+                        config.gcp_effects_profile
+                    };
+
+                    message_component
+                        .create_response(
+                            &ctx.http,
+                            CreateInteractionResponse::Message(
+                                CreateInteractionResponseMessage::new()
+                                    .content(format!(
+                                        "Google TTSの音声プロファイルを {} に設定しました",
+                                        profile.as_deref().unwrap_or("なし")
+                                    ))
+                                    .ephemeral(true),
+                            ),
+                        )
+                        .await
+                        .unwrap();
+                }
+                "TTS_CONFIG_TOGGLE_READ_OWN_NAME" => {
+                    let read_own_name = {
+                        let data_read = ctx.data.read().await;
+                        let database = data_read
+                            .get::<DatabaseClientData>()
+                            .expect("Cannot get DatabaseClientData")
+                            .clone();
+                        let mut database = database.lock().await;
+                        let mut config = database
+                            .get_user_config_or_guild_default(
+                                message_component.user.id.get(),
+                                message_component.guild_id.map(|guild_id| guild_id.get()),
+                            )
+                            .await
+                            .unwrap()
+                            .unwrap();
+                        let read_own_name = !config.read_own_name.unwrap_or(true);
+                        config.read_own_name = Some(read_own_name);
+                        database
+                            .set_user_config(message_component.user.id.get(), config)
+                            .await
                             .unwrap();
-                        Some(u64::from_str_radix(ch, 10).unwrap())
+                        read_own_name
                     };
+
+                    message_component
+                        .create_response(
+                            &ctx.http,
+                            CreateInteractionResponse::Message(
+                                CreateInteractionResponseMessage::new()
+                                    .content(if read_own_name {
+                                        "あなたの名前を読み上げるように設定しました"
+                                    } else {
+                                        "あなたの名前を読み上げないように設定しました"
+                                    })
+                                    .ephemeral(true),
+                            ),
+                        )
+                        .await
+                        .unwrap();
+                }
+                "TTS_CONFIG_SERVER_ANNOUNCE_VOICE" => {
                     {
                         let data_read = ctx.data.read().await;
                         let database = data_read
@@ -309,30 +1977,101 @@ impl EventHandler for Handler {
                             .expect("Cannot get DatabaseClientData")
                             .clone();
                         let mut database = database.lock().await;
+
+                        let announce_voice = if values[0] == "ANNOUNCE_VOICE_MINE" {
+                            let user_config = database
+                                .get_user_config_or_guild_default(
+                                    message_component.user.id.get(),
+                                    message_component.guild_id.map(|guild_id| guild_id.get()),
+                                )
+                                .await
+                                .unwrap()
+                                .unwrap();
+                            Some(VoiceConfig {
+                                tts_type: user_config.tts_type.unwrap_or(TTSType::GCP),
+                                gcp_tts_voice: user_config.gcp_tts_voice,
+                                voicevox_speaker: user_config.voicevox_speaker,
+                                azure_voice: user_config.azure_voice,
+                            })
+                        } else {
+                            None
+                        };
+
                         let mut config = database
-                            .get_server_config_or_default(message_component.guild_id.unwrap().0)
+                            .get_server_config_or_default(message_component.guild_id.unwrap().get())
                             .await
                             .unwrap()
                             .unwrap();
-                        config.autostart_channel_id = autostart_channel_id;
+                        config.announce_voice = announce_voice;
                         database
-                            .set_server_config(message_component.guild_id.unwrap().0, config)
+                            .set_server_config(message_component.guild_id.unwrap().get(), config)
+                            .await
+                            .unwrap();
+                    }
+
+                    message_component
+                        .create_response(
+                            &ctx.http,
+                            CreateInteractionResponse::UpdateMessage(
+                                CreateInteractionResponseMessage::new()
+                                    .content("アナウンス音声を設定しました")
+                                    .components(vec![]),
+                            ),
+                        )
+                        .await
+                        .unwrap();
+                }
+                "TTS_CONFIG_SERVER_TOGGLE" => {
+                    let key = values[0].clone();
+                    let data_read = ctx.data.read().await;
+
+                    let value = {
+                        let database = data_read
+                            .get::<DatabaseClientData>()
+                            .expect("Cannot get DatabaseClientData")
+                            .clone();
+                        let mut database = database.lock().await;
+                        let mut config = database
+                            .get_server_config_or_default(message_component.guild_id.unwrap().get())
                             .await
+                            .unwrap()
                             .unwrap();
+                        let value = config.toggle(&key);
+                        if value.is_some() {
+                            database
+                                .set_server_config(
+                                    message_component.guild_id.unwrap().get(),
+                                    config,
+                                )
+                                .await
+                                .unwrap();
+                        }
+                        value
                     };
 
+                    let label = SERVER_CONFIG_TOGGLES
+                        .iter()
+                        .find(|(k, _, _)| *k == key)
+                        .map(|(_, label, _)| *label)
+                        .unwrap_or("不明な設定");
+
                     message_component
-                        .create_interaction_response(&ctx.http, |c| {
-                            c.kind(InteractionResponseType::UpdateMessage)
-                                .interaction_response_data(|d| {
-                                    d.content("自動参加チャンネルを設定しました。")
-                                        .components(|f| f)
-                                })
-                        })
+                        .create_response(
+                            &ctx.http,
+                            CreateInteractionResponse::UpdateMessage(
+                                CreateInteractionResponseMessage::new()
+                                    .content(match value {
+                                        Some(true) => format!("{}を有効にしました", label),
+                                        Some(false) => format!("{}を無効にしました", label),
+                                        None => format!("{}は変更できません", label),
+                                    })
+                                    .components(vec![]),
+                            ),
+                        )
                         .await
                         .unwrap();
                 }
-                "TTS_CONFIG_SERVER_SET_AUTOSTART_CHANNEL" => {
+                "TTS_CONFIG_SERVER_NUMBERS_BUTTON" => {
                     let config = {
                         let data_read = ctx.data.read().await;
                         let database = data_read
@@ -341,110 +2080,117 @@ impl EventHandler for Handler {
                             .clone();
                         let mut database = database.lock().await;
                         database
-                            .get_server_config_or_default(message_component.guild_id.unwrap().0)
+                            .get_server_config_or_default(message_component.guild_id.unwrap().get())
                             .await
                             .unwrap()
                             .unwrap()
                     };
 
-                    let autostart_channel_id = config.autostart_channel_id.unwrap_or(0);
+                    let options = SERVER_CONFIG_NUMBERS
+                        .iter()
+                        .map(|(key, label, _, _)| {
+                            let current = match config.number(key) {
+                                Some(value) => format!("現在: {}", value),
+                                None => String::from("既定値"),
+                            };
+                            CreateSelectMenuOption::new(*label, *key).description(current)
+                        })
+                        .collect();
 
-                    let channels = message_component
-                        .guild_id
-                        .unwrap()
-                        .channels(&ctx.http)
+                    message_component
+                        .create_response(
+                            &ctx.http,
+                            CreateInteractionResponse::UpdateMessage(
+                                CreateInteractionResponseMessage::new()
+                                    .content("数値設定")
+                                    .components(vec![CreateActionRow::SelectMenu(
+                                        CreateSelectMenu::new(
+                                            "TTS_CONFIG_SERVER_NUMBER",
+                                            CreateSelectMenuKind::String { options },
+                                        )
+                                        .placeholder("変更する設定を選択"),
+                                    )]),
+                            ),
+                        )
                         .await
                         .unwrap();
+                }
+                "TTS_CONFIG_SERVER_NUMBER" => {
+                    let key = values[0].clone();
+                    let (label, min, max) =
+                        match SERVER_CONFIG_NUMBERS.iter().find(|(k, _, _, _)| *k == key) {
+                            Some((_, label, min, max)) => (*label, *min, *max),
+                            None => return,
+                        };
+                    let current = {
+                        let data_read = ctx.data.read().await;
+                        let database = data_read
+                            .get::<DatabaseClientData>()
+                            .expect("Cannot get DatabaseClientData")
+                            .clone();
+                        let mut database = database.lock().await;
+                        database
+                            .get_server_config_or_default(message_component.guild_id.unwrap().get())
+                            .await
+                            .unwrap()
+                            .unwrap()
+                            .number(&key)
+                    };
+
+                    let mut input = CreateInputText::new(
+                        InputTextStyle::Short,
+                        format!("{}〜{} (空欄で既定値)", min, max),
+                        "number",
+                    )
+                    .required(false);
+                    if let Some(current) = current {
+                        input = input.value(current.to_string());
+                    }
 
                     message_component
-                        .create_interaction_response(&ctx.http, |f| {
-                            f.kind(InteractionResponseType::UpdateMessage)
-                                .interaction_response_data(|d| {
-                                    d.custom_id("SET_AUTOSTART_FORM")
-                                        .content("自動参加チャンネル設定")
-                                        .components(|c| {
-                                            c.create_action_row(|a| {
-                                                a.create_select_menu(|m| {
-                                                    m.min_values(0)
-                                                        .max_values(1)
-                                                        .disabled(false)
-                                                        .custom_id("SET_AUTOSTART_CHANNEL")
-                                                        .options(|o| {
-                                                            // Create channel list
-                                                            for (id, channel) in channels {
-                                                                if channel.kind != ChannelType::Voice {
-                                                                    continue;
-                                                                }
-                                                                o.create_option(|co| {
-                                                                    co.label(channel.name)
-                                                                        .description(
-                                                                        channel
-                                                                            .topic
-                                                                            .unwrap_or(String::from(
-                                                                            "No topic provided.",
-                                                                        )),
-                                                                    )
-                                                                        .value(format!("SET_AUTOSTART_CHANNEL_{}", id.0))
-                                                                        .default_selection(channel.id.0 == autostart_channel_id)
-                                                                });
-                                                            }
-                                                            o
-                                                        })
-                                                })
-                                            })
-                                        })
-                                })
-                        })
+                        .create_response(
+                            &ctx.http,
+                            CreateInteractionResponse::Modal(
+                                CreateModal::new(
+                                    format!("TTS_CONFIG_SERVER_NUMBER_FORM:{}", key),
+                                    label,
+                                )
+                                .components(vec![CreateActionRow::InputText(input)]),
+                            ),
+                        )
                         .await
                         .unwrap();
                 }
                 "TTS_CONFIG_SERVER" => {
+                    let config = {
+                        let data_read = ctx.data.read().await;
+                        let database = data_read
+                            .get::<DatabaseClientData>()
+                            .expect("Cannot get DatabaseClientData")
+                            .clone();
+                        let mut database = database.lock().await;
+                        database
+                            .get_server_config_or_default(message_component.guild_id.unwrap().get())
+                            .await
+                            .unwrap()
+                            .unwrap()
+                    };
+
                     message_component
-                        .create_interaction_response(&ctx.http, |f| {
-                            f.kind(InteractionResponseType::UpdateMessage)
-                                .interaction_response_data(|d| {
-                                    d.content("サーバー設定")
-                                        .custom_id("TTS_CONFIG_SERVER")
-                                        .components(|c| {
-                                            c.create_action_row(|a| {
-                                                a.create_button(|b| {
-                                                    b.custom_id(
-                                                        "TTS_CONFIG_SERVER_ADD_DICTIONARY_BUTTON",
-                                                    )
-                                                    .label("辞書を追加")
-                                                    .style(ButtonStyle::Primary)
-                                                })
-                                                .create_button(|b| {
-                                                    b.custom_id(
-                                                        "TTS_CONFIG_SERVER_REMOVE_DICTIONARY_BUTTON",
-                                                    )
-                                                    .label("辞書を削除")
-                                                    .style(ButtonStyle::Danger)
-                                                })
-                                                .create_button(|b| {
-                                                    b.custom_id(
-                                                        "TTS_CONFIG_SERVER_SHOW_DICTIONARY_BUTTON",
-                                                    )
-                                                    .label("辞書一覧")
-                                                    .style(ButtonStyle::Primary)
-                                                })
-                                                .create_button(|b| {
-                                                    b.custom_id(
-                                                        "TTS_CONFIG_SERVER_SET_AUTOSTART_CHANNEL"
-                                                    )
-                                                    .label("自動参加チャンネル")
-                                                    .style(ButtonStyle::Primary)
-                                                })
-                                            })
-                                        })
-                                })
-                        })
+                        .create_response(
+                            &ctx.http,
+                            CreateInteractionResponse::UpdateMessage(
+                                CreateInteractionResponseMessage::new()
+                                    .content("サーバー設定")
+                                    .components(server_menu(&config)),
+                            ),
+                        )
                         .await
                         .unwrap();
                 }
                 _ => {}
             }
-            if let Some(v) = message_component.data.values.get(0) {
+            if let Some(v) = values.first() {
                 let data_read = ctx.data.read().await;
 
                 let mut config = {
@@ -454,7 +2200,10 @@ impl EventHandler for Handler {
                         .clone();
                     let mut database = database.lock().await;
                     database
-                        .get_user_config_or_default(message_component.user.id.0)
+                        .get_user_config_or_guild_default(
+                            message_component.user.id.get(),
+                            message_component.guild_id.map(|guild_id| guild_id.get()),
+                        )
                         .await
                         .unwrap()
                         .unwrap()
@@ -472,14 +2221,38 @@ impl EventHandler for Handler {
                         config.tts_type = Some(TTSType::VOICEVOX);
                         config_changed = true;
                     }
+                    "TTS_CONFIG_ENGINE_SELECTED_AZURE" => {
+                        let azure_configured = data_read
+                            .get::<TTSClientData>()
+                            .expect("Cannot get TTSClientData")
+                            .clone()
+                            .lock()
+                            .await
+                            .2
+                            .is_some();
+                        if !engine_available(&TTSType::Azure, azure_configured) {
+                            message_component
+                                .create_response(
+                                    &ctx.http,
+                                    CreateInteractionResponse::Message(
+                                        CreateInteractionResponseMessage::new()
+                                            .content("Azureは設定されていないため使用できません")
+                                            .ephemeral(true),
+                                    ),
+                                )
+                                .await
+                                .unwrap();
+                            return;
+                        }
+                        config.tts_type = Some(TTSType::Azure);
+                        config_changed = true;
+                    }
                     _ => {
                         if res.starts_with("TTS_CONFIG_VOICEVOX_SPEAKER_SELECTED_") {
                             config.voicevox_speaker = Some(
-                                i64::from_str_radix(
-                                    &res.replace("TTS_CONFIG_VOICEVOX_SPEAKER_SELECTED_", ""),
-                                    10,
-                                )
-                                .unwrap(),
+                                res.replace("TTS_CONFIG_VOICEVOX_SPEAKER_SELECTED_", "")
+                                    .parse::<i64>()
+                                    .unwrap(),
                             );
                             config_changed = true;
                             voicevox_changed = true;
@@ -494,24 +2267,53 @@ impl EventHandler for Handler {
                         .clone();
                     let mut database = database.lock().await;
                     database
-                        .set_user_config(message_component.user.id.0, config.clone())
+                        .set_user_config(message_component.user.id.get(), config.clone())
                         .await
                         .unwrap();
 
+                    let confirmation = if voicevox_changed {
+                        let voicevox = data_read
+                            .get::<TTSClientData>()
+                            .expect("Cannot get TTSClientData")
+                            .clone()
+                            .lock()
+                            .await
+                            .1
+                            .clone();
+                        let styles = voicevox.get_styles().await;
+                        match find_style(&styles, config.voicevox_speaker.unwrap_or(1)) {
+                            Some(name) => format!("{} に設定しました", name),
+                            None => String::from("設定しました"),
+                        }
+                    } else {
+                        String::from("設定しました")
+                    };
+
                     if voicevox_changed && config.tts_type.unwrap_or(TTSType::GCP) == TTSType::GCP {
-                        message_component.create_interaction_response(&ctx.http, |f| {
-                            f.interaction_response_data(|d| {
-                                d.content("設定しました\nこの音声を使うにはAPIをGoogleからVOICEVOXに変更する必要があります。")
-                                    .flags(MessageFlags::EPHEMERAL)
-                            })
-                        }).await.unwrap();
+                        message_component
+                            .create_response(
+                                &ctx.http,
+                                CreateInteractionResponse::Message(
+                                    CreateInteractionResponseMessage::new()
+                                        .content(format!(
+                                            "{}\nこの音声を使うにはAPIをGoogleからVOICEVOXに変更する必要があります。",
+                                            confirmation
+                                        ))
+                                        .ephemeral(true),
+                                ),
+                            )
+                            .await
+                            .unwrap();
                     } else {
                         message_component
-                            .create_interaction_response(&ctx.http, |f| {
-                                f.interaction_response_data(|d| {
-                                    d.content("設定しました").flags(MessageFlags::EPHEMERAL)
-                                })
-                            })
+                            .create_response(
+                                &ctx.http,
+                                CreateInteractionResponse::Message(
+                                    CreateInteractionResponseMessage::new()
+                                        .content(confirmation)
+                                        .ephemeral(true),
+                                ),
+                            )
                             .await
                             .unwrap();
                     }
@@ -524,3 +2326,95 @@ impl EventHandler for Handler {
         events::voice_state_update::voice_state_update(ctx, old, new).await
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(id: &str, from: &str, to: &str) -> Rule {
+        Rule {
+            id: id.to_string(),
+            is_regex: false,
+            rule: from.to_string(),
+            to: to.to_string(),
+        }
+    }
+
+    #[test]
+    fn dictionary_pages_hold_at_most_25_fields() {
+        let rules = (0..60)
+            .map(|i| rule(&i.to_string(), "a", "b"))
+            .collect::<Vec<_>>();
+        let pages = dictionary_pages(&rules);
+        assert_eq!(
+            pages.iter().map(|page| page.len()).collect::<Vec<_>>(),
+            vec![25, 25, 10]
+        );
+        assert_eq!(pages[2][9].0, "59");
+    }
+
+    #[test]
+    fn dictionary_pages_stay_within_the_character_limit() {
+        let long = "あ".repeat(300);
+        let rules = (0..24)
+            .map(|i| rule(&format!("{}{}", i, long), &long, &long))
+            .collect::<Vec<_>>();
+        let pages = dictionary_pages(&rules);
+        assert!(pages.len() > 1);
+        for page in &pages {
+            let chars = page
+                .iter()
+                .map(|(name, value)| name.chars().count() + value.chars().count())
+                .sum::<usize>();
+            assert!(chars <= EMBED_MAX_CHARS, "{}", chars);
+        }
+        assert_eq!(pages.iter().map(|page| page.len()).sum::<usize>(), 24);
+    }
+
+    #[test]
+    fn long_ids_and_patterns_are_truncated() {
+        let long = "あ".repeat(300);
+        let pages = dictionary_pages(&[rule(&long, &long, "い")]);
+        let (name, value) = &pages[0][0];
+        assert_eq!(name.chars().count(), 100);
+        assert!(name.ends_with('…'));
+        assert_eq!(value.chars().count(), 200);
+        assert!(value.ends_with('…'));
+
+        let pages = dictionary_pages(&[rule("w", "w", "わら")]);
+        assert_eq!(pages[0][0], (String::from("w"), String::from("w -> わら")));
+    }
+
+    #[test]
+    fn empty_dictionary_has_no_pages() {
+        assert!(dictionary_pages(&[]).is_empty());
+    }
+
+    #[test]
+    fn autostart_text_channel_is_parsed_from_the_select_value() {
+        assert_eq!(
+            parse_autostart_text_channel("SET_AUTOSTART_TEXT_CHANNEL_123"),
+            Some(123)
+        );
+        assert_eq!(
+            parse_autostart_text_channel("SET_AUTOSTART_TEXT_CHANNEL_CLEAR"),
+            None
+        );
+        assert_eq!(
+            parse_autostart_text_channel("SET_AUTOSTART_CHANNEL_123"),
+            None
+        );
+        assert_eq!(parse_autostart_text_channel("123"), None);
+    }
+
+    #[test]
+    fn preview_is_the_join_announcement_for_the_sample_name() {
+        let text = announce_preview_text();
+        assert_eq!(text, "サンプル さんが通話に参加しました");
+        assert_eq!(
+            announce_preview_reply(true, &text),
+            "試聴を再生しました\nサンプル さんが通話に参加しました"
+        );
+        assert!(announce_preview_reply(false, &text).ends_with(&text));
+    }
+}
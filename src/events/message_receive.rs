@@ -1,19 +1,67 @@
-use serenity::{model::prelude::Message, prelude::Context};
+use std::sync::Arc;
 
-use crate::data::TTSData;
+use async_trait::async_trait;
+use serenity::{
+    http::Http,
+    model::prelude::{ChannelId, Message, MessageId, UserId},
+    prelude::Context,
+};
+use songbird::{
+    events::{Event, EventContext, EventHandler},
+    TrackEvent,
+};
+
+use crate::{
+    data::{DatabaseClientData, TTSData},
+    database::{server_config::ServerConfig, user_config::UserConfig},
+    implement::message::is_news_message,
+    tts::{
+        instance::{get_call, TTSInstance},
+        message::{log_dropped_message, DropReason},
+    },
+};
 
 pub async fn message(ctx: Context, message: Message) {
-    if message.author.bot {
+    let bot_id = ctx.cache.current_user().id;
+    if message.author.id == bot_id {
         return;
     }
 
-    let guild_id = message.guild(&ctx.cache);
+    let guild_id = match message.guild_id {
+        Some(guild_id) => guild_id,
+        None => return,
+    };
 
-    if let None = guild_id {
-        return;
-    }
+    if !message.author.bot
+        && message.mentions_user_id(bot_id)
+        && is_bare_mention(&message.content, bot_id)
+    {
+        // Mentions are answered outside reading too, so do not create a config for the server.
+        let mention_hint = {
+            let data_read = ctx.data.read().await;
+            let database = data_read
+                .get::<DatabaseClientData>()
+                .expect("Cannot get DatabaseClientData")
+                .clone();
+            let mut database = database.lock().await;
+            database
+                .get_server_config(guild_id.get())
+                .await
+                .unwrap()
+                .and_then(|config| config.mention_hint)
+                .unwrap_or(false)
+        };
 
-    let guild_id = guild_id.unwrap().id;
+        if mention_hint {
+            let _ = message
+                .reply(
+                    &ctx.http,
+                    "`/setup` で読み上げを開始、`/stop` で終了、`/config` で設定を変更できます。",
+                )
+                .await;
+            return;
+        }
+    }
 
     let storage_lock = {
         let data_read = ctx.data.read().await;
@@ -23,22 +71,273 @@ pub async fn message(ctx: Context, message: Message) {
             .clone()
     };
 
-    {
-        let mut storage = storage_lock.write().await;
-        if !storage.contains_key(&guild_id) {
-            return;
+    let instance_lock = {
+        let storage = storage_lock.read().await;
+        match storage.get(&guild_id) {
+            Some(instance) => instance.clone(),
+            None => return,
         }
+    };
 
-        let instance = storage.get_mut(&guild_id).unwrap();
+    let config = {
+        let data_read = ctx.data.read().await;
+        let database = data_read
+            .get::<DatabaseClientData>()
+            .expect("Cannot get DatabaseClientData")
+            .clone();
+        let mut database = database.lock().await;
+        database
+            .get_server_config_or_default(guild_id.get())
+            .await
+            .unwrap()
+            .unwrap()
+    };
 
-        if instance.text_channel.0 != message.channel_id.0 {
+    {
+        let mut instance = instance_lock.lock().await;
+        if !should_read_message(&ctx, &mut instance, &config, &message).await {
             return;
         }
+    }
 
-        if message.content.starts_with(";") {
-            return;
+    let (channel_id, message_id) = (message.channel_id, message.id);
+    let handles = TTSInstance::read(&instance_lock, message, &ctx, &config).await;
+
+    let (first, last) = match (handles.first(), handles.last()) {
+        (Some(first), Some(last)) => (first, last),
+        _ => return,
+    };
+
+    let tracks_ahead = match get_call(&ctx, guild_id).await {
+        Ok(call) => call
+            .lock()
+            .await
+            .queue()
+            .len()
+            .saturating_sub(handles.len()),
+        Err(_) => 0,
+    };
+
+    if !should_react_on_queue(config.react_on_queue.unwrap_or(false), tracks_ahead) {
+        return;
+    }
+
+    if channel_id
+        .create_reaction(&ctx.http, message_id, QUEUED_REACTION)
+        .await
+        .is_err()
+    {
+        return;
+    }
+
+    let remove = RemoveQueuedReaction {
+        http: ctx.http.clone(),
+        channel_id,
+        message_id,
+    };
+    let _ = first.add_event(Event::Track(TrackEvent::Play), remove.clone());
+    // The first track may start before the Play handler is added; the reaction is removed
+    // when the message finishes in that case.
+    let _ = last.add_event(Event::Track(TrackEvent::End), remove);
+}
+
+/// Whether the queued reaction should be added.
+/// The reaction is only useful while other tracks play before the message; when nothing is
+/// ahead the message starts at once and the Play event would never remove it.
+fn should_react_on_queue(enabled: bool, tracks_ahead: usize) -> bool {
+    enabled && tracks_ahead > 0
+}
+
+/// Check whether the instance should read the message.
+/// This applies the channel, prefix, author, mute, opt-out and throttle checks,
+/// and is shared by new and edited messages.
+pub async fn should_read_message(
+    ctx: &Context,
+    instance: &mut TTSInstance,
+    config: &ServerConfig,
+    message: &Message,
+) -> bool {
+    let bot_id = ctx.cache.current_user().id;
+
+    if !instance.contains_text_channel(message.channel_id) {
+        return false;
+    }
+
+    if message.content.starts_with(";") {
+        return false;
+    }
+
+    if is_ignored_channel(&config.ignored_text_channels, message.channel_id)
+        || has_ignored_prefix(&config.ignore_prefixes, &message.content)
+    {
+        return false;
+    }
+
+    let read_bots = if message.author.bot {
+        config.read_bots.unwrap_or(false)
+            || (config.read_news.unwrap_or(false) && is_news_message(message, ctx).await)
+    } else {
+        false
+    };
+
+    if instance.is_muted(message.author.id) {
+        return false;
+    }
+
+    if !should_read_author(message.author.id, message.author.bot, bot_id, read_bots) {
+        return false;
+    }
+
+    let opt_out = {
+        let data_read = ctx.data.read().await;
+        let database = data_read
+            .get::<DatabaseClientData>()
+            .expect("Cannot get DatabaseClientData")
+            .clone();
+        let mut database = database.lock().await;
+        let config = database
+            .get_user_config(message.author.id.get())
+            .await
+            .unwrap();
+        is_opted_out(config.as_ref())
+    };
+
+    if opt_out {
+        return false;
+    }
+
+    if let Some(max_reads_per_minute) = config.max_reads_per_minute {
+        if !instance.try_record_read(max_reads_per_minute) {
+            log_dropped_message(
+                DropReason::Throttled,
+                instance.guild.get(),
+                message.author.id.get(),
+                None,
+                &message.content,
+            );
+            if config.react_on_throttle.unwrap_or(false) {
+                let _ = message.react(&ctx.http, '⏳').await;
+            }
+            return false;
         }
+    }
+
+    true
+}
+
+/// Reaction added to a message while it waits in the queue.
+const QUEUED_REACTION: char = '🔊';
+
+/// Remove the queued reaction once the message starts playing.
+#[derive(Clone)]
+struct RemoveQueuedReaction {
+    http: Arc<Http>,
+    channel_id: ChannelId,
+    message_id: MessageId,
+}
+
+#[async_trait]
+impl EventHandler for RemoveQueuedReaction {
+    async fn act(&self, _ctx: &EventContext<'_>) -> Option<Event> {
+        let _ = self
+            .channel_id
+            .delete_reaction(&self.http, self.message_id, None, QUEUED_REACTION)
+            .await;
+        None
+    }
+}
+
+/// Whether the message consists only of mentions of the bot.
+fn is_bare_mention(content: &str, bot_id: UserId) -> bool {
+    content
+        .replace(&format!("<@{}>", bot_id.get()), "")
+        .replace(&format!("<@!{}>", bot_id.get()), "")
+        .trim()
+        .is_empty()
+}
+
+/// Whether messages from the author should be read.
+/// The bot's own messages are never read to avoid loops.
+fn should_read_author(author_id: UserId, is_bot: bool, bot_id: UserId, read_bots: bool) -> bool {
+    if author_id == bot_id {
+        return false;
+    }
+    !is_bot || read_bots
+}
+
+/// Whether the author asked not to be read with /ignoreme.
+/// Authors without a config have not opted out.
+fn is_opted_out(config: Option<&UserConfig>) -> bool {
+    config.and_then(|config| config.opt_out).unwrap_or(false)
+}
+
+/// Whether the channel is excluded from reading by the server config.
+fn is_ignored_channel(ignored_text_channels: &[u64], channel_id: ChannelId) -> bool {
+    ignored_text_channels.contains(&channel_id.get())
+}
+
+/// Whether the message starts with one of the server's ignored prefixes.
+fn has_ignored_prefix(prefixes: &[String], content: &str) -> bool {
+    prefixes
+        .iter()
+        .any(|prefix| !prefix.is_empty() && content.starts_with(prefix.as_str()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::database::Database;
+
+    #[test]
+    fn bare_mention_is_only_mentions() {
+        let bot_id = UserId::new(1);
+        assert!(is_bare_mention("<@1>", bot_id));
+        assert!(is_bare_mention(" <@!1> ", bot_id));
+        assert!(!is_bare_mention("<@1> こんにちは", bot_id));
+        assert!(!is_bare_mention("<@2>", bot_id));
+    }
+
+    #[test]
+    fn own_messages_are_never_read() {
+        let bot_id = UserId::new(1);
+        assert!(!should_read_author(bot_id, true, bot_id, true));
+        assert!(should_read_author(UserId::new(2), false, bot_id, false));
+        assert!(!should_read_author(UserId::new(2), true, bot_id, false));
+        assert!(should_read_author(UserId::new(2), true, bot_id, true));
+    }
+
+    #[test]
+    fn reacts_only_when_tracks_are_ahead() {
+        assert!(should_react_on_queue(true, 1));
+        assert!(!should_react_on_queue(true, 0));
+        assert!(!should_react_on_queue(false, 3));
+    }
+
+    #[test]
+    fn opted_out_authors_are_skipped() {
+        let mut config = Database::default_user_config();
+        assert!(!is_opted_out(None));
+        assert!(!is_opted_out(Some(&config)));
+
+        config.toggle_opt_out();
+        assert!(is_opted_out(Some(&config)));
+        config.toggle_opt_out();
+        assert!(!is_opted_out(Some(&config)));
+    }
+
+    #[test]
+    fn ignored_channels_are_skipped() {
+        assert!(is_ignored_channel(&[10, 20], ChannelId::new(20)));
+        assert!(!is_ignored_channel(&[10, 20], ChannelId::new(30)));
+        assert!(!is_ignored_channel(&[], ChannelId::new(10)));
+    }
 
-        instance.read(message, &ctx).await;
+    #[test]
+    fn any_ignored_prefix_skips() {
+        let prefixes = vec![String::from("!"), String::from("."), String::new()];
+        assert!(has_ignored_prefix(&prefixes, "!play"));
+        assert!(has_ignored_prefix(&prefixes, ".help"));
+        assert!(!has_ignored_prefix(&prefixes, "こんにちは"));
+        assert!(!has_ignored_prefix(&[], "!play"));
     }
 }
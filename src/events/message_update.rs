@@ -0,0 +1,148 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serenity::{
+    model::{event::MessageUpdateEvent, prelude::Message},
+    prelude::Context,
+};
+
+use crate::{
+    data::{DatabaseClientData, TTSData},
+    events::message_receive::should_read_message,
+    tts::instance::TTSInstance,
+};
+
+/// How old an edit timestamp may be for the update to count as a new edit.
+const EDIT_FRESHNESS_SECS: i64 = 30;
+
+/// Whether an edit changed the text.
+/// Embed-only updates carry no content and are ignored.
+fn is_meaningful_edit(old: Option<&str>, new: Option<&str>) -> bool {
+    match new {
+        Some(new) if !new.trim().is_empty() => old != Some(new),
+        _ => false,
+    }
+}
+
+/// Whether the update was caused by an edit that just happened.
+/// Embed unfurls carry no edit timestamp and pin updates carry an old one,
+/// so only recent timestamps are treated as edits.
+fn is_fresh_edit(edited_at: Option<i64>, now: i64) -> bool {
+    match edited_at {
+        Some(edited_at) => (now - edited_at).abs() <= EDIT_FRESHNESS_SECS,
+        None => false,
+    }
+}
+
+pub async fn message_update(
+    ctx: Context,
+    old: Option<Message>,
+    new: Option<Message>,
+    event: MessageUpdateEvent,
+) {
+    let guild_id = match event.guild_id {
+        Some(guild_id) => guild_id,
+        None => return,
+    };
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs() as i64)
+        .unwrap_or(0);
+    if !is_fresh_edit(
+        event
+            .edited_timestamp
+            .map(|timestamp| timestamp.unix_timestamp()),
+        now,
+    ) {
+        return;
+    }
+
+    if !is_meaningful_edit(
+        old.as_ref().map(|message| message.content.as_str()),
+        event.content.as_deref(),
+    ) {
+        return;
+    }
+
+    let storage_lock = {
+        let data_read = ctx.data.read().await;
+        data_read
+            .get::<TTSData>()
+            .expect("Cannot get TTSStorage")
+            .clone()
+    };
+
+    let instance_lock = match storage_lock.read().await.get(&guild_id) {
+        Some(instance) => instance.clone(),
+        None => return,
+    };
+
+    let config = {
+        let data_read = ctx.data.read().await;
+        let database = data_read
+            .get::<DatabaseClientData>()
+            .expect("Cannot get DatabaseClientData")
+            .clone();
+        let mut database = database.lock().await;
+        database
+            .get_server_config_or_default(guild_id.get())
+            .await
+            .unwrap()
+            .unwrap()
+    };
+
+    if !config.read_edits.unwrap_or(false) {
+        return;
+    }
+
+    if !instance_lock
+        .lock()
+        .await
+        .contains_text_channel(event.channel_id)
+    {
+        return;
+    }
+
+    let mut message = match new {
+        Some(message) => message,
+        None => match event.channel_id.message(&ctx.http, event.id).await {
+            Ok(message) => message,
+            Err(err) => {
+                println!("Cannot get edited message: {:?}", err);
+                return;
+            }
+        },
+    };
+
+    {
+        let mut instance = instance_lock.lock().await;
+        if !should_read_message(&ctx, &mut instance, &config, &message).await {
+            return;
+        }
+    }
+
+    message.content = format!("修正: {}", message.content);
+    TTSInstance::read(&instance_lock, message, &ctx, &config).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn content_changes_are_edits() {
+        assert!(is_meaningful_edit(Some("a"), Some("b")));
+        assert!(is_meaningful_edit(None, Some("b")));
+        assert!(!is_meaningful_edit(Some("a"), Some("a")));
+        assert!(!is_meaningful_edit(Some("a"), None));
+        assert!(!is_meaningful_edit(None, Some("  ")));
+    }
+
+    #[test]
+    fn only_recent_edit_timestamps_are_edits() {
+        assert!(is_fresh_edit(Some(1_000), 1_000));
+        assert!(is_fresh_edit(Some(1_000), 1_000 + EDIT_FRESHNESS_SECS));
+        assert!(!is_fresh_edit(Some(1_000), 1_001 + EDIT_FRESHNESS_SECS));
+        assert!(!is_fresh_edit(None, 1_000));
+    }
+}
@@ -1,3 +1,4 @@
 pub mod message_receive;
+pub mod message_update;
 pub mod ready;
 pub mod voice_state_update;
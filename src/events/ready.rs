@@ -1,32 +1,201 @@
+use std::time::Instant;
+
 use serenity::{
-    model::prelude::{command::Command, Ready},
+    builder::{CreateCommand, CreateCommandOption},
+    model::{
+        prelude::{Command, CommandOptionType, Ready},
+        Permissions,
+    },
     prelude::Context,
 };
 
+use crate::{
+    data::StartTimeData,
+    database::schedule::{MAX_SCHEDULE_INTERVAL_MINUTES, MIN_SCHEDULE_INTERVAL_MINUTES},
+    tts::scheduler::run_scheduler,
+};
+
 pub async fn ready(ctx: Context, ready: Ready) {
     println!("{} is connected!", ready.user.name);
 
-    let _ = Command::set_global_application_commands(&ctx.http, |commands| {
-        commands
-            .create_application_command(|command| command.name("stop").description("Stop tts"))
-            .create_application_command(|command| {
-                command
-                    .name("setup")
-                    .description("Setup tts")
-                    .create_option(|o| {
-                        o.name("mode")
-                            .description("TTS channel")
-                            .add_string_choice("Text Channel", "TEXT_CHANNEL")
-                            .add_string_choice("New Thread", "NEW_THREAD")
-                            .add_string_choice("Voice Channel", "VOICE_CHANNEL")
-                            .kind(serenity::model::prelude::command::CommandOptionType::String)
-                            .required(false)
-                    })
-            })
-            .create_application_command(|command| command.name("config").description("Config"))
-            .create_application_command(|command| {
-                command.name("skip").description("skip tts message")
-            })
-    })
-    .await;
+    {
+        // Ready fires again on reconnect; keep the first start time.
+        let mut data = ctx.data.write().await;
+        if !data.contains_key::<StartTimeData>() {
+            data.insert::<StartTimeData>(Instant::now());
+            tokio::spawn(run_scheduler(ctx.clone()));
+        }
+    }
+
+    let commands = vec![
+        CreateCommand::new("stop").description("Stop tts"),
+        CreateCommand::new("setup")
+            .description("Setup tts")
+            .add_option(
+                CreateCommandOption::new(CommandOptionType::String, "mode", "TTS channel")
+                    .add_string_choice("Text Channel", "TEXT_CHANNEL")
+                    .add_string_choice("New Thread", "NEW_THREAD")
+                    .add_string_choice("Voice Channel", "VOICE_CHANNEL")
+                    .required(false),
+            ),
+        CreateCommand::new("join").description("Join your voice channel and read its chat"),
+        CreateCommand::new("resetconfig").description("Reset your voice settings to defaults"),
+        CreateCommand::new("broadcast")
+            .description("Send a notice to every active TTS channel (owner only)")
+            .add_option(
+                CreateCommandOption::new(CommandOptionType::String, "message", "Message to send")
+                    .required(true),
+            ),
+        CreateCommand::new("cleanup").description("Remove stale TTS instances (owner only)"),
+        CreateCommand::new("setspeaker")
+            .description("Set your VOICEVOX speaker")
+            .add_option(
+                CreateCommandOption::new(CommandOptionType::Integer, "id", "VOICEVOX speaker id")
+                    .required(true),
+            ),
+        CreateCommand::new("setvoice")
+            .description("Set your Google TTS voice by name")
+            .add_option(
+                CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "name",
+                    "Voice name such as ja-JP-Neural2-C",
+                )
+                .required(true),
+            ),
+        CreateCommand::new("mute")
+            .description("Stop reading a user's messages until /unmute or /stop")
+            .default_member_permissions(Permissions::MODERATE_MEMBERS)
+            .add_option(
+                CreateCommandOption::new(CommandOptionType::User, "user", "User to mute")
+                    .required(true),
+            ),
+        CreateCommand::new("unmute")
+            .description("Resume reading a muted user's messages")
+            .default_member_permissions(Permissions::MODERATE_MEMBERS)
+            .add_option(
+                CreateCommandOption::new(CommandOptionType::User, "user", "User to unmute")
+                    .required(true),
+            ),
+        CreateCommand::new("addchannel")
+            .description("Also read messages in this channel")
+            .default_member_permissions(Permissions::MODERATE_MEMBERS),
+        CreateCommand::new("removechannel")
+            .description("Stop reading messages in this channel")
+            .default_member_permissions(Permissions::MODERATE_MEMBERS),
+        CreateCommand::new("config")
+            .description("Config")
+            .add_option(
+                CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "engine",
+                    "Switch the TTS engine directly",
+                )
+                .add_string_choice("Google TTS", "google")
+                .add_string_choice("VOICEVOX", "voicevox")
+                .add_string_choice("Azure", "azure")
+                .required(false),
+            ),
+        CreateCommand::new("skip").description("skip tts message"),
+        CreateCommand::new("skipall").description("skip all queued tts messages"),
+        CreateCommand::new("serverconfig")
+            .description("Export, import or undo the server config")
+            .default_member_permissions(Permissions::MANAGE_GUILD)
+            .add_option(CreateCommandOption::new(
+                CommandOptionType::SubCommand,
+                "export",
+                "Download the server config as JSON",
+            ))
+            .add_option(
+                CreateCommandOption::new(
+                    CommandOptionType::SubCommand,
+                    "import",
+                    "Apply a server config JSON file",
+                )
+                .add_sub_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::Attachment,
+                        "file",
+                        "JSON file from /serverconfig export",
+                    )
+                    .required(true),
+                ),
+            )
+            .add_option(CreateCommandOption::new(
+                CommandOptionType::SubCommand,
+                "undo",
+                "Revert the last server config change",
+            )),
+        CreateCommand::new("schedule")
+            .description("Announce a phrase at a fixed interval")
+            .default_member_permissions(Permissions::MANAGE_GUILD)
+            .add_option(
+                CreateCommandOption::new(
+                    CommandOptionType::SubCommand,
+                    "add",
+                    "Add a scheduled announcement",
+                )
+                .add_sub_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::String,
+                        "phrase",
+                        "Phrase to announce",
+                    )
+                    .required(true),
+                )
+                .add_sub_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::Integer,
+                        "interval",
+                        "Interval in minutes",
+                    )
+                    .min_int_value(MIN_SCHEDULE_INTERVAL_MINUTES)
+                    .max_int_value(MAX_SCHEDULE_INTERVAL_MINUTES)
+                    .required(true),
+                ),
+            )
+            .add_option(CreateCommandOption::new(
+                CommandOptionType::SubCommand,
+                "list",
+                "List scheduled announcements",
+            ))
+            .add_option(
+                CreateCommandOption::new(
+                    CommandOptionType::SubCommand,
+                    "remove",
+                    "Remove a scheduled announcement",
+                )
+                .add_sub_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::Integer,
+                        "id",
+                        "Schedule id from /schedule list",
+                    )
+                    .required(true),
+                ),
+            ),
+        CreateCommand::new("gcp").description("Show and refresh the GCP token (owner only)"),
+        CreateCommand::new("ping").description("Show gateway latency and uptime"),
+        CreateCommand::new("feedback").description("Show the feedback link"),
+        CreateCommand::new("ignoreme").description("Toggle reading of your messages"),
+        CreateCommand::new("diag")
+            .description("Diagnostics (owner only)")
+            .add_option(
+                CreateCommandOption::new(
+                    CommandOptionType::SubCommand,
+                    "synth",
+                    "Run synthesis without playing audio",
+                )
+                .add_sub_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::String,
+                        "text",
+                        "Text to synthesize",
+                    )
+                    .required(true),
+                ),
+            ),
+    ];
+
+    let _ = Command::set_global_commands(&ctx.http, commands).await;
 }
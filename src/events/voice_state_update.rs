@@ -1,12 +1,26 @@
 use crate::{
-    data::{DatabaseClientData, TTSClientData, TTSData},
+    commands::utils::{is_text_channel, where_to_type_hint},
+    data::{ConfigData, DatabaseClientData, TTSClientData, TTSData},
+    database::server_config::ServerConfig,
     implement::{
         member_name::ReadName,
-        voice_move_state::{VoiceMoveState, VoiceMoveStateTrait},
+        voice_move_state::{voice_mute_change, VoiceMoveState, VoiceMoveStateTrait},
     },
-    tts::{instance::TTSInstance, message::AnnounceMessage},
+    tts::{
+        instance::{at_capacity, TTSInstance},
+        message::{
+            leave_announcement, mute_announcement, select_join_announcement, AnnounceMessage,
+        },
+        voicevox::voicevox::speaker_credits,
+    },
+};
+use serenity::{
+    builder::{CreateEmbed, CreateMessage, EditThread},
+    futures::lock::Mutex,
+    model::{prelude::ChannelId, voice::VoiceState},
+    prelude::Context,
 };
-use serenity::{model::voice::VoiceState, prelude::Context};
+use std::{sync::Arc, time::Duration};
 
 pub async fn voice_state_update(ctx: Context, old: Option<VoiceState>, new: VoiceState) {
     if new.member.clone().unwrap().user.bot {
@@ -39,7 +53,7 @@ pub async fn voice_state_update(ctx: Context, old: Option<VoiceState>, new: Voic
             .clone();
         let mut database = database.lock().await;
         database
-            .get_server_config_or_default(guild_id.0)
+            .get_server_config_or_default(guild_id.get())
             .await
             .unwrap()
             .unwrap()
@@ -49,95 +63,222 @@ pub async fn voice_state_update(ctx: Context, old: Option<VoiceState>, new: Voic
         let mut storage = storage_lock.write().await;
         if !storage.contains_key(&guild_id) {
             if let Some(new_channel) = new.channel_id {
-                if config.autostart_channel_id.unwrap_or(0) == new_channel.0 {
-                    let manager = songbird::get(&ctx)
+                if config.autostart_channel_id.unwrap_or(0) == new_channel.get() {
+                    let max_active_instances = ctx
+                        .data
+                        .read()
                         .await
-                        .expect("Cannot get songbird client.")
-                        .clone();
+                        .get::<ConfigData>()
+                        .expect("Cannot get ConfigData")
+                        .max_active_instances;
+                    if at_capacity(storage.len(), max_active_instances) {
+                        println!(
+                            "Skipping autostart in guild {}: active instance limit reached",
+                            guild_id.get()
+                        );
+                        return;
+                    }
+
+                    let manager = match songbird::get(&ctx).await {
+                        Some(manager) => manager,
+                        None => {
+                            println!("Cannot get songbird client.");
+                            return;
+                        }
+                    };
+                    let configured = config.autostart_text_channel_id.map(ChannelId::new);
+                    let valid = match configured {
+                        Some(channel_id) => is_text_channel(&ctx, channel_id).await,
+                        None => false,
+                    };
+                    if configured.is_some() && !valid {
+                        println!(
+                            "Autostart text channel is invalid in guild {}, using the voice channel chat",
+                            guild_id.get()
+                        );
+                    }
+                    let text_channel = autostart_text_channel(configured, valid, new_channel);
+
                     storage.insert(
                         guild_id,
-                        TTSInstance {
-                            before_message: None,
-                            guild: guild_id,
-                            text_channel: new_channel,
-                            voice_channel: new_channel,
-                        },
+                        Arc::new(Mutex::new(TTSInstance::new(
+                            guild_id,
+                            text_channel,
+                            new_channel,
+                        ))),
                     );
 
-                    let _handler = manager.join(guild_id.0, new_channel.0).await;
-                    let tts_client = ctx
-                        .data
-                        .read()
-                        .await
-                        .get::<TTSClientData>()
-                        .expect("Cannot get TTSClientData")
-                        .clone();
-                    let voicevox_speakers = tts_client.lock().await.1.get_speakers().await;
-
-                    new_channel
-                        .send_message(&ctx.http, |f| {
-                            f.embed(|e| {
-                                e.title("自動参加 読み上げ (Serenity)")
-                                    .field(
-                                        "VOICEVOXクレジット",
-                                        format!("```\n{}\n```", voicevox_speakers.join("\n")),
-                                        false,
-                                    )
-                                    .field("設定コマンド", "`/config`", false)
-                                    .field("フィードバック", "https://feedback.mii.codes/", false)
+                    let _handler = manager.join(guild_id, new_channel).await;
+                    if posts_join_embed(&config) {
+                        let tts_client = ctx
+                            .data
+                            .read()
+                            .await
+                            .get::<TTSClientData>()
+                            .expect("Cannot get TTSClientData")
+                            .clone();
+                        let voicevox = tts_client.lock().await.1.clone();
+                        let voicevox_speakers = voicevox.get_speakers().await;
+                        if let Err(err) = &voicevox_speakers {
+                            println!("Cannot get speaker list. {err:?}");
+                        }
+                        let feedback_url = ctx
+                            .data
+                            .read()
+                            .await
+                            .get::<ConfigData>()
+                            .expect("Cannot get ConfigData")
+                            .feedback_url()
+                            .to_string();
+
+                        text_channel
+                            .send_message(&ctx.http, {
+                                let mut embed = CreateEmbed::new()
+                                    .title("自動参加 読み上げ (Serenity)")
+                                    .description(where_to_type_hint(text_channel, new_channel));
+                                if let Some(credits) = speaker_credits(&voicevox_speakers) {
+                                    embed = embed.field("VOICEVOXクレジット", credits, false);
+                                }
+                                embed = embed.field("設定コマンド", "`/config`", false).field(
+                                    "フィードバック",
+                                    feedback_url,
+                                    false,
+                                );
+                                CreateMessage::new().embed(embed)
                             })
-                        })
-                        .await
-                        .unwrap();
+                            .await
+                            .unwrap();
+                    }
+
+                    TTSInstance::check_speak_permission(&ctx, new_channel, text_channel).await;
                 }
             }
             return;
         }
 
-        let instance = storage.get_mut(&guild_id).unwrap();
+        let instance_lock = storage.get(&guild_id).unwrap().clone();
+        drop(storage);
+        let mut instance = instance_lock.lock().await;
 
         let voice_move_state = new.move_state(&old, instance.voice_channel);
 
+        let debounce = Duration::from_secs(config.announce_debounce_secs.unwrap_or(0));
+        let announce = debounce.is_zero()
+            || !matches!(
+                voice_move_state,
+                VoiceMoveState::JOIN | VoiceMoveState::LEAVE
+            )
+            || instance.should_announce(new.user_id, voice_move_state, debounce);
+
         let message: Option<String> = match voice_move_state {
-            VoiceMoveState::JOIN => Some(format!(
-                "{} さんが通話に参加しました",
-                new.member.unwrap().read_name()
-            )),
-            VoiceMoveState::LEAVE => Some(format!(
-                "{} さんが通話から退出しました",
-                new.member.unwrap().read_name()
-            )),
-            _ => None,
+            VoiceMoveState::JOIN => {
+                let custom_join_message = if config.allow_custom_join.unwrap_or(false) {
+                    let data_read = ctx.data.read().await;
+                    let database = data_read
+                        .get::<DatabaseClientData>()
+                        .expect("Cannot get DatabaseClientData")
+                        .clone();
+                    let mut database = database.lock().await;
+                    database
+                        .get_user_config(new.user_id.get())
+                        .await
+                        .unwrap()
+                        .and_then(|config| config.custom_join_message)
+                } else {
+                    None
+                };
+                Some(select_join_announcement(
+                    &new.member.clone().unwrap().read_name(),
+                    custom_join_message.as_deref(),
+                    config.allow_custom_join.unwrap_or(false),
+                ))
+            }
+            VoiceMoveState::LEAVE => {
+                Some(leave_announcement(&new.member.clone().unwrap().read_name()))
+            }
+            _ => match voice_mute_change(&old, &new, instance.voice_channel) {
+                Some(change) if config.announce_mute_changes.unwrap_or(false) => Some(
+                    mute_announcement(&new.member.clone().unwrap().read_name(), change),
+                ),
+                _ => None,
+            },
         };
 
-        if let Some(message) = message {
-            instance.read(AnnounceMessage { message }, &ctx).await;
+        if let Some(message) = message.filter(|_| announce) {
+            drop(instance);
+            TTSInstance::read(&instance_lock, AnnounceMessage { message }, &ctx, &config).await;
+            instance = instance_lock.lock().await;
         }
 
         if voice_move_state == VoiceMoveState::LEAVE {
             let mut del_flag = false;
             for channel in guild_id.channels(&ctx.http).await.unwrap() {
                 if channel.0 == instance.voice_channel {
-                    del_flag = channel.1.members(&ctx.cache).await.unwrap().len() <= 1;
+                    del_flag = channel.1.members(&ctx.cache).unwrap().len() <= 1;
                 }
             }
 
             if del_flag {
-                let _ = storage
-                    .get(&guild_id)
-                    .unwrap()
+                let _ = instance
                     .text_channel
-                    .edit_thread(&ctx.http, |f| f.archived(true))
+                    .edit_thread(&ctx.http, EditThread::new().archived(true))
                     .await;
-                storage.remove(&guild_id);
-
-                let manager = songbird::get(&ctx)
-                    .await
-                    .expect("Cannot get songbird client.")
-                    .clone();
+                storage_lock.write().await.remove(&guild_id);
 
-                manager.remove(guild_id.0).await.unwrap();
+                match songbird::get(&ctx).await {
+                    Some(manager) => {
+                        if let Err(err) = manager.remove(guild_id).await {
+                            println!("Cannot leave voice channel: {:?}", err);
+                        }
+                    }
+                    None => println!("Cannot get songbird client."),
+                }
             }
         }
     }
 }
+
+/// Text channel used when autostarting.
+/// Falls back to the voice channel's chat when the configured channel is missing or invalid.
+fn autostart_text_channel(
+    configured: Option<ChannelId>,
+    valid: bool,
+    voice: ChannelId,
+) -> ChannelId {
+    match configured {
+        Some(channel_id) if valid => channel_id,
+        _ => voice,
+    }
+}
+
+/// Whether the credit embed is posted after an autostart join.
+/// Posted unless the server turned it off.
+fn posts_join_embed(config: &ServerConfig) -> bool {
+    config.post_join_embed.unwrap_or(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn autostart_falls_back_to_the_voice_channel_chat() {
+        let voice = ChannelId::new(1);
+        let text = ChannelId::new(2);
+        assert_eq!(autostart_text_channel(Some(text), true, voice), text);
+        assert_eq!(autostart_text_channel(Some(text), false, voice), voice);
+        assert_eq!(autostart_text_channel(None, false, voice), voice);
+    }
+
+    #[test]
+    fn join_embed_is_posted_unless_disabled() {
+        let mut config = ServerConfig::default();
+        assert!(posts_join_embed(&config));
+
+        config.post_join_embed = Some(false);
+        assert!(!posts_join_embed(&config));
+
+        config.post_join_embed = Some(true);
+        assert!(posts_join_embed(&config));
+    }
+}
@@ -1,11 +1,17 @@
 use serenity::model::guild::Member;
 
+use crate::utils::truncate_chars;
+
+/// Longest name read in announcements, in characters.
+pub const MAX_USERNAME_LENGTH: usize = 32;
+
 pub trait ReadName {
     fn read_name(&self) -> String;
 }
 
 impl ReadName for Member {
     fn read_name(&self) -> String {
-        self.nick.clone().unwrap_or(self.user.name.clone())
+        let name = self.nick.clone().unwrap_or(self.user.name.clone());
+        truncate_chars(&name, MAX_USERNAME_LENGTH, "")
     }
 }
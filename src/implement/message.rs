@@ -1,71 +1,453 @@
-use std::{env, fs::File, io::Write};
+use std::{sync::LazyLock, time::SystemTime};
 
 use async_trait::async_trait;
 use regex::Regex;
-use serenity::{model::prelude::Message, prelude::Context};
+use serenity::{
+    model::prelude::{Channel, ChannelType, Message, MessageFlags, Role},
+    prelude::Context,
+};
 
 use crate::{
-    data::{DatabaseClientData, TTSClientData},
+    data::{ConfigData, DatabaseClientData},
+    database::{
+        database::utc_day, server_config::ServerConfig, user_config::DEFAULT_SPEAKING_RATE,
+    },
     tts::{
-        gcp_tts::structs::{
-            audio_config::AudioConfig, synthesis_input::SynthesisInput,
-            synthesize_request::SynthesizeRequest,
-        },
-        instance::TTSInstance,
-        message::TTSMessage,
-        tts_type::TTSType,
+        instance::{get_call, TTSInstance},
+        message::{log_dropped_message, DropReason, SynthesisPlan, SynthesisVoice, TTSMessage},
+        number::normalize_numbers,
+        ssml,
     },
+    utils::truncate_chars,
 };
 
+const INLINE_SPEED_PREFIX: &str = "[speed:";
+
+/// Default maximum length of a chunk when long messages are split.
+const DEFAULT_CHUNK_LENGTH: usize = 100;
+
+/// Offset from UTC used for read times when the server has not set one (JST).
+const DEFAULT_TIMEZONE_OFFSET_MINUTES: i32 = 9 * 60;
+
+/// Format a unix time as "HH時MM分" in the timezone `offset_minutes` from UTC.
+///
+/// Example:
+/// ```rust
+/// assert_eq!(format_read_time(0, 9 * 60), "09時00分");
+/// ```
+pub fn format_read_time(unix_secs: i64, offset_minutes: i32) -> String {
+    let minutes = (unix_secs.div_euclid(60) + offset_minutes as i64).rem_euclid(24 * 60);
+    format!("{:02}時{:02}分", minutes / 60, minutes % 60)
+}
+
+/// Speaking rate increase per queued track when auto speed is enabled.
+const AUTO_SPEED_STEP: f32 = 0.1;
+/// Highest speaking rate auto speed scales up to.
+const MAX_AUTO_SPEAKING_RATE: f32 = 2.0;
+
+/// Speaking rate scaled up for the number of tracks waiting in the queue.
+/// The rate never drops below `base_rate` and is capped at MAX_AUTO_SPEAKING_RATE.
+///
+/// Example:
+/// ```rust
+/// assert_eq!(queue_speed_factor(0, 1.2), 1.2);
+/// ```
+pub fn queue_speed_factor(queue_len: usize, base_rate: f32) -> f32 {
+    let scaled = base_rate * (1.0 + AUTO_SPEED_STEP * queue_len as f32);
+    scaled.min(MAX_AUTO_SPEAKING_RATE).max(base_rate)
+}
+
+/// Parse an inline speed directive like `[speed:1.5]` at the start of the text.
+/// Returns the clamped speaking rate and the text with the directive removed.
+/// Invalid directives are left as they are.
+///
+/// Example:
+/// ```rust
+/// let (speed, text) = parse_inline_speed("[speed:1.5] hello");
+/// assert_eq!(speed, Some(1.5));
+/// assert_eq!(text, "hello");
+/// ```
+pub fn parse_inline_speed(text: &str) -> (Option<f32>, String) {
+    let rest = match text.strip_prefix(INLINE_SPEED_PREFIX) {
+        Some(rest) => rest,
+        None => return (None, text.to_string()),
+    };
+
+    let (value, rest) = match rest.split_once(']') {
+        Some(pair) => pair,
+        None => return (None, text.to_string()),
+    };
+
+    match value.trim().parse::<f32>() {
+        Ok(speed) if speed.is_finite() => (
+            Some(speed.clamp(0.25f32, 4.0f32)),
+            rest.trim_start().to_string(),
+        ),
+        _ => (None, text.to_string()),
+    }
+}
+
+/// Remove the inline speed directive and spoilers from a message's content, as configured.
+pub fn strip_content(content: &str, config: &ServerConfig) -> String {
+    let content = if config.allow_inline_speed.unwrap_or(false) {
+        parse_inline_speed(content).1
+    } else {
+        content.to_string()
+    };
+    if config.read_spoilers.unwrap_or(true) {
+        content
+    } else {
+        redact_spoilers(&content)
+    }
+}
+
+/// Apply the server dictionary and, when configured, keep only the first line.
+pub fn apply_text_rules(content: &str, config: &ServerConfig) -> String {
+    let text = config.dictionary.apply(content);
+    if config.read_first_line_only.unwrap_or(false) {
+        first_line(&text)
+    } else {
+        text
+    }
+}
+
+/// Whether an author with the roles may use SSML tags.
+pub fn is_ssml_trusted(mut roles: impl Iterator<Item = u64>, config: &ServerConfig) -> bool {
+    roles.any(|role| config.ssml_allowed_roles.contains(&role))
+}
+
+/// Turn read text into SSML: escape it, or keep the allowed tags for trusted authors,
+/// then normalize numbers when configured.
+pub fn to_ssml(text: &str, config: &ServerConfig, trusted: bool) -> String {
+    let text = if trusted {
+        ssml::sanitize_allowlist(text)
+    } else {
+        ssml::escape(text)
+    };
+    if config.normalize_numbers.unwrap_or(false) {
+        ssml::map_text(&text, normalize_numbers)
+    } else {
+        text
+    }
+}
+
+/// Split SSML text into the chunks that are synthesized, as configured.
+pub fn message_chunks(text: String, config: &ServerConfig) -> Vec<String> {
+    if config.chunk_long_messages.unwrap_or(false) {
+        ssml::split_sentences(&text, config.chunk_length.unwrap_or(DEFAULT_CHUNK_LENGTH))
+    } else {
+        vec![text]
+    }
+}
+
+static SPOILER_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\|\|[\s\S]*?\|\|").unwrap());
+
+/// Replace spoiler blocks (`||...||`) with "ネタバレ".
+pub fn redact_spoilers(text: &str) -> String {
+    SPOILER_REGEX.replace_all(text, "ネタバレ").to_string()
+}
+
+/// Keep only the first line and append "以下略" when more lines follow.
+pub fn first_line(text: &str) -> String {
+    let mut lines = text.lines();
+    let first = lines.next().unwrap_or_default();
+    if lines.any(|line| !line.trim().is_empty()) {
+        format!("{}、以下略", first)
+    } else {
+        first.to_string()
+    }
+}
+
+/// Whether nothing would be spoken for the text,
+/// i.e. it only contains whitespace and punctuation.
+pub fn is_effectively_empty(text: &str) -> bool {
+    text.chars().all(|c| {
+        c.is_whitespace()
+            || c.is_ascii_punctuation()
+            || matches!(
+                c,
+                '、' | '。'
+                    | '，'
+                    | '．'
+                    | '・'
+                    | '！'
+                    | '？'
+                    | '「'
+                    | '」'
+                    | '『'
+                    | '』'
+                    | '（'
+                    | '）'
+                    | '…'
+                    | 'ー'
+                    | '〜'
+            )
+    })
+}
+
+/// Content read for a message.
+/// A forwarded message has no content of its own, so the first forwarded
+/// snapshot with content is read instead, prefixed with "転送: ".
+pub fn readable_content(message: &Message) -> String {
+    if !message.content.trim().is_empty() {
+        return message.content.clone();
+    }
+    message
+        .message_snapshots
+        .iter()
+        .map(|snapshot| snapshot.content.trim())
+        .find(|content| !content.is_empty())
+        .map(|content| format!("転送: {}", content))
+        .unwrap_or_else(|| message.content.clone())
+}
+
+/// Whether the channel is a thread, whose name can be read.
+pub fn is_thread(kind: ChannelType) -> bool {
+    matches!(
+        kind,
+        ChannelType::PublicThread | ChannelType::PrivateThread | ChannelType::NewsThread
+    )
+}
+
+/// Escaped SSML read before the first message of a thread.
+pub fn thread_name_text(name: &str) -> String {
+    format!("スレッド『{}』", ssml::escape(name))
+}
+
+/// Escaped SSML read before the message text, if any.
+/// News is announced as such; otherwise the author's name is read unless they turned it
+/// off or their previous message was the last one read.
+pub fn speaker_prefix(
+    news: bool,
+    read_own_name: bool,
+    is_continuation: bool,
+    name: &str,
+) -> Option<String> {
+    if news {
+        Some(String::from("お知らせ"))
+    } else if read_own_name && !is_continuation {
+        Some(format!("{}さんの発言", ssml::escape(name)))
+    } else {
+        None
+    }
+}
+
+/// Text read for a message that only contains stickers.
+pub fn sticker_text(names: &[String]) -> String {
+    format!("スタンプ: {}", names.join("、"))
+}
+
+/// Text read for a message that only contains a poll.
+pub fn poll_text(question: &str) -> String {
+    format!("投票: {}", question.trim())
+}
+
+/// Maximum number of attachments read by name; the rest are only counted.
+const MAX_READ_ATTACHMENTS: usize = 3;
+/// Maximum length of a read attachment name, in characters.
+const MAX_ATTACHMENT_NAME_LENGTH: usize = 40;
+
+/// Text read for a message's attachments, given each one's filename and description.
+/// The description (alt text) is preferred over the filename.
+/// Names have control characters removed and are shortened; the result is not escaped.
+///
+/// Example:
+/// ```rust
+/// let text = attachment_text(&[
+///     ("example.png".to_string(), None),
+///     ("cat.jpg".to_string(), Some("寝ている猫".to_string())),
+/// ]);
+/// assert_eq!(text, "ファイル: example.png、ファイル: 寝ている猫");
+/// ```
+pub fn attachment_text(attachments: &[(String, Option<String>)]) -> String {
+    let mut parts = attachments
+        .iter()
+        .take(MAX_READ_ATTACHMENTS)
+        .map(|(filename, description)| {
+            let name = description
+                .as_deref()
+                .map(str::trim)
+                .filter(|description| !description.is_empty())
+                .unwrap_or(filename);
+            let name = name.chars().filter(|c| !c.is_control()).collect::<String>();
+            let name = truncate_chars(&name, MAX_ATTACHMENT_NAME_LENGTH, "");
+            format!("ファイル: {}", name.trim())
+        })
+        .collect::<Vec<_>>();
+    if attachments.len() > MAX_READ_ATTACHMENTS {
+        parts.push(format!(
+            "他{}個の添付ファイル",
+            attachments.len() - MAX_READ_ATTACHMENTS
+        ));
+    }
+    parts.join("、")
+}
+
+/// Keep only the first sentence of the text.
+pub fn first_sentence(text: &str) -> String {
+    match text.find(['。', '！', '？', '.', '!', '?', '\n']) {
+        Some(index) => {
+            let end = index + text[index..].chars().next().unwrap().len_utf8();
+            text[..end].trim_end().to_string()
+        }
+        None => text.to_string(),
+    }
+}
+
+/// Whether the message is a published news message.
+/// Crossposts from followed announcement channels carry message flags,
+/// while messages in the announcement channel itself are detected by the channel type.
+pub fn is_news(flags: Option<MessageFlags>, kind: Option<ChannelType>) -> bool {
+    flags.is_some_and(|flags| {
+        flags.intersects(MessageFlags::IS_CROSSPOST | MessageFlags::CROSSPOSTED)
+    }) || kind == Some(ChannelType::News)
+}
+
+/// Whether the message is a published news message.
+pub async fn is_news_message(message: &Message, ctx: &Context) -> bool {
+    let kind = match message.channel_id.to_channel(ctx).await {
+        Ok(Channel::Guild(channel)) => Some(channel.kind),
+        _ => None,
+    };
+    is_news(message.flags, kind)
+}
+
+/// Title of a member: the name of their highest hoisted role.
+/// Returns None when the member has no hoisted role.
+pub fn role_title<'a>(roles: impl Iterator<Item = &'a Role>) -> Option<String> {
+    roles
+        .filter(|role| role.hoist)
+        .max_by_key(|role| role.position)
+        .map(|role| role.name.clone())
+}
+
+/// Prefix the name with a role title such as "管理者のAlice".
+pub fn name_with_title(title: Option<&str>, name: &str) -> String {
+    match title {
+        Some(title) => format!("{}の{}", title, name),
+        None => name.to_string(),
+    }
+}
+
+/// Name used when reading a message.
+pub trait MessageAuthorName {
+    fn get_user_name(&self) -> String;
+}
+
+impl MessageAuthorName for Message {
+    fn get_user_name(&self) -> String {
+        match &self.member {
+            Some(member) => member.nick.clone().unwrap_or(self.author.name.clone()),
+            None => self.author.name.clone(),
+        }
+    }
+}
+
 #[async_trait]
 impl TTSMessage for Message {
-    async fn parse(&self, instance: &mut TTSInstance, ctx: &Context) -> String {
-        let data_read = ctx.data.read().await;
-
-        let config = {
-            let database = data_read
-                .get::<DatabaseClientData>()
-                .expect("Cannot get DatabaseClientData")
-                .clone();
-            let mut database = database.lock().await;
-            database
-                .get_server_config_or_default(instance.guild.0)
-                .await
-                .unwrap()
-                .unwrap()
+    async fn parse(
+        &self,
+        instance: &mut TTSInstance,
+        ctx: &Context,
+        config: &ServerConfig,
+        read_own_name: bool,
+    ) -> String {
+        let content = strip_content(&readable_content(self), config);
+        let poll_question = self
+            .poll
+            .as_ref()
+            .and_then(|poll| poll.question.text.as_deref())
+            .filter(|question| !question.trim().is_empty());
+        let content = if !config.read_stickers.unwrap_or(false) || !content.trim().is_empty() {
+            content
+        } else if !self.sticker_items.is_empty() {
+            let names = self
+                .sticker_items
+                .iter()
+                .map(|sticker| sticker.name.clone())
+                .collect::<Vec<_>>();
+            sticker_text(&names)
+        } else if let Some(question) = poll_question {
+            poll_text(question)
+        } else {
+            content
         };
-        let mut text = self.content.clone();
-        for rule in config.dictionary.rules {
-            if rule.is_regex {
-                let regex = Regex::new(&rule.rule).unwrap();
-                text = regex.replace_all(&text, rule.to).to_string();
-            } else {
-                text = text.replace(&rule.rule, &rule.to);
-            }
+        let news = config.read_news.unwrap_or(false) && is_news_message(self, ctx).await;
+        let content = if news && config.news_first_sentence_only.unwrap_or(false) {
+            first_sentence(&content)
+        } else {
+            content
+        };
+        let text = apply_text_rules(&content, config);
+        if is_effectively_empty(&text) && self.attachments.is_empty() {
+            return String::new();
         }
-        let mut res = if let Some(before_message) = &instance.before_message {
-            if before_message.author.id == self.author.id {
-                text.clone()
-            } else {
-                let member = self.member.clone();
-                let name = if let Some(member) = member {
-                    member.nick.unwrap_or(self.author.name.clone())
-                } else {
-                    self.author.name.clone()
-                };
-                format!("{}さんの発言<break time=\"200ms\"/>{}", name, text)
+        let trusted = self.member.as_ref().map_or(false, |member| {
+            is_ssml_trusted(member.roles.iter().map(|role| role.get()), config)
+        });
+        let text = to_ssml(&text, config, trusted);
+
+        let is_continuation = instance
+            .before_message
+            .as_ref()
+            .is_some_and(|before_message| before_message.author.id == self.author.id);
+
+        let title = if config.read_role_title.unwrap_or(false) {
+            match (&self.member, self.guild(&ctx.cache)) {
+                (Some(member), Some(guild)) => {
+                    role_title(member.roles.iter().filter_map(|role| guild.roles.get(role)))
+                }
+                _ => None,
             }
         } else {
-            let member = self.member.clone();
-            let name = if let Some(member) = member {
-                member.nick.unwrap_or(self.author.name.clone())
-            } else {
-                self.author.name.clone()
-            };
-            format!("{}さんの発言<break time=\"200ms\"/>{}", name, text)
+            None
         };
 
-        if self.attachments.len() > 0 {
+        let name = name_with_title(title.as_deref(), &self.get_user_name());
+        let mut res = match speaker_prefix(news, read_own_name, is_continuation, &name) {
+            Some(prefix) => format!("{}<break time=\"200ms\"/>{}", prefix, text),
+            None => text.clone(),
+        };
+
+        if config.read_thread_names.unwrap_or(false)
+            && !instance.announced_threads.contains(&self.channel_id)
+        {
+            if let Ok(Channel::Guild(channel)) = self.channel_id.to_channel(ctx).await {
+                if is_thread(channel.kind) && instance.mark_thread_announced(self.channel_id) {
+                    res = format!(
+                        "{}<break time=\"200ms\"/>{}",
+                        thread_name_text(&channel.name),
+                        res
+                    );
+                }
+            }
+        }
+
+        if config.read_timestamp.unwrap_or(false) {
+            res = format!(
+                "{}<break time=\"200ms\"/>{}",
+                format_read_time(
+                    self.timestamp.unix_timestamp(),
+                    config
+                        .timezone_offset_minutes
+                        .unwrap_or(DEFAULT_TIMEZONE_OFFSET_MINUTES)
+                ),
+                res
+            );
+        }
+
+        if config.read_attachment_names.unwrap_or(false) && !self.attachments.is_empty() {
+            let attachments = self
+                .attachments
+                .iter()
+                .map(|attachment| (attachment.filename.clone(), attachment.description.clone()))
+                .collect::<Vec<_>>();
+            res = format!(
+                "{}<break time=\"200ms\"/>{}",
+                res,
+                ssml::escape(&attachment_text(&attachments))
+            );
+        } else if !self.attachments.is_empty() {
             res = format!(
                 "{}<break time=\"200ms\"/>{}個の添付ファイル",
                 res,
@@ -78,15 +460,13 @@ impl TTSMessage for Message {
         res
     }
 
-    async fn synthesize(&self, instance: &mut TTSInstance, ctx: &Context) -> String {
-        let text = self.parse(instance, ctx).await;
-
+    async fn prepare(
+        &self,
+        instance: &mut TTSInstance,
+        ctx: &Context,
+        server_config: &ServerConfig,
+    ) -> Option<SynthesisPlan> {
         let data_read = ctx.data.read().await;
-        let storage = data_read
-            .get::<TTSClientData>()
-            .expect("Cannot get GCP TTSClientStorage")
-            .clone();
-        let mut tts = storage.lock().await;
 
         let config = {
             let database = data_read
@@ -95,48 +475,413 @@ impl TTSMessage for Message {
                 .clone();
             let mut database = database.lock().await;
             database
-                .get_user_config_or_default(self.author.id.0)
+                .get_user_config_or_guild_default(self.author.id.get(), Some(instance.guild.get()))
                 .await
                 .unwrap()
                 .unwrap()
         };
 
-        let audio = match config.tts_type.unwrap_or(TTSType::GCP) {
-            TTSType::GCP => tts
-                .0
-                .synthesize(SynthesizeRequest {
-                    input: SynthesisInput {
-                        text: None,
-                        ssml: Some(format!("<speak>{}</speak>", text)),
-                    },
-                    voice: config.gcp_tts_voice.unwrap(),
-                    audioConfig: AudioConfig {
-                        audioEncoding: String::from("mp3"),
-                        speakingRate: 1.2f32,
-                        pitch: 1.0f32,
-                    },
-                })
-                .await
-                .unwrap(),
-
-            TTSType::VOICEVOX => tts
-                .1
-                .synthesize(
-                    text.replace("<break time=\"200ms\"/>", "、"),
-                    config.voicevox_speaker.unwrap_or(1),
-                )
-                .await
-                .unwrap(),
+        let text = self
+            .parse(
+                instance,
+                ctx,
+                server_config,
+                config.read_own_name.unwrap_or(true),
+            )
+            .await;
+        if is_effectively_empty(&ssml::to_plain_text(&text)) {
+            return None;
+        }
+
+        let daily_char_limit = server_config.daily_char_limit.or(data_read
+            .get::<ConfigData>()
+            .expect("Cannot get ConfigData")
+            .daily_char_limit);
+        if let Some(limit) = daily_char_limit {
+            // Only checked here; the characters are counted once they are synthesized.
+            let chars = ssml::to_plain_text(&text).chars().count() as u64;
+            let total = {
+                let database = data_read
+                    .get::<DatabaseClientData>()
+                    .expect("Cannot get DatabaseClientData")
+                    .clone();
+                let mut database = database.lock().await;
+                database
+                    .get_guild_daily_chars(instance.guild.get(), utc_day(SystemTime::now()))
+                    .await
+            };
+            match total {
+                Ok(total) if total + chars > limit => {
+                    println!(
+                        "Daily character limit exceeded in guild {}: {}/{}",
+                        instance.guild.get(),
+                        total + chars,
+                        limit
+                    );
+                    log_dropped_message(
+                        DropReason::DailyLimit,
+                        instance.guild.get(),
+                        self.author.id.get(),
+                        config.tts_type.as_ref(),
+                        &ssml::to_plain_text(&text),
+                    );
+                    return None;
+                }
+                Ok(_) => {}
+                Err(e) => println!("Failed to read synthesized characters: {}", e),
+            }
+        }
+
+        let inline_speed = if server_config.allow_inline_speed.unwrap_or(false) {
+            parse_inline_speed(&self.content).0
+        } else {
+            None
         };
 
-        let uuid = uuid::Uuid::new_v4().to_string();
+        let mut speaking_rate = inline_speed
+            .or(config.speaking_rate)
+            .unwrap_or(DEFAULT_SPEAKING_RATE);
+        if server_config.auto_speed.unwrap_or(false) {
+            let queue_len = match get_call(ctx, instance.guild).await {
+                Ok(call) => call.lock().await.queue().len(),
+                Err(_) => 0,
+            };
+            speaking_rate = queue_speed_factor(queue_len, speaking_rate);
+        }
+
+        Some(SynthesisPlan {
+            voices: vec![SynthesisVoice::from_user_config(&config, speaking_rate)],
+            chunks: message_chunks(text, server_config),
+            user_id: Some(self.author.id.get()),
+            counts_daily_chars: daily_char_limit.is_some(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serenity::model::id::{ChannelId, GuildId};
+
+    #[test]
+    fn queue_speed_factor_keeps_base_rate_for_empty_queue() {
+        assert_eq!(
+            queue_speed_factor(0, DEFAULT_SPEAKING_RATE),
+            DEFAULT_SPEAKING_RATE
+        );
+    }
+
+    #[test]
+    fn queue_speed_factor_grows_with_queue() {
+        let one = queue_speed_factor(1, 1.0);
+        let three = queue_speed_factor(3, 1.0);
+        assert!((one - 1.1).abs() < 1e-6);
+        assert!(three > one);
+    }
+
+    #[test]
+    fn queue_speed_factor_is_capped() {
+        assert_eq!(queue_speed_factor(100, 1.0), MAX_AUTO_SPEAKING_RATE);
+        // A base rate above the cap is never slowed down.
+        assert_eq!(queue_speed_factor(5, 3.0), 3.0);
+    }
+
+    #[test]
+    fn empty_or_punctuation_only_text_is_effectively_empty() {
+        assert!(is_effectively_empty(""));
+        assert!(is_effectively_empty("  \n\t"));
+        assert!(is_effectively_empty("!?..."));
+        assert!(is_effectively_empty("「」、。…ー〜"));
+    }
 
-        let path = env::current_dir().unwrap();
-        let file_path = path.join("audio").join(format!("{}.mp3", uuid));
+    #[test]
+    fn text_with_words_is_not_effectively_empty() {
+        assert!(!is_effectively_empty("a"));
+        assert!(!is_effectively_empty("。あ。"));
+        assert!(!is_effectively_empty("123"));
+        assert!(!is_effectively_empty("👍"));
+    }
+
+    #[test]
+    fn inline_speed_is_parsed_and_removed() {
+        assert_eq!(
+            parse_inline_speed("[speed:1.5] hello"),
+            (Some(1.5), String::from("hello"))
+        );
+        assert_eq!(
+            parse_inline_speed("[speed: 2 ]はい"),
+            (Some(2.0), String::from("はい"))
+        );
+    }
+
+    #[test]
+    fn inline_speed_is_clamped() {
+        assert_eq!(parse_inline_speed("[speed:10] a").0, Some(4.0));
+        assert_eq!(parse_inline_speed("[speed:0] a").0, Some(0.25));
+    }
 
-        let mut file = File::create(file_path.clone()).unwrap();
-        file.write(&audio).unwrap();
+    #[test]
+    fn invalid_inline_speed_leaves_text_unchanged() {
+        for text in [
+            "hello [speed:1.5]",
+            "[speed:fast] hello",
+            "[speed:1.5 hello",
+            "[speed:NaN] hello",
+            "[speed:inf] hello",
+        ] {
+            assert_eq!(parse_inline_speed(text), (None, String::from(text)));
+        }
+    }
+
+    #[test]
+    fn spoilers_are_redacted() {
+        assert_eq!(redact_spoilers("答えは||42||です"), "答えはネタバレです");
+        assert_eq!(redact_spoilers("||a|| and ||b||"), "ネタバレ and ネタバレ");
+        assert_eq!(redact_spoilers("||複数\n行||"), "ネタバレ");
+    }
+
+    #[test]
+    fn unclosed_spoiler_is_kept() {
+        assert_eq!(redact_spoilers("a || b"), "a || b");
+        assert_eq!(redact_spoilers("no spoiler"), "no spoiler");
+    }
+
+    #[test]
+    fn first_line_marks_omitted_lines() {
+        assert_eq!(first_line("一行目\n二行目"), "一行目、以下略");
+        assert_eq!(first_line("a\r\nb"), "a、以下略");
+    }
+
+    #[test]
+    fn first_line_ignores_trailing_blank_lines() {
+        assert_eq!(first_line("一行目"), "一行目");
+        assert_eq!(first_line("一行目\n\n  \n"), "一行目");
+        assert_eq!(first_line(""), "");
+    }
+
+    fn message(content: &str, snapshots: &[&str]) -> Message {
+        let author = serde_json::json!({
+            "id": "2",
+            "username": "alice",
+            "discriminator": "0000",
+            "avatar": null,
+        });
+        let snapshots = snapshots
+            .iter()
+            .map(|content| {
+                serde_json::json!({
+                    "message": {
+                        "content": content,
+                        "timestamp": "2024-01-01T00:00:00+00:00",
+                        "edited_timestamp": null,
+                        "mentions": [],
+                        "attachments": [],
+                        "embeds": [],
+                        "type": 0,
+                        "flags": 0,
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        serde_json::from_value(serde_json::json!({
+            "id": "1",
+            "channel_id": "3",
+            "author": author,
+            "content": content,
+            "timestamp": "2024-01-01T00:00:00+00:00",
+            "edited_timestamp": null,
+            "tts": false,
+            "mention_everyone": false,
+            "mentions": [],
+            "mention_roles": [],
+            "attachments": [],
+            "embeds": [],
+            "pinned": false,
+            "type": 0,
+            "message_snapshots": snapshots,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn forwarded_message_reads_the_snapshot() {
+        let config = ServerConfig::default();
+        let forwarded = message("", &["転送元の本文"]);
+        let text = apply_text_rules(
+            &strip_content(&readable_content(&forwarded), &config),
+            &config,
+        );
+        assert_eq!(text, "転送: 転送元の本文");
+        assert!(!is_effectively_empty(&text));
+        assert_eq!(
+            message_chunks(to_ssml(&text, &config, false), &config).len(),
+            1
+        );
+    }
+
+    #[test]
+    fn own_content_is_read_before_snapshots() {
+        assert_eq!(readable_content(&message("本文", &["転送元"])), "本文");
+        assert_eq!(
+            readable_content(&message("", &["", "二つ目"])),
+            "転送: 二つ目"
+        );
+        assert_eq!(readable_content(&message("", &[])), "");
+    }
+
+    #[test]
+    fn sticker_names_are_listed() {
+        assert_eq!(sticker_text(&[String::from("ねこ")]), "スタンプ: ねこ");
+        assert_eq!(
+            sticker_text(&[String::from("ねこ"), String::from("いぬ")]),
+            "スタンプ: ねこ、いぬ"
+        );
+    }
+
+    #[test]
+    fn poll_question_is_read() {
+        assert_eq!(poll_text(" 今日の夕飯は？ "), "投票: 今日の夕飯は？");
+    }
+
+    #[test]
+    fn news_takes_precedence_over_the_author_name() {
+        assert_eq!(
+            speaker_prefix(true, true, false, "alice"),
+            Some(String::from("お知らせ"))
+        );
+        assert_eq!(
+            speaker_prefix(true, false, true, "alice"),
+            Some(String::from("お知らせ"))
+        );
+        assert_eq!(
+            speaker_prefix(false, true, false, "a&b"),
+            Some(String::from("a&amp;bさんの発言"))
+        );
+        // The user's setting and a continued message both suppress the name.
+        assert_eq!(speaker_prefix(false, false, false, "alice"), None);
+        assert_eq!(speaker_prefix(false, true, true, "alice"), None);
+    }
+
+    #[test]
+    fn thread_name_is_announced_once_per_thread() {
+        let mut instance = TTSInstance::new(GuildId::new(1), ChannelId::new(2), ChannelId::new(3));
+        assert!(is_thread(ChannelType::PublicThread));
+        assert!(!is_thread(ChannelType::Text));
+
+        assert!(instance.mark_thread_announced(ChannelId::new(10)));
+        assert!(!instance.mark_thread_announced(ChannelId::new(10)));
+        assert!(instance.mark_thread_announced(ChannelId::new(11)));
+        assert_eq!(thread_name_text("雑談<1>"), "スレッド『雑談&lt;1&gt;』");
+    }
+
+    #[test]
+    fn news_is_detected_by_flags_or_channel_type() {
+        assert!(is_news(Some(MessageFlags::IS_CROSSPOST), None));
+        assert!(is_news(
+            Some(MessageFlags::CROSSPOSTED),
+            Some(ChannelType::Text)
+        ));
+        assert!(is_news(None, Some(ChannelType::News)));
+        assert!(!is_news(
+            Some(MessageFlags::empty()),
+            Some(ChannelType::Text)
+        ));
+        assert!(!is_news(Some(MessageFlags::EPHEMERAL), None));
+        assert!(!is_news(None, None));
+    }
+
+    #[test]
+    fn news_can_be_shortened_to_the_first_sentence() {
+        assert_eq!(
+            first_sentence("本日メンテナンスです。詳細は後ほど"),
+            "本日メンテナンスです。"
+        );
+        assert_eq!(
+            first_sentence("Update released! Details below"),
+            "Update released!"
+        );
+        assert_eq!(first_sentence("見出し\n本文"), "見出し");
+        assert_eq!(first_sentence("句読点なし"), "句読点なし");
+    }
+
+    #[test]
+    fn read_time_is_shifted_by_the_offset() {
+        assert_eq!(format_read_time(0, 0), "00時00分");
+        assert_eq!(format_read_time(0, 9 * 60), "09時00分");
+        assert_eq!(format_read_time(13 * 3600 + 5 * 60 + 59, 0), "13時05分");
+    }
+
+    #[test]
+    fn read_time_wraps_around_midnight() {
+        assert_eq!(format_read_time(20 * 3600, 9 * 60), "05時00分");
+        assert_eq!(format_read_time(3600, -2 * 60), "23時00分");
+        assert_eq!(format_read_time(-60, 0), "23時59分");
+    }
+
+    fn attachment(filename: &str, description: Option<&str>) -> (String, Option<String>) {
+        (
+            filename.to_string(),
+            description.map(|description| description.to_string()),
+        )
+    }
+
+    #[test]
+    fn attachments_are_read_by_description_or_filename() {
+        let text = attachment_text(&[
+            attachment("example.png", None),
+            attachment("cat.jpg", Some("寝ている猫")),
+            attachment("blank.png", Some("  ")),
+        ]);
+        assert_eq!(
+            text,
+            "ファイル: example.png、ファイル: 寝ている猫、ファイル: blank.png"
+        );
+    }
+
+    #[test]
+    fn attachment_names_are_sanitized_and_capped() {
+        let long = "あ".repeat(MAX_ATTACHMENT_NAME_LENGTH + 10);
+        let text = attachment_text(&[
+            attachment("a\nb\u{7}.txt", None),
+            attachment(&long, None),
+            attachment("c.png", None),
+            attachment("d.png", None),
+            attachment("e.png", None),
+        ]);
+        assert_eq!(
+            text,
+            format!(
+                "ファイル: ab.txt、ファイル: {}、ファイル: c.png、他2個の添付ファイル",
+                "あ".repeat(MAX_ATTACHMENT_NAME_LENGTH)
+            )
+        );
+        assert_eq!(attachment_text(&[]), "");
+    }
+
+    fn role(name: &str, hoist: bool, position: u16) -> Role {
+        let mut role = Role::default();
+        role.name = name.to_string();
+        role.hoist = hoist;
+        role.position = position;
+        role
+    }
+
+    #[test]
+    fn title_is_the_highest_hoisted_role() {
+        let roles = [
+            role("メンバー", true, 1),
+            role("管理者", true, 5),
+            role("Bot", false, 9),
+        ];
+        let title = role_title(roles.iter());
+        assert_eq!(title.as_deref(), Some("管理者"));
+        assert_eq!(name_with_title(title.as_deref(), "Alice"), "管理者のAlice");
+    }
 
-        file_path.into_os_string().into_string().unwrap()
+    #[test]
+    fn name_is_unchanged_without_a_hoisted_role() {
+        assert_eq!(role_title([].iter()), None);
+        assert_eq!(role_title([role("Bot", false, 9)].iter()), None);
+        assert_eq!(name_with_title(None, "Alice"), "Alice");
     }
 }
@@ -11,11 +11,41 @@ pub enum VoiceMoveState {
     NONE,
 }
 
+/// Self mute or deafen change of a user staying in the voice channel.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VoiceMuteChange {
+    MUTE,
+    UNMUTE,
+    DEAF,
+    UNDEAF,
+}
+
+/// Detect a self mute/deafen change of a user who stays in `target_channel`.
+/// Deafening also mutes, so a deafen change is reported instead of the mute change.
+pub fn voice_mute_change(
+    old: &Option<VoiceState>,
+    new: &VoiceState,
+    target_channel: ChannelId,
+) -> Option<VoiceMuteChange> {
+    let old = old.as_ref()?;
+    if old.channel_id != Some(target_channel) || new.channel_id != Some(target_channel) {
+        return None;
+    }
+
+    match (old.self_deaf, new.self_deaf, old.self_mute, new.self_mute) {
+        (false, true, _, _) => Some(VoiceMuteChange::DEAF),
+        (true, false, _, _) => Some(VoiceMuteChange::UNDEAF),
+        (_, _, false, true) => Some(VoiceMuteChange::MUTE),
+        (_, _, true, false) => Some(VoiceMuteChange::UNMUTE),
+        _ => None,
+    }
+}
+
 impl VoiceMoveStateTrait for VoiceState {
     fn move_state(&self, old: &Option<VoiceState>, target_channel: ChannelId) -> VoiceMoveState {
         let new = self;
 
-        if let None = old.clone() {
+        if old.clone().is_none() {
             return if target_channel == new.channel_id.unwrap() {
                 VoiceMoveState::JOIN
             } else {
@@ -50,3 +80,79 @@ impl VoiceMoveStateTrait for VoiceState {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(channel: Option<u64>, self_mute: bool, self_deaf: bool) -> VoiceState {
+        serde_json::from_value(serde_json::json!({
+            "channel_id": channel.map(|channel| channel.to_string()),
+            "deaf": false,
+            "mute": false,
+            "self_deaf": self_deaf,
+            "self_mute": self_mute,
+            "self_video": false,
+            "session_id": "session",
+            "suppress": false,
+            "user_id": "1",
+            "request_to_speak_timestamp": null,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn mute_and_deafen_changes_are_detected() {
+        let channel = ChannelId::new(10);
+        let change =
+            |old: VoiceState, new: VoiceState| voice_mute_change(&Some(old), &new, channel);
+
+        assert_eq!(
+            change(state(Some(10), false, false), state(Some(10), true, false)),
+            Some(VoiceMuteChange::MUTE)
+        );
+        assert_eq!(
+            change(state(Some(10), true, false), state(Some(10), false, false)),
+            Some(VoiceMuteChange::UNMUTE)
+        );
+        // Deafening also mutes; only the deafen is reported.
+        assert_eq!(
+            change(state(Some(10), false, false), state(Some(10), true, true)),
+            Some(VoiceMuteChange::DEAF)
+        );
+        assert_eq!(
+            change(state(Some(10), true, true), state(Some(10), false, false)),
+            Some(VoiceMuteChange::UNDEAF)
+        );
+        assert_eq!(
+            change(state(Some(10), true, false), state(Some(10), true, false)),
+            None
+        );
+    }
+
+    #[test]
+    fn changes_outside_the_channel_are_ignored() {
+        let channel = ChannelId::new(10);
+        assert_eq!(
+            voice_mute_change(&None, &state(Some(10), true, false), channel),
+            None
+        );
+        assert_eq!(
+            voice_mute_change(
+                &Some(state(Some(20), false, false)),
+                &state(Some(20), true, false),
+                channel
+            ),
+            None
+        );
+        // Joining while muted is a join, not a mute.
+        assert_eq!(
+            voice_mute_change(
+                &Some(state(None, false, false)),
+                &state(Some(10), true, false),
+                channel
+            ),
+            None
+        );
+    }
+}
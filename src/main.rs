@@ -1,3 +1,5 @@
+#![allow(clippy::module_inception, clippy::upper_case_acronyms)]
+
 mod commands;
 mod config;
 mod data;
@@ -6,20 +8,21 @@ mod event_handler;
 mod events;
 mod implement;
 mod tts;
+mod utils;
 
 use std::{collections::HashMap, env, sync::Arc};
 
 use config::Config;
-use data::{DatabaseClientData, TTSClientData, TTSData};
+use data::{ConfigData, DatabaseClientData, ShardManagerData, TTSClientData, TTSData};
 use database::database::Database;
 use event_handler::Handler;
 use serenity::{
     client::Client,
-    framework::StandardFramework,
     futures::lock::Mutex,
+    model::id::ApplicationId,
     prelude::{GatewayIntents, RwLock},
 };
-use tts::{gcp_tts::gcp_tts::TTS, voicevox::voicevox::VOICEVOX};
+use tts::{azure::azure::AzureTTS, gcp_tts::gcp_tts::TTS, voicevox::voicevox::VOICEVOX};
 
 use songbird::SerenityInit;
 
@@ -27,21 +30,54 @@ use songbird::SerenityInit;
 ///
 /// Example:
 /// ```rust
-/// let client = create_client("!", "BOT_TOKEN", 123456789123456789).await;
+/// let client = create_client("BOT_TOKEN", 123456789123456789).await;
 ///
 /// client.start().await;
 /// ```
-async fn create_client(prefix: &str, token: &str, id: u64) -> Result<Client, serenity::Error> {
-    let framework = StandardFramework::new().configure(|c| c.with_whitespace(true).prefix(prefix));
-
+async fn create_client(token: &str, id: u64) -> Result<Client, serenity::Error> {
     Client::builder(token, GatewayIntents::all())
         .event_handler(Handler)
-        .application_id(id)
-        .framework(framework)
+        .application_id(ApplicationId::new(id))
         .register_songbird()
         .await
 }
 
+/// Validate the config, Redis connection and GCP credentials without starting the client.
+/// Returns true when no problem is found.
+fn check_config(config: &Config) -> bool {
+    let mut problems = config.validate();
+
+    if let Ok(client) = redis::Client::open(config.redis_url.clone()) {
+        match client.get_connection() {
+            Ok(mut connection) => {
+                if let Err(err) = redis::cmd("PING").query::<String>(&mut connection) {
+                    problems.push(format!("Redis ping failed: {}", err));
+                }
+            }
+            Err(err) => problems.push(format!("Cannot connect to Redis: {}", err)),
+        }
+    }
+
+    match std::fs::read_to_string("./credentials.json") {
+        Ok(credentials) => {
+            if let Err(err) = serde_json::from_str::<serde_json::Value>(&credentials) {
+                problems.push(format!("credentials.json is invalid: {}", err));
+            }
+        }
+        Err(err) => problems.push(format!("Cannot read credentials.json: {}", err)),
+    }
+
+    if problems.is_empty() {
+        println!("Config OK");
+    } else {
+        for problem in &problems {
+            println!("Config error: {}", problem);
+        }
+    }
+
+    problems.is_empty()
+}
+
 #[tokio::main]
 async fn main() {
     // Load config
@@ -52,22 +88,34 @@ async fn main() {
         } else {
             let token = env::var("NCB_TOKEN").unwrap();
             let application_id = env::var("NCB_APP_ID").unwrap();
-            let prefix = env::var("NCB_PREFIX").unwrap();
             let redis_url = env::var("NCB_REDIS_URL").unwrap();
             let voicevox_key = env::var("NCB_VOICEVOX_KEY").unwrap();
 
             Config {
                 token,
-                application_id: u64::from_str_radix(&application_id, 10).unwrap(),
-                prefix,
+                application_id: application_id.parse::<u64>().unwrap(),
                 redis_url,
                 voicevox_key,
+                dictionary_blacklist: None,
+                default_server_config: None,
+                daily_char_limit: None,
+                redis_key_prefix: env::var("NCB_REDIS_KEY_PREFIX").ok(),
+                azure_region: env::var("NCB_AZURE_REGION").ok(),
+                azure_key: env::var("NCB_AZURE_KEY").ok(),
+                feedback_url: env::var("NCB_FEEDBACK_URL").ok(),
+                max_active_instances: env::var("NCB_MAX_ACTIVE_INSTANCES")
+                    .ok()
+                    .and_then(|max| max.parse().ok()),
             }
         }
     };
 
+    if env::args().any(|arg| arg == "--check-config") {
+        std::process::exit(if check_config(&config) { 0 } else { 1 });
+    }
+
     // Create discord client
-    let mut client = create_client(&config.prefix, &config.token, config.application_id)
+    let mut client = create_client(&config.token, config.application_id)
         .await
         .expect("Err creating client");
 
@@ -77,19 +125,30 @@ async fn main() {
         Err(err) => panic!("GCP init error: {}", err),
     };
 
-    let voicevox = VOICEVOX::new(config.voicevox_key);
+    let voicevox = VOICEVOX::new(config.voicevox_key.clone());
+
+    let azure = match (config.azure_region.clone(), config.azure_key.clone()) {
+        (Some(region), Some(key)) => Some(AzureTTS::new(region, key)),
+        _ => None,
+    };
 
     let database_client = {
-        let redis_client = redis::Client::open(config.redis_url).unwrap();
-        Database::new(redis_client)
+        let redis_client = redis::Client::open(config.redis_url.clone()).unwrap();
+        Database::new(
+            redis_client,
+            config.server_config_default(),
+            config.redis_key_prefix.clone().unwrap_or_default(),
+        )
     };
 
     // Create TTS storage
     {
         let mut data = client.data.write().await;
         data.insert::<TTSData>(Arc::new(RwLock::new(HashMap::default())));
-        data.insert::<TTSClientData>(Arc::new(Mutex::new((tts, voicevox))));
+        data.insert::<TTSClientData>(Arc::new(Mutex::new((tts, voicevox, azure))));
         data.insert::<DatabaseClientData>(Arc::new(Mutex::new(database_client)));
+        data.insert::<ConfigData>(Arc::new(config));
+        data.insert::<ShardManagerData>(client.shard_manager.clone());
     }
 
     // Run client
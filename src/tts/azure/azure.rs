@@ -0,0 +1,166 @@
+use std::time::{Duration, Instant};
+
+use crate::tts::ssml;
+
+/// Azure access tokens are valid for 10 minutes. Refresh a little earlier.
+const TOKEN_LIFETIME: Duration = Duration::from_secs(9 * 60);
+
+const OUTPUT_FORMAT: &str = "audio-24khz-48kbitrate-mono-mp3";
+
+/// Voice used when the user has not selected an Azure voice.
+pub const DEFAULT_AZURE_VOICE: &str = "ja-JP-NanamiNeural";
+
+/// Longest Azure voice name accepted from users.
+pub const MAX_AZURE_VOICE_LENGTH: usize = 100;
+
+#[derive(Clone)]
+pub struct AzureTTS {
+    pub region: String,
+    pub subscription_key: String,
+    /// Access token and the time it was issued.
+    pub token: Option<(String, Instant)>,
+}
+
+/// Whether a token issued at `issued` must be refreshed at `now`.
+pub fn token_expired(issued: Instant, now: Instant) -> bool {
+    now.duration_since(issued) >= TOKEN_LIFETIME
+}
+
+/// Whether the name looks like an Azure voice name such as "ja-JP-NanamiNeural":
+/// a locale and a name separated by hyphens, using only ASCII letters and digits.
+pub fn is_valid_voice_name(name: &str) -> bool {
+    let parts = name.split('-').collect::<Vec<_>>();
+    name.len() <= MAX_AZURE_VOICE_LENGTH
+        && parts.len() >= 3
+        && parts
+            .iter()
+            .all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_alphanumeric()))
+}
+
+/// Wrap an SSML fragment in the speak and voice elements Azure requires.
+///
+/// Example:
+/// ```rust
+/// let body = wrap_ssml("こんにちは", "ja-JP-NanamiNeural");
+/// ```
+pub fn wrap_ssml(text: &str, voice: &str) -> String {
+    format!(
+        "<speak version=\"1.0\" xml:lang=\"ja-JP\"><voice name=\"{}\">{}</voice></speak>",
+        ssml::escape(voice),
+        text
+    )
+}
+
+impl AzureTTS {
+    pub fn new(region: String, subscription_key: String) -> Self {
+        Self {
+            region,
+            subscription_key,
+            token: None,
+        }
+    }
+
+    pub async fn update_token(&mut self) -> Result<(), reqwest::Error> {
+        if let Some((_, issued)) = &self.token {
+            if !token_expired(*issued, Instant::now()) {
+                return Ok(());
+            }
+        }
+
+        let client = reqwest::Client::new();
+        let token = client
+            .post(format!(
+                "https://{}.api.cognitive.microsoft.com/sts/v1.0/issueToken",
+                self.region
+            ))
+            .header("Ocp-Apim-Subscription-Key", self.subscription_key.clone())
+            .header(reqwest::header::CONTENT_LENGTH, 0)
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        self.token = Some((token, Instant::now()));
+
+        Ok(())
+    }
+
+    /// Synthesize an SSML fragment with the voice and return the audio data.
+    ///
+    /// Example:
+    /// ```rust
+    /// let audio = azure.synthesize("テスト", "ja-JP-NanamiNeural").await.unwrap();
+    /// ```
+    pub async fn synthesize(
+        &mut self,
+        text: &str,
+        voice: &str,
+    ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        self.update_token().await?;
+        let token = self.token.as_ref().map(|(token, _)| token.clone()).unwrap();
+
+        let client = reqwest::Client::new();
+        let response = client
+            .post(format!(
+                "https://{}.tts.speech.microsoft.com/cognitiveservices/v1",
+                self.region
+            ))
+            .header(reqwest::header::CONTENT_TYPE, "application/ssml+xml")
+            .header(reqwest::header::AUTHORIZATION, format!("Bearer {}", token))
+            .header("X-Microsoft-OutputFormat", OUTPUT_FORMAT)
+            .header(reqwest::header::USER_AGENT, "ncb-tts-r2")
+            .body(wrap_ssml(text, voice))
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(response.bytes().await?.to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn token_expires_after_its_lifetime() {
+        let issued = Instant::now();
+        assert!(!token_expired(issued, issued));
+        assert!(!token_expired(
+            issued,
+            issued + TOKEN_LIFETIME - Duration::from_secs(1)
+        ));
+        assert!(token_expired(issued, issued + TOKEN_LIFETIME));
+        assert!(token_expired(issued, issued + Duration::from_secs(60 * 60)));
+    }
+
+    #[test]
+    fn wrap_ssml_adds_speak_and_voice() {
+        assert_eq!(
+            wrap_ssml(r#"あ<break time="200ms"/>い"#, DEFAULT_AZURE_VOICE),
+            r#"<speak version="1.0" xml:lang="ja-JP"><voice name="ja-JP-NanamiNeural">あ<break time="200ms"/>い</voice></speak>"#
+        );
+    }
+
+    #[test]
+    fn wrap_ssml_escapes_the_voice_name() {
+        assert_eq!(
+            wrap_ssml("a", r#"x"/><audio src="y"#),
+            r#"<speak version="1.0" xml:lang="ja-JP"><voice name="x&quot;/&gt;&lt;audio src=&quot;y">a</voice></speak>"#
+        );
+    }
+
+    #[test]
+    fn voice_names_are_checked() {
+        assert!(is_valid_voice_name("ja-JP-NanamiNeural"));
+        assert!(is_valid_voice_name("zh-CN-henan-YundengNeural"));
+        assert!(!is_valid_voice_name(""));
+        assert!(!is_valid_voice_name("NanamiNeural"));
+        assert!(!is_valid_voice_name("ja-JP-"));
+        assert!(!is_valid_voice_name("ja-JP-Nanami Neural"));
+        assert!(!is_valid_voice_name(&format!(
+            "ja-JP-{}",
+            "a".repeat(MAX_AZURE_VOICE_LENGTH)
+        )));
+    }
+}
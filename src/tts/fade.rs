@@ -0,0 +1,125 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use songbird::{
+    events::{Event, EventContext, EventHandler},
+    input::Input,
+    tracks::TrackHandle,
+};
+
+/// Number of volume steps used for each fade.
+const FADE_STEPS: u32 = 5;
+
+/// Upper bound for the fade length.
+const MAX_FADE_MS: u64 = 1000;
+
+/// Compute the volume envelope for a track.
+/// Returns (position, volume) pairs. The fade-out is only added when the
+/// track duration is known and long enough to hold both fades.
+///
+/// Example:
+/// ```rust
+/// let steps = fade_steps(100, Some(Duration::from_secs(3)));
+/// ```
+pub fn fade_steps(fade_ms: u64, duration: Option<Duration>) -> Vec<(Duration, f32)> {
+    let fade = Duration::from_millis(fade_ms.min(MAX_FADE_MS));
+    if fade.is_zero() {
+        return vec![];
+    }
+
+    let step = fade / FADE_STEPS;
+    let mut steps: Vec<(Duration, f32)> = (1..=FADE_STEPS)
+        .map(|i| (step * i, i as f32 / FADE_STEPS as f32))
+        .collect();
+
+    if let Some(duration) = duration {
+        if duration > fade * 2 {
+            let start = duration - fade;
+            steps.extend(
+                (1..=FADE_STEPS).map(|i| (start + step * i, 1.0 - i as f32 / FADE_STEPS as f32)),
+            );
+        }
+    }
+
+    steps
+}
+
+/// Length of a parsed input, when the container reports it.
+pub fn input_duration(input: &Input) -> Option<Duration> {
+    let track = input.parsed()?.format.default_track()?;
+    let frames = track.codec_params.n_frames?;
+    let sample_rate = track.codec_params.sample_rate?;
+    Some(Duration::from_secs_f64(frames as f64 / sample_rate as f64))
+}
+
+/// Apply fade-in/out to an enqueued track.
+pub fn apply_fade(handle: &TrackHandle, fade_ms: u64, duration: Option<Duration>) {
+    let steps = fade_steps(fade_ms, duration);
+    if steps.is_empty() {
+        return;
+    }
+
+    let _ = handle.set_volume(0.0);
+    for (position, volume) in steps {
+        let _ = handle.add_event(
+            Event::Delayed(position),
+            VolumeStep {
+                handle: handle.clone(),
+                volume,
+            },
+        );
+    }
+}
+
+struct VolumeStep {
+    handle: TrackHandle,
+    volume: f32,
+}
+
+#[async_trait]
+impl EventHandler for VolumeStep {
+    async fn act(&self, _ctx: &EventContext<'_>) -> Option<Event> {
+        let _ = self.handle.set_volume(self.volume);
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_steps_without_fade() {
+        assert!(fade_steps(0, Some(Duration::from_secs(3))).is_empty());
+    }
+
+    #[test]
+    fn fades_in_and_out_for_long_tracks() {
+        let steps = fade_steps(100, Some(Duration::from_secs(3)));
+        assert_eq!(steps.len(), FADE_STEPS as usize * 2);
+        assert_eq!(steps[0], (Duration::from_millis(20), 0.2));
+        assert_eq!(
+            steps[FADE_STEPS as usize - 1],
+            (Duration::from_millis(100), 1.0)
+        );
+        assert_eq!(steps.last(), Some(&(Duration::from_secs(3), 0.0)));
+    }
+
+    #[test]
+    fn only_fades_in_for_short_or_unknown_tracks() {
+        assert_eq!(
+            fade_steps(100, Some(Duration::from_millis(150))).len(),
+            FADE_STEPS as usize
+        );
+        assert_eq!(fade_steps(100, None).len(), FADE_STEPS as usize);
+    }
+
+    #[test]
+    fn fade_length_is_capped() {
+        let steps = fade_steps(10_000, None);
+        assert_eq!(
+            steps.last(),
+            Some(&(Duration::from_millis(MAX_FADE_MS), 1.0))
+        );
+    }
+}
@@ -1,40 +1,78 @@
 use crate::tts::gcp_tts::structs::{
-    synthesize_request::SynthesizeRequest, synthesize_response::SynthesizeResponse,
+    synthesize_request::SynthesizeRequest,
+    synthesize_response::SynthesizeResponse,
+    voice::{ListVoicesResponse, Voice},
 };
-use gcp_auth::Token;
+use base64::Engine;
+use gcp_auth::{CustomServiceAccount, Token, TokenProvider};
+use std::{sync::Arc, time::SystemTime};
+
+/// OAuth scope used for Cloud Text-to-Speech.
+const SCOPES: &[&str] = &["https://www.googleapis.com/auth/cloud-platform"];
 
 #[derive(Clone)]
 pub struct TTS {
-    pub token: Token,
+    pub token: Arc<Token>,
     pub credentials_path: String,
+    /// When the current token was fetched.
+    pub token_updated_at: SystemTime,
+    /// Voice list, fetched on first use.
+    pub voices: Option<Vec<Voice>>,
 }
 
 impl TTS {
     pub async fn update_token(&mut self) -> Result<(), gcp_auth::Error> {
         if self.token.has_expired() {
-            let authenticator =
-                gcp_auth::from_credentials_file(self.credentials_path.clone()).await?;
-            let token = authenticator
-                .get_token(&["https://www.googleapis.com/auth/cloud-platform"])
-                .await?;
-            self.token = token;
+            self.refresh_token().await?;
         }
 
         Ok(())
     }
 
+    /// Fetch a new token even if the current one is still valid.
+    pub async fn refresh_token(&mut self) -> Result<(), gcp_auth::Error> {
+        let authenticator = CustomServiceAccount::from_file(&self.credentials_path)?;
+        let token = authenticator.token(SCOPES).await?;
+        self.token = token;
+        self.token_updated_at = SystemTime::now();
+
+        Ok(())
+    }
+
     pub async fn new(credentials_path: String) -> Result<TTS, gcp_auth::Error> {
-        let authenticator = gcp_auth::from_credentials_file(credentials_path.clone()).await?;
-        let token = authenticator
-            .get_token(&["https://www.googleapis.com/auth/cloud-platform"])
-            .await?;
+        let authenticator = CustomServiceAccount::from_file(&credentials_path)?;
+        let token = authenticator.token(SCOPES).await?;
 
         Ok(TTS {
             token,
             credentials_path,
+            token_updated_at: SystemTime::now(),
+            voices: None,
         })
     }
 
+    /// Available voices. The list is fetched once and cached.
+    pub async fn get_voices(&mut self) -> Result<Vec<Voice>, Box<dyn std::error::Error>> {
+        if let Some(voices) = &self.voices {
+            return Ok(voices.clone());
+        }
+
+        self.update_token().await?;
+        let response: ListVoicesResponse = reqwest::Client::new()
+            .get("https://texttospeech.googleapis.com/v1/voices")
+            .header(
+                reqwest::header::AUTHORIZATION,
+                format!("Bearer {}", self.token.as_str()),
+            )
+            .send()
+            .await?
+            .json()
+            .await?;
+        self.voices = Some(response.voices.clone());
+
+        Ok(response.voices)
+    }
+
     /// Synthesize text to speech and return the audio data.
     ///
     /// Example:
@@ -52,7 +90,8 @@ impl TTS {
     ///    audioConfig: AudioConfig {
     ///        audioEncoding: String::from("mp3"),
     ///        speakingRate: 1.2f32,
-    ///        pitch: 1.0f32
+    ///        pitch: 1.0f32,
+    ///        effectsProfileId: None
     ///    }
     /// }).await.unwrap();
     /// ```
@@ -60,7 +99,7 @@ impl TTS {
         &mut self,
         request: SynthesizeRequest,
     ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-        self.update_token().await.unwrap();
+        self.update_token().await?;
         let client = reqwest::Client::new();
         match client
             .post("https://texttospeech.googleapis.com/v1/text:synthesize")
@@ -76,7 +115,7 @@ impl TTS {
             Ok(ok) => {
                 let response: SynthesizeResponse =
                     serde_json::from_str(&ok.text().await.expect("")).unwrap();
-                Ok(base64::decode(response.audioContent).unwrap()[..].to_vec())
+                Ok(base64::engine::general_purpose::STANDARD.decode(response.audioContent)?)
             }
             Err(err) => Err(Box::new(err)),
         }
@@ -1,11 +1,32 @@
 use serde::{Deserialize, Serialize};
 
+/// Effects profiles that can be selected in /config.
+pub const EFFECTS_PROFILES: &[&str] = &[
+    "headphone-class-device",
+    "small-bluetooth-speaker-class-device",
+    "large-home-entertainment-class-device",
+    "telephony-class-application",
+];
+
+/// Profile after `current` in EFFECTS_PROFILES. Cycles back to None after the last one.
+pub fn next_effects_profile(current: Option<&str>) -> Option<String> {
+    let next = match current {
+        None => 0,
+        Some(current) => match EFFECTS_PROFILES.iter().position(|p| *p == current) {
+            Some(i) => i + 1,
+            None => 0,
+        },
+    };
+    EFFECTS_PROFILES.get(next).map(|p| p.to_string())
+}
+
 /// Example:
 /// ```rust
 /// AudioConfig {
 ///     audioEncoding: String::from("mp3"),
 ///     speakingRate: 1.2f32,
-///     pitch: 1.0f32
+///     pitch: 1.0f32,
+///     effectsProfileId: None
 /// }
 /// ```
 #[derive(Serialize, Deserialize, Debug)]
@@ -14,4 +35,49 @@ pub struct AudioConfig {
     pub audioEncoding: String,
     pub speakingRate: f32,
     pub pitch: f32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub effectsProfileId: Option<Vec<String>>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn audio_config(effects_profile: Option<&str>) -> AudioConfig {
+        AudioConfig {
+            audioEncoding: String::from("mp3"),
+            speakingRate: 1.0,
+            pitch: 0.0,
+            effectsProfileId: effects_profile.map(|profile| vec![profile.to_string()]),
+        }
+    }
+
+    #[test]
+    fn effects_profile_is_omitted_when_unset() {
+        let json = serde_json::to_value(audio_config(None)).unwrap();
+        assert!(json.get("effectsProfileId").is_none());
+        assert_eq!(json["audioEncoding"], "mp3");
+    }
+
+    #[test]
+    fn effects_profile_is_serialized_as_an_array() {
+        let json = serde_json::to_value(audio_config(Some("telephony-class-application"))).unwrap();
+        assert_eq!(
+            json["effectsProfileId"],
+            serde_json::json!(["telephony-class-application"])
+        );
+    }
+
+    #[test]
+    fn effects_profiles_cycle_back_to_none() {
+        assert_eq!(
+            next_effects_profile(None).as_deref(),
+            Some(EFFECTS_PROFILES[0])
+        );
+        assert_eq!(
+            next_effects_profile(Some(EFFECTS_PROFILES[0])).as_deref(),
+            Some(EFFECTS_PROFILES[1])
+        );
+        assert_eq!(next_effects_profile(EFFECTS_PROFILES.last().copied()), None);
+    }
 }
@@ -19,7 +19,8 @@ use serde::{Deserialize, Serialize};
 ///     audioConfig: AudioConfig {
 ///         audioEncoding: String::from("mp3"),
 ///         speakingRate: 1.2f32,
-///         pitch: 1.0f32
+///         pitch: 1.0f32,
+///         effectsProfileId: None
 ///     }
 /// }
 /// ```
@@ -0,0 +1,136 @@
+use serde::{Deserialize, Serialize};
+
+use super::voice_selection_params::VoiceSelectionParams;
+
+/// A voice returned by the `voices` endpoint.
+///
+/// Example:
+/// ```rust
+/// Voice {
+///     languageCodes: vec![String::from("ja-JP")],
+///     name: String::from("ja-JP-Neural2-C"),
+///     ssmlGender: String::from("MALE"),
+///     naturalSampleRateHertz: 24000
+/// }
+/// ```
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[allow(non_snake_case)]
+pub struct Voice {
+    pub languageCodes: Vec<String>,
+    pub name: String,
+    pub ssmlGender: String,
+    pub naturalSampleRateHertz: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ListVoicesResponse {
+    #[serde(default)]
+    pub voices: Vec<Voice>,
+}
+
+/// Maximum number of suggestions for an unknown voice name.
+const MAX_VOICE_SUGGESTIONS: usize = 5;
+
+/// Find the voice by name, ignoring case, and build the selection params from its metadata.
+/// Returns similar voice names when it is not found, and no suggestions for an empty name.
+///
+/// Example:
+/// ```rust
+/// let params = resolve_voice(&voices, "ja-jp-neural2-c").unwrap();
+/// assert_eq!(params.languageCode, "ja-JP");
+/// assert_eq!(params.ssmlGender, "MALE");
+/// ```
+pub fn resolve_voice(voices: &[Voice], name: &str) -> Result<VoiceSelectionParams, Vec<String>> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(vec![]);
+    }
+    if let Some(voice) = voices
+        .iter()
+        .find(|voice| voice.name.eq_ignore_ascii_case(name))
+    {
+        return Ok(VoiceSelectionParams {
+            languageCode: voice
+                .languageCodes
+                .first()
+                .cloned()
+                .unwrap_or_else(|| language_of(&voice.name)),
+            name: voice.name.clone(),
+            ssmlGender: voice.ssmlGender.clone(),
+        });
+    }
+
+    // Suggest voices of the same language, or the same type such as "Neural2".
+    let lower = name.to_lowercase();
+    let language = language_of(&lower);
+    let kind = lower.split('-').nth(2).unwrap_or_default().to_string();
+    Err(voices
+        .iter()
+        .map(|voice| (voice.name.to_lowercase(), &voice.name))
+        .filter(|(voice, _)| {
+            voice.starts_with(&language) || (!kind.is_empty() && voice.contains(&kind))
+        })
+        .take(MAX_VOICE_SUGGESTIONS)
+        .map(|(_, name)| name.clone())
+        .collect())
+}
+
+/// Language code at the start of a voice name, e.g. "ja-JP" for "ja-JP-Neural2-C".
+fn language_of(name: &str) -> String {
+    name.splitn(3, '-').take(2).collect::<Vec<_>>().join("-")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn voice(name: &str, gender: &str) -> Voice {
+        Voice {
+            languageCodes: vec![language_of(name)],
+            name: name.to_string(),
+            ssmlGender: gender.to_string(),
+            naturalSampleRateHertz: 24000,
+        }
+    }
+
+    fn voices() -> Vec<Voice> {
+        vec![
+            voice("ja-JP-Neural2-B", "FEMALE"),
+            voice("ja-JP-Neural2-C", "MALE"),
+            voice("en-US-Neural2-A", "MALE"),
+            voice("en-US-Wavenet-A", "MALE"),
+        ]
+    }
+
+    #[test]
+    fn resolve_voice_ignores_case_and_whitespace() {
+        let params = resolve_voice(&voices(), " ja-jp-neural2-c ").unwrap();
+        assert_eq!(params.name, "ja-JP-Neural2-C");
+        assert_eq!(params.languageCode, "ja-JP");
+        assert_eq!(params.ssmlGender, "MALE");
+    }
+
+    #[test]
+    fn resolve_voice_suggests_similar_voices() {
+        assert_eq!(
+            resolve_voice(&voices(), "ja-JP-Neural2-Z").unwrap_err(),
+            vec!["ja-JP-Neural2-B", "ja-JP-Neural2-C", "en-US-Neural2-A"]
+        );
+        assert_eq!(
+            resolve_voice(&voices(), "fr-FR-Studio-A").unwrap_err(),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn resolve_voice_rejects_empty_names() {
+        assert_eq!(
+            resolve_voice(&voices(), "").unwrap_err(),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            resolve_voice(&voices(), "   ").unwrap_err(),
+            Vec::<String>::new()
+        );
+    }
+}
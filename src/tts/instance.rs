@@ -1,49 +1,694 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fmt, fs,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+
+use async_trait::async_trait;
+
 use serenity::{
+    futures::lock::Mutex as FuturesMutex,
     model::{
-        channel::Message,
-        id::{ChannelId, GuildId},
+        channel::{Channel, Message},
+        id::{ChannelId, GuildId, UserId},
+        Permissions,
+    },
+    prelude::{Context, Mutex},
+};
+use songbird::{
+    events::{Event, EventContext, EventHandler},
+    input::{
+        codecs::{CODEC_REGISTRY, PROBE},
+        File, Input,
     },
-    prelude::Context,
+    tracks::TrackHandle,
+    Call, Songbird, TrackEvent,
 };
+use uuid::Uuid;
+
+use crate::{
+    data::{DatabaseClientData, TTSClientData},
+    database::{database::utc_day, server_config::ServerConfig},
+    implement::voice_move_state::VoiceMoveState,
+    tts::{
+        fade::{apply_fade, input_duration},
+        message::{
+            engine_clients, log_dropped_message, synthesize_with_fallback, write_audio_file,
+            DropReason, TTSMessage,
+        },
+        ssml,
+    },
+};
+
+#[derive(Debug)]
+pub enum CallError {
+    ManagerNotFound,
+    CallNotFound,
+}
+
+impl fmt::Display for CallError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CallError::ManagerNotFound => write!(f, "songbird manager is not registered"),
+            CallError::CallNotFound => write!(f, "not connected to a voice channel"),
+        }
+    }
+}
+
+impl std::error::Error for CallError {}
+
+/// Resolve the songbird call for the guild.
+///
+/// Example:
+/// ```rust
+/// let call = get_call(&ctx, guild_id).await?;
+/// ```
+pub async fn get_call(ctx: &Context, guild: GuildId) -> Result<Arc<Mutex<Call>>, CallError> {
+    resolve_call(songbird::get(ctx).await, guild)
+}
 
-use crate::tts::message::TTSMessage;
+/// Find the guild's call in the songbird manager, if one is registered.
+fn resolve_call(
+    manager: Option<Arc<Songbird>>,
+    guild: GuildId,
+) -> Result<Arc<Mutex<Call>>, CallError> {
+    let manager = manager.ok_or(CallError::ManagerNotFound)?;
+    manager.get(guild).ok_or(CallError::CallNotFound)
+}
+
+/// Whether the event reverses the previous one within the window.
+pub fn is_bounce(
+    last: Option<(VoiceMoveState, Instant)>,
+    state: VoiceMoveState,
+    now: Instant,
+    window: Duration,
+) -> bool {
+    match last {
+        Some((last_state, at)) => last_state != state && now.duration_since(at) < window,
+        None => false,
+    }
+}
+
+/// Record a read at `now` if fewer than `max_per_minute` reads happened in the minute before.
+/// Reads older than a minute are dropped from `recent`. Returns false when the read should be skipped.
+pub fn record_read(recent: &mut VecDeque<Instant>, now: Instant, max_per_minute: u32) -> bool {
+    while let Some(oldest) = recent.front() {
+        if now.duration_since(*oldest) < Duration::from_secs(60) {
+            break;
+        }
+        recent.pop_front();
+    }
+
+    if recent.len() >= max_per_minute as usize {
+        return false;
+    }
+
+    recent.push_back(now);
+    true
+}
+
+/// Text logged when chunks of a message could not be synthesized with any voice.
+/// A message is counted as dropped once, however many of its chunks failed.
+fn dropped_text(failed_chunks: &[String]) -> Option<String> {
+    if failed_chunks.is_empty() {
+        None
+    } else {
+        Some(failed_chunks.join(" "))
+    }
+}
+
+/// Whether no more instances can be started.
+/// There is no limit when `max` is None.
+pub fn at_capacity(active: usize, max: Option<usize>) -> bool {
+    max.is_some_and(|max| active >= max)
+}
+
+/// Number of distinct messages among the queued tracks.
+/// Tracks without a known message are counted one each.
+pub fn count_queued_messages(tracks: &[Uuid], track_messages: &HashMap<Uuid, u64>) -> usize {
+    let mut seen = HashSet::new();
+    tracks
+        .iter()
+        .filter(|track| match track_messages.get(track) {
+            Some(message) => seen.insert(*message),
+            None => true,
+        })
+        .count()
+}
+
+/// Log line for an audio file that could not be played.
+/// The file has already been deleted when this is logged.
+pub fn playback_error_message(path: &str, guild_id: u64, err: &impl std::fmt::Debug) -> String {
+    format!(
+        "Cannot play audio {} in guild {}: {:?} (file removed)",
+        path, guild_id, err
+    )
+}
+
+/// Notice posted when the bot's permissions in the voice channel lack Speak.
+/// Unknown permissions are assumed to allow speaking.
+pub fn speak_permission_notice(
+    permissions: Option<Permissions>,
+    voice_channel: ChannelId,
+) -> Option<String> {
+    if permissions.is_none_or(|permissions| permissions.speak()) {
+        return None;
+    }
+    Some(format!(
+        "<#{}> で発言する権限がないため読み上げできません．Botに「発言」権限を付与してください．",
+        voice_channel.get()
+    ))
+}
+
+/// Delete a synthesized audio file once its track has finished.
+struct RemoveAudioFile {
+    path: String,
+}
+
+#[async_trait]
+impl EventHandler for RemoveAudioFile {
+    async fn act(&self, _ctx: &EventContext<'_>) -> Option<Event> {
+        if let Err(err) = fs::remove_file(&self.path) {
+            println!("Cannot remove audio file {}: {:?}", self.path, err);
+        }
+        None
+    }
+}
 
 pub struct TTSInstance {
     pub before_message: Option<Message>,
     pub text_channel: ChannelId,
+    /// Channels read in addition to `text_channel`.
+    pub extra_text_channels: Vec<ChannelId>,
     pub voice_channel: ChannelId,
     pub guild: GuildId,
+    pub announced_threads: HashSet<ChannelId>,
+    /// Times of the reads within the last minute, oldest first.
+    pub recent_reads: VecDeque<Instant>,
+    /// Users whose messages are not read until unmuted or the instance stops.
+    pub muted_users: HashSet<UserId>,
+    /// Last join/leave of each user, used to debounce announcements.
+    pub last_voice_events: HashMap<UserId, (VoiceMoveState, Instant)>,
+    /// Whether a playback failure was already reported to the text channel.
+    pub playback_error_notified: bool,
+    /// Held while a message is read so its chunks stay together in the queue.
+    pub read_queue: Arc<Mutex<()>>,
+    /// Message each queued track belongs to, used to count skipped messages.
+    pub track_messages: HashMap<Uuid, u64>,
+    /// Number given to the next read message.
+    pub next_message_id: u64,
 }
 
 impl TTSInstance {
-    /// Synthesize text to speech and send it to the voice channel.
+    pub fn new(guild: GuildId, text_channel: ChannelId, voice_channel: ChannelId) -> Self {
+        Self {
+            before_message: None,
+            text_channel,
+            extra_text_channels: Vec::new(),
+            voice_channel,
+            guild,
+            announced_threads: HashSet::new(),
+            recent_reads: VecDeque::new(),
+            muted_users: HashSet::new(),
+            last_voice_events: HashMap::new(),
+            playback_error_notified: false,
+            read_queue: Arc::new(Mutex::new(())),
+            track_messages: HashMap::new(),
+            next_message_id: 0,
+        }
+    }
+
+    /// Whether messages in the channel are read by this instance.
+    pub fn contains_text_channel(&self, channel: ChannelId) -> bool {
+        self.text_channel == channel || self.extra_text_channels.contains(&channel)
+    }
+
+    /// Read messages in the channel as well.
+    /// Returns false when the channel is already read.
+    pub fn add_text_channel(&mut self, channel: ChannelId) -> bool {
+        if self.contains_text_channel(channel) {
+            return false;
+        }
+        self.extra_text_channels.push(channel);
+        true
+    }
+
+    /// Stop reading a channel added with `add_text_channel`.
+    /// Returns false when the channel was not added. The primary `text_channel` is never removed.
+    pub fn remove_text_channel(&mut self, channel: ChannelId) -> bool {
+        let len = self.extra_text_channels.len();
+        self.extra_text_channels.retain(|c| *c != channel);
+        self.extra_text_channels.len() != len
+    }
+
+    /// Whether the user's messages are currently not read.
+    pub fn is_muted(&self, user: UserId) -> bool {
+        self.muted_users.contains(&user)
+    }
+
+    /// Stop reading the user's messages.
+    /// Returns false when the user was already muted.
+    pub fn mute_user(&mut self, user: UserId) -> bool {
+        self.muted_users.insert(user)
+    }
+
+    /// Read the user's messages again.
+    /// Returns false when the user was not muted.
+    pub fn unmute_user(&mut self, user: UserId) -> bool {
+        self.muted_users.remove(&user)
+    }
+
+    /// Record that the thread's name has been read in this session.
+    /// Returns false when it already was, so each thread is announced once.
+    pub fn mark_thread_announced(&mut self, thread: ChannelId) -> bool {
+        self.announced_threads.insert(thread)
+    }
+
+    /// Record a join/leave and decide whether to announce it.
+    /// The event is suppressed when the user did the opposite within `window`.
+    pub fn should_announce(
+        &mut self,
+        user: UserId,
+        state: VoiceMoveState,
+        window: Duration,
+    ) -> bool {
+        let now = Instant::now();
+        let last = self.last_voice_events.insert(user, (state, now));
+        !is_bounce(last, state, now, window)
+    }
+
+    /// Record a read if fewer than `max_per_minute` reads happened in the last minute.
+    /// Returns false when the read should be skipped.
+    pub fn try_record_read(&mut self, max_per_minute: u32) -> bool {
+        record_read(&mut self.recent_reads, Instant::now(), max_per_minute)
+    }
+
+    /// Synthesize the message and send it to the voice channel.
+    /// Each chunk is enqueued as soon as it is synthesized, and the instance is only
+    /// locked while preparing and enqueueing, not during synthesis.
+    /// Returns the handles of the enqueued tracks in playback order.
     ///
     /// Example:
     /// ```rust
-    /// instance.read(message, &ctx).await;
+    /// TTSInstance::read(&instance, message, &ctx, &config).await;
     /// ```
-    pub async fn read<T>(&mut self, message: T, ctx: &Context)
+    pub async fn read<T>(
+        instance: &Arc<FuturesMutex<TTSInstance>>,
+        message: T,
+        ctx: &Context,
+        config: &ServerConfig,
+    ) -> Vec<TrackHandle>
     where
         T: TTSMessage,
     {
-        let path = message.synthesize(self, ctx).await;
+        // Chunks of one message must not be interleaved with another message's.
+        let read_queue = instance.lock().await.read_queue.clone();
+        let _turn = read_queue.lock().await;
 
-        {
-            let manager = songbird::get(&ctx).await.unwrap();
-            let call = manager.get(self.guild).unwrap();
-            let mut call = call.lock().await;
-            let input = songbird::input::ffmpeg(path)
+        let (plan, guild, message_id) = {
+            let mut instance = instance.lock().await;
+            instance.next_message_id += 1;
+            (
+                message.prepare(&mut instance, ctx, config).await,
+                instance.guild,
+                instance.next_message_id,
+            )
+        };
+        let plan = match plan {
+            Some(plan) => plan,
+            None => return vec![],
+        };
+
+        let storage = {
+            let data_read = ctx.data.read().await;
+            data_read
+                .get::<TTSClientData>()
+                .expect("Cannot get TTSClientData")
+                .clone()
+        };
+        let mut clients = engine_clients(&storage, &plan.voices).await;
+        let fade_ms = config.fade_ms.unwrap_or(0);
+
+        let mut handles = vec![];
+        let mut failed_chunks = vec![];
+        let mut synthesized_chars = 0;
+        for chunk in &plan.chunks {
+            let audio =
+                synthesize_with_fallback(&mut clients, &plan.voices, chunk, guild.get()).await;
+
+            let path = match audio {
+                Some(audio) => {
+                    synthesized_chars += ssml::to_plain_text(chunk).chars().count() as u64;
+                    write_audio_file(&audio)
+                }
+                None => {
+                    failed_chunks.push(ssml::to_plain_text(chunk));
+                    continue;
+                }
+            };
+            if let Some(handle) = instance
+                .lock()
                 .await
-                .expect("File not found.");
-            call.enqueue_source(input);
+                .enqueue(ctx, path, fade_ms, message_id)
+                .await
+            {
+                handles.push(handle);
+            }
         }
+
+        if plan.counts_daily_chars && synthesized_chars > 0 {
+            let database = ctx
+                .data
+                .read()
+                .await
+                .get::<DatabaseClientData>()
+                .expect("Cannot get DatabaseClientData")
+                .clone();
+            let result = database
+                .lock()
+                .await
+                .incr_guild_daily_chars(guild.get(), utc_day(SystemTime::now()), synthesized_chars)
+                .await;
+            if let Err(err) = result {
+                println!("Failed to count synthesized characters: {}", err);
+            }
+        }
+
+        if let (Some(user_id), Some(text)) = (plan.user_id, dropped_text(&failed_chunks)) {
+            log_dropped_message(
+                DropReason::SynthesisFailed,
+                guild.get(),
+                user_id,
+                plan.voices.last().map(|voice| &voice.tts_type),
+                &text,
+            );
+        }
+
+        handles
+    }
+
+    /// Play an audio file of the message after the queued tracks. The file is deleted once played.
+    /// Returns None when the file cannot be played; it is deleted in that case too.
+    async fn enqueue(
+        &mut self,
+        ctx: &Context,
+        path: String,
+        fade_ms: u64,
+        message_id: u64,
+    ) -> Option<TrackHandle> {
+        let call = match get_call(ctx, self.guild).await {
+            Ok(call) => call,
+            Err(err) => {
+                println!(
+                    "Cannot enqueue audio for guild {}: {}",
+                    self.guild.get(),
+                    err
+                );
+                let _ = fs::remove_file(&path);
+                return None;
+            }
+        };
+
+        let input = Input::from(File::new(path.clone()))
+            .make_playable_async(&CODEC_REGISTRY, &PROBE)
+            .await;
+        let input = match input {
+            Ok(input) => input,
+            Err(err) => {
+                let _ = fs::remove_file(&path);
+                println!("{}", playback_error_message(&path, self.guild.get(), &err));
+                if !self.playback_error_notified {
+                    self.playback_error_notified = true;
+                    let _ = self
+                        .text_channel
+                        .say(&ctx.http, "音声の再生に失敗しました．")
+                        .await;
+                }
+                return None;
+            }
+        };
+
+        let (handle, duration) = {
+            let mut call = call.lock().await;
+            let duration = input_duration(&input);
+            let handle = call.enqueue_input(input).await;
+            // Forget tracks that have already finished.
+            let queued = call
+                .queue()
+                .current_queue()
+                .iter()
+                .map(|track| track.uuid())
+                .collect::<HashSet<_>>();
+            self.track_messages.retain(|uuid, _| queued.contains(uuid));
+            (handle, duration)
+        };
+        self.track_messages.insert(handle.uuid(), message_id);
+        apply_fade(&handle, fade_ms, duration);
+        let _ = handle.add_event(Event::Track(TrackEvent::End), RemoveAudioFile { path });
+        Some(handle)
+    }
+
+    /// Stop the current track and drop every queued track.
+    /// Returns the number of messages that were cleared.
+    pub async fn skip_all(&mut self, ctx: &Context) -> usize {
+        let call = match get_call(ctx, self.guild).await {
+            Ok(call) => call,
+            Err(err) => {
+                println!("Cannot skip audio for guild {}: {}", self.guild.get(), err);
+                return 0;
+            }
+        };
+        let call = call.lock().await;
+        let queue = call.queue();
+        let tracks = queue
+            .current_queue()
+            .iter()
+            .map(|track| track.uuid())
+            .collect::<Vec<_>>();
+        let cleared = count_queued_messages(&tracks, &self.track_messages);
+        queue.stop();
+        self.track_messages.clear();
+        cleared
     }
 
     pub async fn skip(&mut self, ctx: &Context) {
-        let manager = songbird::get(&ctx).await.unwrap();
-        let call = manager.get(self.guild).unwrap();
+        let call = match get_call(ctx, self.guild).await {
+            Ok(call) => call,
+            Err(err) => {
+                println!("Cannot skip audio for guild {}: {}", self.guild.get(), err);
+                return;
+            }
+        };
         let call = call.lock().await;
         let queue = call.queue();
         let _ = queue.skip();
     }
+
+    /// Post a notice to the text channel when the bot cannot speak in the voice channel.
+    /// Synthesis still succeeds in that case, so nothing would be audible otherwise.
+    ///
+    /// Example:
+    /// ```rust
+    /// TTSInstance::check_speak_permission(&ctx, voice_channel, text_channel).await;
+    /// ```
+    pub async fn check_speak_permission(
+        ctx: &Context,
+        voice_channel: ChannelId,
+        text_channel: ChannelId,
+    ) -> bool {
+        let permissions = match voice_channel.to_channel(&ctx).await {
+            Ok(Channel::Guild(channel)) => {
+                let bot_id = ctx.cache.current_user().id;
+                channel.guild(&ctx.cache).and_then(|guild| {
+                    guild
+                        .members
+                        .get(&bot_id)
+                        .map(|member| guild.user_permissions_in(&channel, member))
+                })
+            }
+            _ => None,
+        };
+
+        match speak_permission_notice(permissions, voice_channel) {
+            Some(notice) => {
+                let _ = text_channel.say(&ctx.http, notice).await;
+                false
+            }
+            None => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolving_a_call_fails_without_manager_or_call() {
+        assert!(matches!(
+            resolve_call(None, GuildId::new(1)),
+            Err(CallError::ManagerNotFound)
+        ));
+        assert!(matches!(
+            resolve_call(Some(Songbird::serenity()), GuildId::new(1)),
+            Err(CallError::CallNotFound)
+        ));
+    }
+
+    #[test]
+    fn missing_speak_permission_posts_a_notice() {
+        let channel = ChannelId::new(10);
+        let notice = speak_permission_notice(Some(Permissions::CONNECT), channel).unwrap();
+        assert!(notice.starts_with("<#10> で発言する権限がない"));
+
+        assert_eq!(
+            speak_permission_notice(Some(Permissions::CONNECT | Permissions::SPEAK), channel),
+            None
+        );
+        // Administrators are resolved to every permission.
+        assert_eq!(
+            speak_permission_notice(Some(Permissions::all()), channel),
+            None
+        );
+        assert_eq!(speak_permission_notice(None, channel), None);
+    }
+
+    #[test]
+    fn queued_chunks_are_counted_per_message() {
+        let tracks = (0..4).map(|_| Uuid::new_v4()).collect::<Vec<_>>();
+        let track_messages = HashMap::from([(tracks[0], 1), (tracks[1], 1), (tracks[2], 2)]);
+        // Two chunks of message 1, one of message 2 and an unknown track.
+        assert_eq!(count_queued_messages(&tracks, &track_messages), 3);
+        assert_eq!(count_queued_messages(&[], &track_messages), 0);
+    }
+
+    #[test]
+    fn playback_error_message_names_the_file_and_guild() {
+        let message = playback_error_message("audio/a.mp3", 42, &"Decode");
+        assert_eq!(
+            message,
+            "Cannot play audio audio/a.mp3 in guild 42: \"Decode\" (file removed)"
+        );
+    }
+
+    #[test]
+    fn reads_are_limited_per_minute() {
+        let start = Instant::now();
+        let mut recent = VecDeque::new();
+        assert!(record_read(&mut recent, start, 2));
+        assert!(record_read(&mut recent, start + Duration::from_secs(10), 2));
+        assert!(!record_read(
+            &mut recent,
+            start + Duration::from_secs(20),
+            2
+        ));
+        assert_eq!(recent.len(), 2);
+
+        assert!(record_read(&mut recent, start + Duration::from_secs(60), 2));
+        assert!(!record_read(
+            &mut recent,
+            start + Duration::from_secs(65),
+            2
+        ));
+        assert!(record_read(&mut recent, start + Duration::from_secs(70), 2));
+    }
+
+    #[test]
+    fn failed_chunks_are_one_drop() {
+        assert_eq!(dropped_text(&[]), None);
+        assert_eq!(
+            dropped_text(&[String::from("一文目。"), String::from("二文目。")]),
+            Some(String::from("一文目。 二文目。"))
+        );
+    }
+
+    #[test]
+    fn zero_limit_skips_every_read() {
+        let mut recent = VecDeque::new();
+        assert!(!record_read(&mut recent, Instant::now(), 0));
+        assert!(recent.is_empty());
+    }
+
+    #[test]
+    fn opposite_events_within_the_window_bounce() {
+        let start = Instant::now();
+        let window = Duration::from_secs(10);
+        let at = |secs| start + Duration::from_secs(secs);
+        // (state, seconds after start, expected bounce)
+        let events = [
+            (VoiceMoveState::JOIN, 0, false),
+            (VoiceMoveState::LEAVE, 3, true),
+            (VoiceMoveState::JOIN, 5, true),
+            (VoiceMoveState::LEAVE, 30, false),
+            (VoiceMoveState::LEAVE, 32, false),
+            (VoiceMoveState::JOIN, 45, false),
+        ];
+        let mut last = None;
+        for (state, secs, bounce) in events {
+            assert_eq!(
+                is_bounce(last, state, at(secs), window),
+                bounce,
+                "{}s",
+                secs
+            );
+            last = Some((state, at(secs)));
+        }
+    }
+
+    #[test]
+    fn quick_rejoins_are_not_announced() {
+        let mut instance = TTSInstance::new(GuildId::new(1), ChannelId::new(2), ChannelId::new(3));
+        let window = Duration::from_secs(60);
+        let alice = UserId::new(10);
+        assert!(instance.should_announce(alice, VoiceMoveState::JOIN, window));
+        assert!(!instance.should_announce(alice, VoiceMoveState::LEAVE, window));
+        assert!(!instance.should_announce(alice, VoiceMoveState::JOIN, window));
+        assert!(instance.should_announce(UserId::new(11), VoiceMoveState::LEAVE, window));
+    }
+
+    #[test]
+    fn capacity_is_reached_at_the_limit() {
+        assert!(!at_capacity(0, Some(2)));
+        assert!(!at_capacity(1, Some(2)));
+        assert!(at_capacity(2, Some(2)));
+        assert!(at_capacity(3, Some(2)));
+        assert!(at_capacity(0, Some(0)));
+        assert!(!at_capacity(10_000, None));
+    }
+
+    #[test]
+    fn extra_text_channels_are_not_duplicated() {
+        let text = ChannelId::new(2);
+        let extra = ChannelId::new(4);
+        let mut instance = TTSInstance::new(GuildId::new(1), text, ChannelId::new(3));
+
+        assert!(!instance.add_text_channel(text));
+        assert!(instance.add_text_channel(extra));
+        assert!(!instance.add_text_channel(extra));
+        assert_eq!(instance.extra_text_channels, [extra]);
+        assert!(instance.contains_text_channel(extra));
+
+        assert!(!instance.remove_text_channel(text));
+        assert!(instance.contains_text_channel(text));
+        assert!(instance.remove_text_channel(extra));
+        assert!(!instance.remove_text_channel(extra));
+        assert!(!instance.contains_text_channel(extra));
+    }
+
+    #[test]
+    fn muted_users_are_skipped_until_unmuted() {
+        let mut instance = TTSInstance::new(GuildId::new(1), ChannelId::new(2), ChannelId::new(3));
+        let alice = UserId::new(10);
+        let bob = UserId::new(11);
+
+        assert!(instance.mute_user(alice));
+        assert!(!instance.mute_user(alice));
+        assert!(instance.is_muted(alice));
+        assert!(!instance.is_muted(bob));
+
+        assert!(instance.unmute_user(alice));
+        assert!(!instance.unmute_user(alice));
+        assert!(!instance.is_muted(alice));
+    }
 }
@@ -1,33 +1,257 @@
-use std::{env, fs::File, io::Write};
+use std::{
+    env,
+    fs::File,
+    io::Write,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Instant,
+};
 
 use async_trait::async_trait;
-use serenity::prelude::Context;
+use serenity::{futures::lock::Mutex, prelude::Context};
 
-use crate::{data::TTSClientData, tts::instance::TTSInstance};
+use crate::{
+    database::{
+        server_config::{ServerConfig, VoiceConfig},
+        user_config::{UserConfig, DEFAULT_PITCH, DEFAULT_SPEAKING_RATE},
+    },
+    implement::voice_move_state::VoiceMuteChange,
+    tts::{
+        azure::azure::{AzureTTS, DEFAULT_AZURE_VOICE},
+        gcp_tts::gcp_tts::TTS,
+        instance::TTSInstance,
+        tts_type::TTSType,
+        voicevox::voicevox::VOICEVOX,
+    },
+    utils::truncate_chars,
+};
 
-use super::gcp_tts::structs::{
-    audio_config::AudioConfig, synthesis_input::SynthesisInput,
-    synthesize_request::SynthesizeRequest, voice_selection_params::VoiceSelectionParams,
+use super::{
+    gcp_tts::structs::{
+        audio_config::AudioConfig, synthesis_input::SynthesisInput,
+        synthesize_request::SynthesizeRequest, voice_selection_params::VoiceSelectionParams,
+    },
+    ssml,
 };
 
+/// Engine and voice parameters for one synthesis request.
+#[derive(Clone, Debug)]
+pub struct SynthesisVoice {
+    pub tts_type: TTSType,
+    pub gcp_voice: VoiceSelectionParams,
+    pub voicevox_speaker: i64,
+    pub azure_voice: String,
+    pub speaking_rate: f32,
+    pub pitch: f32,
+    pub effects_profile: Option<String>,
+}
+
+impl SynthesisVoice {
+    /// Voice for server-side speech such as announcements.
+    pub fn from_voice_config(voice: &VoiceConfig) -> Self {
+        Self {
+            tts_type: voice.tts_type.clone(),
+            gcp_voice: voice
+                .gcp_tts_voice
+                .clone()
+                .unwrap_or_else(|| VoiceConfig::announce_default().gcp_tts_voice.unwrap()),
+            voicevox_speaker: voice.voicevox_speaker.unwrap_or(1),
+            azure_voice: voice
+                .azure_voice
+                .clone()
+                .unwrap_or_else(|| DEFAULT_AZURE_VOICE.to_string()),
+            speaking_rate: DEFAULT_SPEAKING_RATE,
+            pitch: DEFAULT_PITCH,
+            effects_profile: None,
+        }
+    }
+
+    /// Voice for a user's messages, read at `speaking_rate`.
+    pub fn from_user_config(config: &UserConfig, speaking_rate: f32) -> Self {
+        Self {
+            tts_type: config.tts_type.clone().unwrap_or(TTSType::GCP),
+            gcp_voice: config
+                .gcp_tts_voice
+                .clone()
+                .unwrap_or_else(|| VoiceConfig::announce_default().gcp_tts_voice.unwrap()),
+            voicevox_speaker: config.voicevox_speaker.unwrap_or(1),
+            azure_voice: config
+                .azure_voice
+                .clone()
+                .unwrap_or_else(|| DEFAULT_AZURE_VOICE.to_string()),
+            speaking_rate,
+            pitch: config.pitch.unwrap_or(DEFAULT_PITCH),
+            effects_profile: config.gcp_effects_profile.clone(),
+        }
+    }
+}
+
+/// A message that is ready to be synthesized.
+pub struct SynthesisPlan {
+    /// Voices tried in order until one succeeds.
+    pub voices: Vec<SynthesisVoice>,
+    /// SSML chunks without `<speak>`, in playback order.
+    pub chunks: Vec<String>,
+    /// Author of the message, if any.
+    pub user_id: Option<u64>,
+    /// Whether synthesized characters count towards the guild's daily limit.
+    pub counts_daily_chars: bool,
+}
+
 /// Message trait that can be used to synthesize text to speech.
 #[async_trait]
 pub trait TTSMessage {
     /// Parse the message for synthesis.
+    /// `read_own_name` is the author's setting for reading their name before the message.
     ///
     /// Example:
     /// ```rust
-    /// let text = message.parse(instance, ctx).await;
+    /// let text = message.parse(instance, ctx, &config, true).await;
     /// ```
-    async fn parse(&self, instance: &mut TTSInstance, ctx: &Context) -> String;
+    async fn parse(
+        &self,
+        instance: &mut TTSInstance,
+        ctx: &Context,
+        config: &ServerConfig,
+        read_own_name: bool,
+    ) -> String;
 
-    /// Synthesize the message and returns the path to the audio file.
+    /// Parse the message and decide the voice and chunks to synthesize.
+    /// This runs with the instance locked; the chunks are synthesized after it is released.
+    /// Returns None when nothing should be read.
     ///
     /// Example:
     /// ```rust
-    /// let path = message.synthesize(instance, ctx).await;
+    /// let plan = message.prepare(instance, ctx, &config).await;
     /// ```
-    async fn synthesize(&self, instance: &mut TTSInstance, ctx: &Context) -> String;
+    async fn prepare(
+        &self,
+        instance: &mut TTSInstance,
+        ctx: &Context,
+        config: &ServerConfig,
+    ) -> Option<SynthesisPlan>;
+}
+
+/// Announcement read when a user joins the voice channel.
+pub fn join_announcement(name: &str) -> String {
+    format!("{} さんが通話に参加しました", name)
+}
+
+/// Maximum length of a user's custom join message.
+pub const MAX_CUSTOM_JOIN_LENGTH: usize = 50;
+
+/// Join announcement for the user.
+/// A custom message replaces the default one when the server allows it.
+/// `{name}` in the custom message is replaced with the user's name.
+pub fn select_join_announcement(name: &str, custom: Option<&str>, allowed: bool) -> String {
+    match custom {
+        Some(custom) if allowed && !custom.trim().is_empty() => custom.replace("{name}", name),
+        _ => join_announcement(name),
+    }
+}
+
+/// Announcement read when a user leaves the voice channel.
+pub fn leave_announcement(name: &str) -> String {
+    format!("{} さんが通話から退出しました", name)
+}
+
+/// Announcement read when a user in the voice channel mutes or deafens.
+pub fn mute_announcement(name: &str, change: VoiceMuteChange) -> String {
+    match change {
+        VoiceMuteChange::MUTE => format!("{} さんがミュートしました", name),
+        VoiceMuteChange::UNMUTE => format!("{} さんがミュートを解除しました", name),
+        VoiceMuteChange::DEAF => format!("{} さんがスピーカーミュートしました", name),
+        VoiceMuteChange::UNDEAF => format!("{} さんがスピーカーミュートを解除しました", name),
+    }
+}
+
+/// Rolling average synthesis latency in milliseconds, 0 before the first synthesis.
+static SYNTHESIS_LATENCY_MS: AtomicU64 = AtomicU64::new(0);
+
+/// Share of a new sample in the rolling latency average, as 1 / N.
+const LATENCY_AVERAGE_WINDOW: u64 = 8;
+
+/// Fold a latency sample into the rolling average; an average of 0 means no samples yet.
+///
+/// Example:
+/// ```rust
+/// assert_eq!(rolling_average(0, 400), 400);
+/// assert_eq!(rolling_average(400, 1200), 500);
+/// ```
+pub fn rolling_average(average: u64, sample: u64) -> u64 {
+    if average == 0 {
+        sample.max(1)
+    } else {
+        (average * (LATENCY_AVERAGE_WINDOW - 1) + sample) / LATENCY_AVERAGE_WINDOW
+    }
+}
+
+/// Log how long a synthesis request took and add it to `synthesis_latency_ms`.
+pub fn record_synthesis_latency(tts_type: &TTSType, chars: usize, start: Instant) {
+    let elapsed = start.elapsed().as_millis() as u64;
+    let _ = SYNTHESIS_LATENCY_MS.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |average| {
+        Some(rolling_average(average, elapsed))
+    });
+    println!(
+        "synthesis engine={:?} chars={} latency_ms={}",
+        tts_type, chars, elapsed
+    );
+}
+
+/// Rolling average synthesis latency in milliseconds, or None before the first synthesis.
+pub fn synthesis_latency_ms() -> Option<u64> {
+    match SYNTHESIS_LATENCY_MS.load(Ordering::Relaxed) {
+        0 => None,
+        average => Some(average),
+    }
+}
+
+/// Number of messages dropped since startup, see `log_dropped_message`.
+static DROPPED_MESSAGES: AtomicU64 = AtomicU64::new(0);
+
+/// Length of the text preview in a dropped message log, in characters.
+const DROPPED_PREVIEW_LENGTH: usize = 30;
+
+/// Why a message was not read.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DropReason {
+    SynthesisFailed,
+    DailyLimit,
+    Throttled,
+}
+
+/// Log a message that will not be read and count it in `dropped_messages`.
+/// Only a short preview of the text is logged.
+pub fn log_dropped_message(
+    reason: DropReason,
+    guild_id: u64,
+    user_id: u64,
+    tts_type: Option<&TTSType>,
+    text: &str,
+) {
+    DROPPED_MESSAGES.fetch_add(1, Ordering::Relaxed);
+    let preview = truncate_chars(text, DROPPED_PREVIEW_LENGTH, "");
+    println!(
+        "dropped reason={:?} guild={} user={} engine={:?} text={:?}",
+        reason, guild_id, user_id, tts_type, preview
+    );
+}
+
+/// Number of messages dropped since startup.
+pub fn dropped_messages() -> u64 {
+    DROPPED_MESSAGES.load(Ordering::Relaxed)
+}
+
+/// Write synthesized audio to the audio directory and return its path.
+pub fn write_audio_file(audio: &[u8]) -> String {
+    let uuid = uuid::Uuid::new_v4().to_string();
+
+    let path = env::current_dir().unwrap();
+    let file_path = path.join("audio").join(format!("{}.mp3", uuid));
+
+    let mut file = File::create(file_path.clone()).unwrap();
+    file.write_all(audio).unwrap();
+
+    file_path.into_os_string().into_string().unwrap()
 }
 
 pub struct AnnounceMessage {
@@ -36,52 +260,352 @@ pub struct AnnounceMessage {
 
 #[async_trait]
 impl TTSMessage for AnnounceMessage {
-    async fn parse(&self, instance: &mut TTSInstance, _ctx: &Context) -> String {
+    async fn parse(
+        &self,
+        instance: &mut TTSInstance,
+        _ctx: &Context,
+        _config: &ServerConfig,
+        _read_own_name: bool,
+    ) -> String {
         instance.before_message = None;
         format!(
-            r#"<speak>アナウンス<break time="200ms"/>{}</speak>"#,
-            self.message
+            r#"アナウンス<break time="200ms"/>{}"#,
+            ssml::escape(&self.message)
         )
     }
 
-    async fn synthesize(&self, instance: &mut TTSInstance, ctx: &Context) -> String {
-        let text = self.parse(instance, ctx).await;
-        let data_read = ctx.data.read().await;
-        let storage = data_read
-            .get::<TTSClientData>()
-            .expect("Cannot get TTSClientStorage")
-            .clone();
-        let mut storage = storage.lock().await;
-
-        let audio = storage
-            .0
-            .synthesize(SynthesizeRequest {
-                input: SynthesisInput {
-                    text: None,
-                    ssml: Some(text),
-                },
-                voice: VoiceSelectionParams {
-                    languageCode: String::from("ja-JP"),
-                    name: String::from("ja-JP-Wavenet-B"),
-                    ssmlGender: String::from("neutral"),
-                },
-                audioConfig: AudioConfig {
-                    audioEncoding: String::from("mp3"),
-                    speakingRate: 1.2f32,
-                    pitch: 1.0f32,
-                },
-            })
+    async fn prepare(
+        &self,
+        instance: &mut TTSInstance,
+        ctx: &Context,
+        config: &ServerConfig,
+    ) -> Option<SynthesisPlan> {
+        let text = self.parse(instance, ctx, config, false).await;
+
+        Some(SynthesisPlan {
+            voices: announce_voices(config),
+            chunks: vec![text],
+            user_id: None,
+            counts_daily_chars: false,
+        })
+    }
+}
+
+/// Voices tried for announcements: the server's announce voice, then the fallback engine.
+pub fn announce_voices(config: &ServerConfig) -> Vec<SynthesisVoice> {
+    let voice = config
+        .announce_voice
+        .clone()
+        .unwrap_or_else(VoiceConfig::announce_default);
+
+    let fallback = VoiceConfig {
+        tts_type: fallback_engine(&voice.tts_type),
+        ..VoiceConfig::announce_default()
+    };
+
+    vec![
+        SynthesisVoice::from_voice_config(&voice),
+        SynthesisVoice::from_voice_config(&fallback),
+    ]
+}
+
+/// Engine used for announcements when the configured engine fails.
+pub fn fallback_engine(tts_type: &TTSType) -> TTSType {
+    match tts_type {
+        TTSType::GCP => TTSType::VOICEVOX,
+        TTSType::VOICEVOX | TTSType::Azure => TTSType::GCP,
+    }
+}
+
+/// Engines that synthesize one SSML chunk with a voice.
+#[async_trait]
+pub trait ChunkSynthesizer {
+    async fn synthesize(&mut self, voice: &SynthesisVoice, chunk: &str) -> Result<Vec<u8>, String>;
+}
+
+#[async_trait]
+impl ChunkSynthesizer for (TTS, VOICEVOX, Option<AzureTTS>) {
+    async fn synthesize(&mut self, voice: &SynthesisVoice, chunk: &str) -> Result<Vec<u8>, String> {
+        synthesize_chunk(self, voice, chunk)
             .await
-            .unwrap();
+            .map_err(|err| err.to_string())
+    }
+}
+
+/// Synthesize the chunk with each voice in order until one succeeds, logging the failures.
+/// Returns None when every voice fails.
+///
+/// Example:
+/// ```rust
+/// let audio = synthesize_with_fallback(&mut clients, &plan.voices, chunk, guild.get()).await;
+/// ```
+pub async fn synthesize_with_fallback(
+    engines: &mut (impl ChunkSynthesizer + Send),
+    voices: &[SynthesisVoice],
+    chunk: &str,
+    guild_id: u64,
+) -> Option<Vec<u8>> {
+    for voice in voices {
+        match engines.synthesize(voice, chunk).await {
+            Ok(audio) => return Some(audio),
+            Err(err) => {
+                println!(
+                    "Cannot synthesize with {:?} in guild {}: {}",
+                    voice.tts_type, guild_id, err
+                );
+            }
+        }
+    }
+    None
+}
+
+/// Copy the engine clients out of the shared lock so synthesis does not hold it.
+/// Expired tokens of the engines in `voices` are refreshed first, under the lock,
+/// so later copies reuse the new token. A failed refresh is retried and reported
+/// by the synthesis itself.
+pub async fn engine_clients(
+    storage: &Mutex<(TTS, VOICEVOX, Option<AzureTTS>)>,
+    voices: &[SynthesisVoice],
+) -> (TTS, VOICEVOX, Option<AzureTTS>) {
+    let mut storage = storage.lock().await;
+    if voices.iter().any(|voice| voice.tts_type == TTSType::GCP) {
+        storage.0.update_token().await.ok();
+    }
+    if voices.iter().any(|voice| voice.tts_type == TTSType::Azure) {
+        if let Some(azure) = storage.2.as_mut() {
+            azure.update_token().await.ok();
+        }
+    }
+    storage.clone()
+}
+
+/// Synthesize one SSML chunk (without `<speak>`) with the voice.
+///
+/// Example:
+/// ```rust
+/// let audio = synthesize_chunk(&mut storage, &voice, "こんにちは").await?;
+/// ```
+pub async fn synthesize_chunk(
+    storage: &mut (TTS, VOICEVOX, Option<AzureTTS>),
+    voice: &SynthesisVoice,
+    chunk: &str,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let start = Instant::now();
+    let audio = match voice.tts_type {
+        TTSType::GCP => {
+            storage
+                .0
+                .synthesize(SynthesizeRequest {
+                    input: SynthesisInput {
+                        text: None,
+                        ssml: Some(format!(
+                            "<speak>{}</speak>",
+                            ssml::truncate(chunk, ssml::MAX_SSML_BYTES)
+                        )),
+                    },
+                    voice: voice.gcp_voice.clone(),
+                    audioConfig: AudioConfig {
+                        audioEncoding: String::from("mp3"),
+                        speakingRate: voice.speaking_rate,
+                        pitch: voice.pitch,
+                        effectsProfileId: voice
+                            .effects_profile
+                            .clone()
+                            .map(|profile| vec![profile]),
+                    },
+                })
+                .await
+        }
+        TTSType::VOICEVOX => {
+            storage
+                .1
+                .synthesize(
+                    ssml::to_plain_text(chunk),
+                    voice.voicevox_speaker,
+                    voice.speaking_rate,
+                    voice.pitch,
+                )
+                .await
+        }
+        TTSType::Azure => match storage.2.as_mut() {
+            Some(azure) => azure.synthesize(chunk, &voice.azure_voice).await,
+            None => Err("Azure TTS is not configured".into()),
+        },
+    }?;
 
-        let uuid = uuid::Uuid::new_v4().to_string();
+    record_synthesis_latency(&voice.tts_type, chunk.chars().count(), start);
+    Ok(audio)
+}
 
-        let path = env::current_dir().unwrap();
-        let file_path = path.join("audio").join(format!("{}.mp3", uuid));
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        let mut file = File::create(file_path.clone()).unwrap();
-        file.write(&audio).unwrap();
+    #[test]
+    fn first_latency_sample_is_the_average() {
+        assert_eq!(rolling_average(0, 400), 400);
+        // A 0ms sample still marks that a synthesis happened.
+        assert_eq!(rolling_average(0, 0), 1);
+    }
 
-        file_path.into_os_string().into_string().unwrap()
+    #[test]
+    fn latency_average_moves_towards_new_samples() {
+        assert_eq!(rolling_average(400, 1200), 500);
+        assert_eq!(rolling_average(400, 0), 350);
+        assert_eq!(rolling_average(400, 400), 400);
+    }
+
+    #[test]
+    fn custom_join_message_replaces_the_default_when_allowed() {
+        assert_eq!(
+            select_join_announcement("Alice", Some("{name}さん、いらっしゃい"), true),
+            "Aliceさん、いらっしゃい"
+        );
+        assert_eq!(
+            select_join_announcement("Alice", Some("{name}さん、いらっしゃい"), false),
+            join_announcement("Alice")
+        );
+        assert_eq!(
+            select_join_announcement("Alice", None, true),
+            join_announcement("Alice")
+        );
+        assert_eq!(
+            select_join_announcement("Alice", Some("  "), true),
+            join_announcement("Alice")
+        );
+    }
+
+    /// Engines where only `working` synthesizes; records the engines tried.
+    struct FakeEngines {
+        working: Option<TTSType>,
+        tried: Vec<TTSType>,
+    }
+
+    #[async_trait]
+    impl ChunkSynthesizer for FakeEngines {
+        async fn synthesize(
+            &mut self,
+            voice: &SynthesisVoice,
+            _chunk: &str,
+        ) -> Result<Vec<u8>, String> {
+            self.tried.push(voice.tts_type.clone());
+            if self.working.as_ref() == Some(&voice.tts_type) {
+                Ok(vec![1, 2, 3])
+            } else {
+                Err(String::from("unavailable"))
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn failing_primary_announce_engine_falls_back() {
+        let voices = announce_voices(&ServerConfig::default());
+        let mut engines = FakeEngines {
+            working: Some(TTSType::VOICEVOX),
+            tried: vec![],
+        };
+        let audio = synthesize_with_fallback(&mut engines, &voices, "お知らせ", 1).await;
+        assert_eq!(audio, Some(vec![1, 2, 3]));
+        assert_eq!(engines.tried, [TTSType::GCP, TTSType::VOICEVOX]);
+    }
+
+    #[tokio::test]
+    async fn every_engine_failing_is_none() {
+        let voices = announce_voices(&ServerConfig::default());
+        let mut engines = FakeEngines {
+            working: None,
+            tried: vec![],
+        };
+        assert_eq!(
+            synthesize_with_fallback(&mut engines, &voices, "お知らせ", 1).await,
+            None
+        );
+
+        // An unconfigured Azure engine fails instead of panicking.
+        let audio =
+            synthesize_with_fallback(&mut clients(), &[voice(TTSType::Azure)], "お知らせ", 1).await;
+        assert_eq!(audio, None);
+    }
+
+    fn clients() -> (TTS, VOICEVOX, Option<AzureTTS>) {
+        let token = serde_json::from_value(serde_json::json!({
+            "access_token": "token",
+            "expires_in": 3600,
+        }))
+        .unwrap();
+        (
+            TTS {
+                token: std::sync::Arc::new(token),
+                credentials_path: String::new(),
+                token_updated_at: std::time::SystemTime::now(),
+                voices: None,
+            },
+            VOICEVOX { key: String::new() },
+            None,
+        )
+    }
+
+    fn voice(tts_type: TTSType) -> SynthesisVoice {
+        SynthesisVoice {
+            tts_type,
+            gcp_voice: VoiceSelectionParams {
+                languageCode: String::from("ja-JP"),
+                name: String::from("ja-JP-Wavenet-B"),
+                ssmlGender: String::from("neutral"),
+            },
+            voicevox_speaker: 1,
+            azure_voice: String::from(DEFAULT_AZURE_VOICE),
+            speaking_rate: DEFAULT_SPEAKING_RATE,
+            pitch: DEFAULT_PITCH,
+            effects_profile: None,
+        }
+    }
+
+    #[test]
+    fn announce_voice_comes_from_the_server_config() {
+        let voices = announce_voices(&ServerConfig::default());
+        assert_eq!(voices[0].tts_type, TTSType::GCP);
+        assert_eq!(voices[0].gcp_voice.name, "ja-JP-Wavenet-B");
+
+        let config = ServerConfig {
+            announce_voice: Some(VoiceConfig {
+                tts_type: TTSType::VOICEVOX,
+                gcp_tts_voice: None,
+                voicevox_speaker: Some(3),
+                azure_voice: None,
+            }),
+            ..Default::default()
+        };
+        let voices = announce_voices(&config);
+        assert_eq!(voices[0].tts_type, TTSType::VOICEVOX);
+        assert_eq!(voices[0].voicevox_speaker, 3);
+        assert_eq!(voices[0].speaking_rate, DEFAULT_SPEAKING_RATE);
+    }
+
+    #[tokio::test]
+    async fn two_guilds_synthesize_concurrently() {
+        let storage = std::sync::Arc::new(Mutex::new(clients()));
+        let synthesizing = std::sync::Arc::new(tokio::sync::Barrier::new(2));
+
+        let guilds = (0..2).map(|_| {
+            let storage = storage.clone();
+            let synthesizing = synthesizing.clone();
+            tokio::spawn(async move {
+                let _clients =
+                    engine_clients(&storage, &[voice(TTSType::GCP), voice(TTSType::Azure)]).await;
+                // Both guilds are mid-synthesis at once only if neither holds the lock.
+                synthesizing.wait().await;
+            })
+        });
+        let both = async {
+            for guild in guilds.collect::<Vec<_>>() {
+                guild.await.unwrap();
+            }
+        };
+
+        tokio::time::timeout(std::time::Duration::from_secs(5), both)
+            .await
+            .expect("synthesis in one guild blocked the other");
+        assert!(storage.try_lock().is_some());
     }
 }
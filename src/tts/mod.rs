@@ -1,5 +1,10 @@
+pub mod azure;
+pub mod fade;
 pub mod gcp_tts;
 pub mod instance;
 pub mod message;
+pub mod number;
+pub mod scheduler;
+pub mod ssml;
 pub mod tts_type;
 pub mod voicevox;
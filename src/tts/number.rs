@@ -0,0 +1,153 @@
+use regex::{Captures, Regex};
+use std::sync::LazyLock;
+
+const DIGITS: [&str; 10] = ["零", "一", "二", "三", "四", "五", "六", "七", "八", "九"];
+const SMALL_UNITS: [&str; 4] = ["", "十", "百", "千"];
+const LARGE_UNITS: [&str; 4] = ["", "万", "億", "兆"];
+
+static NUMBER_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"[0-9]+(\.[0-9]+)?").unwrap());
+
+/// Convert an ASCII digit string into its Japanese numeral reading.
+/// Strings with leading zeros or values of 10^16 and above are returned as they are.
+///
+/// Example:
+/// ```rust
+/// assert_eq!(japanese_number_reading("12345"), "一万二千三百四十五");
+/// ```
+pub fn japanese_number_reading(n: &str) -> String {
+    if n.is_empty() || !n.bytes().all(|b| b.is_ascii_digit()) {
+        return n.to_string();
+    }
+
+    if n == "0" {
+        return String::from("零");
+    }
+
+    // Leading zeros mark codes such as "007" or phone numbers, and values of 10^16 and
+    // above have no unit here and are mostly ids. The engines read both digit by digit.
+    if n.starts_with('0') || n.len() > 4 * LARGE_UNITS.len() {
+        return n.to_string();
+    }
+
+    let digits: Vec<usize> = n.bytes().map(|b| (b - b'0') as usize).collect();
+    let mut reading = String::new();
+
+    let groups: Vec<&[usize]> = digits.rchunks(4).collect();
+    for (group_index, group) in groups.iter().enumerate().rev() {
+        let mut group_reading = String::new();
+        for (i, digit) in group.iter().enumerate() {
+            let place = group.len() - 1 - i;
+            match (*digit, place) {
+                (0, _) => {}
+                (1, 1..=3) => group_reading.push_str(SMALL_UNITS[place]),
+                (digit, _) => {
+                    group_reading.push_str(DIGITS[digit]);
+                    group_reading.push_str(SMALL_UNITS[place]);
+                }
+            }
+        }
+
+        if !group_reading.is_empty() {
+            reading.push_str(&group_reading);
+            reading.push_str(LARGE_UNITS[group_index]);
+        }
+    }
+
+    reading
+}
+
+/// Convert a decimal such as "3.14" into its reading, "三点一四".
+/// The fraction is read digit by digit. When the integer part is left unchanged by
+/// `japanese_number_reading`, the whole number is.
+///
+/// Example:
+/// ```rust
+/// assert_eq!(japanese_decimal_reading("0.05"), "零点零五");
+/// ```
+pub fn japanese_decimal_reading(n: &str) -> String {
+    let (integer, fraction) = match n.split_once('.') {
+        Some(parts) => parts,
+        None => return japanese_number_reading(n),
+    };
+    if fraction.is_empty() || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+        return n.to_string();
+    }
+
+    let integer_reading = japanese_number_reading(integer);
+    if integer_reading == integer {
+        return n.to_string();
+    }
+
+    let fraction_reading = fraction
+        .bytes()
+        .map(|b| DIGITS[(b - b'0') as usize])
+        .collect::<String>();
+    format!("{}点{}", integer_reading, fraction_reading)
+}
+
+/// Replace every ASCII number in the text, including decimals, with its Japanese reading.
+///
+/// Example:
+/// ```rust
+/// assert_eq!(normalize_numbers("1.5倍の100円"), "一点五倍の百円");
+/// ```
+pub fn normalize_numbers(text: &str) -> String {
+    NUMBER_REGEX
+        .replace_all(text, |caps: &Captures| japanese_decimal_reading(&caps[0]))
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_integers() {
+        assert_eq!(japanese_number_reading("0"), "零");
+        assert_eq!(japanese_number_reading("1"), "一");
+        assert_eq!(japanese_number_reading("10"), "十");
+        assert_eq!(japanese_number_reading("11"), "十一");
+        assert_eq!(japanese_number_reading("20"), "二十");
+        assert_eq!(japanese_number_reading("105"), "百五");
+        assert_eq!(japanese_number_reading("1000"), "千");
+        assert_eq!(japanese_number_reading("2024"), "二千二十四");
+        assert_eq!(japanese_number_reading("10000"), "一万");
+        assert_eq!(japanese_number_reading("12345"), "一万二千三百四十五");
+        assert_eq!(japanese_number_reading("100000000"), "一億");
+        assert_eq!(japanese_number_reading("100010000"), "一億一万");
+        assert_eq!(
+            japanese_number_reading("9999999999999999"),
+            "九千九百九十九兆九千九百九十九億九千九百九十九万九千九百九十九"
+        );
+    }
+
+    #[test]
+    fn leaves_codes_and_huge_numbers_unchanged() {
+        assert_eq!(japanese_number_reading("007"), "007");
+        assert_eq!(japanese_number_reading("09012345678"), "09012345678");
+        assert_eq!(
+            japanese_number_reading("10000000000000000"),
+            "10000000000000000"
+        );
+        assert_eq!(japanese_number_reading(""), "");
+        assert_eq!(japanese_number_reading("12a"), "12a");
+    }
+
+    #[test]
+    fn reads_decimals_digit_by_digit() {
+        assert_eq!(japanese_decimal_reading("3.14"), "三点一四");
+        assert_eq!(japanese_decimal_reading("0.5"), "零点五");
+        assert_eq!(japanese_decimal_reading("10.05"), "十点零五");
+        assert_eq!(japanese_decimal_reading("01.5"), "01.5");
+        assert_eq!(japanese_decimal_reading("12"), "十二");
+    }
+
+    #[test]
+    fn normalizes_numbers_in_text() {
+        assert_eq!(normalize_numbers("1.5倍の100円"), "一点五倍の百円");
+        assert_eq!(normalize_numbers("v1.2.3"), "v一点二.三");
+        assert_eq!(normalize_numbers("電話は090です。"), "電話は090です。");
+        assert_eq!(normalize_numbers("文末の1."), "文末の一.");
+        assert_eq!(normalize_numbers("数字なし"), "数字なし");
+    }
+}
@@ -0,0 +1,76 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serenity::{model::id::GuildId, prelude::Context};
+
+use crate::{
+    data::{DatabaseClientData, TTSData},
+    database::schedule::is_due,
+    tts::{instance::TTSInstance, message::AnnounceMessage},
+};
+
+/// How often schedules are checked.
+const SCHEDULER_TICK: Duration = Duration::from_secs(60);
+
+/// Announce due schedules in every connected instance until the process exits.
+/// A schedule first runs one interval after it is first seen, so restarts do not replay it.
+pub async fn run_scheduler(ctx: Context) {
+    let mut last_runs: HashMap<(GuildId, u32), u64> = HashMap::new();
+    let mut ticker = tokio::time::interval(SCHEDULER_TICK);
+
+    loop {
+        ticker.tick().await;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+
+        let (instances, database) = {
+            let data_read = ctx.data.read().await;
+            let storage_lock = data_read
+                .get::<TTSData>()
+                .expect("Cannot get TTSStorage")
+                .clone();
+            let database = data_read
+                .get::<DatabaseClientData>()
+                .expect("Cannot get DatabaseClientData")
+                .clone();
+            let instances = storage_lock
+                .read()
+                .await
+                .iter()
+                .map(|(guild_id, instance)| (*guild_id, instance.clone()))
+                .collect::<Vec<_>>();
+            (instances, database)
+        };
+
+        for (guild_id, instance) in instances {
+            let config = {
+                let mut database = database.lock().await;
+                match database.get_server_config(guild_id.get()).await {
+                    Ok(Some(config)) => config,
+                    _ => continue,
+                }
+            };
+
+            for schedule in &config.schedules {
+                let last_run = *last_runs.entry((guild_id, schedule.id)).or_insert(now);
+                if !is_due(last_run, schedule.interval_minutes, now) {
+                    continue;
+                }
+                last_runs.insert((guild_id, schedule.id), now);
+                TTSInstance::read(
+                    &instance,
+                    AnnounceMessage {
+                        message: schedule.phrase.clone(),
+                    },
+                    &ctx,
+                    &config,
+                )
+                .await;
+            }
+        }
+    }
+}
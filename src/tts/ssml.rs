@@ -0,0 +1,443 @@
+use regex::{Captures, Regex};
+use std::sync::LazyLock;
+
+/// SSML tags that trusted users may use, with the attributes allowed on each.
+const ALLOWED_TAGS: &[(&str, &[&str])] = &[
+    ("break", &["time", "strength"]),
+    ("emphasis", &["level"]),
+    ("prosody", &["rate", "pitch", "volume"]),
+    ("say-as", &["interpret-as", "format", "detail"]),
+    ("sub", &["alias"]),
+    ("p", &[]),
+    ("s", &[]),
+];
+
+static TAG_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"</?([a-zA-Z][a-zA-Z\-]*)(\s[^<>]*)?/?>").unwrap());
+
+static ATTRIBUTE_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"^\s*([a-zA-Z][a-zA-Z\-]*)\s*=\s*"([^"<>&]*)""#).unwrap());
+
+/// Longest SSML body sent to Google TTS, in bytes.
+/// The API rejects input over 5000 bytes, and `<speak></speak>` is added around the body.
+pub const MAX_SSML_BYTES: usize = 4900;
+
+/// Escape text so that it can be embedded in SSML.
+///
+/// Example:
+/// ```rust
+/// assert_eq!(escape("a < b"), "a &lt; b");
+/// ```
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Sanitize text while keeping the allowed SSML tags.
+/// Tags that are not allowed, carry attributes not allowed on them or close an element
+/// that is not open are escaped, as are stray characters. Elements left open are closed.
+///
+/// Example:
+/// ```rust
+/// let ssml = sanitize_allowlist(r#"<emphasis>hi</emphasis><audio src="x"/>"#);
+/// ```
+pub fn sanitize_allowlist(text: &str) -> String {
+    let mut result = String::new();
+    let mut open: Vec<String> = vec![];
+    let mut last = 0;
+
+    for caps in TAG_REGEX.captures_iter(text) {
+        let tag = caps.get(0).unwrap();
+        result.push_str(&escape(&text[last..tag.start()]));
+        last = tag.end();
+
+        let name = caps[1].to_ascii_lowercase();
+        let attributes = caps.get(2).map_or("", |attributes| attributes.as_str());
+        let closing = tag.as_str().starts_with("</");
+        let self_closing = tag.as_str().ends_with("/>");
+
+        let allowed = if closing {
+            !self_closing && attributes.trim().is_empty() && open.last() == Some(&name)
+        } else {
+            let attributes = attributes.strip_suffix('/').unwrap_or(attributes);
+            allowed_attributes(&name, attributes)
+        };
+
+        if !allowed {
+            result.push_str(&escape(tag.as_str()));
+        } else if closing {
+            open.pop();
+            result.push_str(&format!("</{}>", name));
+        } else {
+            let attributes = attributes.strip_suffix('/').unwrap_or(attributes).trim();
+            result.push('<');
+            result.push_str(&name);
+            if !attributes.is_empty() {
+                result.push(' ');
+                result.push_str(attributes);
+            }
+            if self_closing {
+                result.push_str("/>");
+            } else {
+                result.push('>');
+                open.push(name);
+            }
+        }
+    }
+    result.push_str(&escape(&text[last..]));
+
+    for name in open.iter().rev() {
+        result.push_str(&format!("</{}>", name));
+    }
+
+    result
+}
+
+/// Whether the tag is allowed and every attribute is allowed on it.
+fn allowed_attributes(name: &str, attributes: &str) -> bool {
+    let allowed = match ALLOWED_TAGS.iter().find(|(tag, _)| *tag == name) {
+        Some((_, allowed)) => allowed,
+        None => return false,
+    };
+
+    let mut rest = attributes;
+    while !rest.trim().is_empty() {
+        let caps = match ATTRIBUTE_REGEX.captures(rest) {
+            Some(caps) => caps,
+            None => return false,
+        };
+        if !allowed.contains(&caps[1].to_ascii_lowercase().as_str()) {
+            return false;
+        }
+        rest = &rest[caps.get(0).unwrap().end()..];
+    }
+    true
+}
+
+/// Apply `f` to the text between tags and entities, leaving markup unchanged.
+///
+/// Example:
+/// ```rust
+/// let ssml = map_text(r#"<break time="500ms"/>1"#, |text| text.replace('1', "一"));
+/// assert_eq!(ssml, r#"<break time="500ms"/>一"#);
+/// ```
+pub fn map_text<F>(ssml: &str, f: F) -> String
+where
+    F: Fn(&str) -> String,
+{
+    let mut result = String::new();
+    let mut text = String::new();
+    for token in tokenize(ssml) {
+        if token.len() > 1 && (token.starts_with('<') || token.starts_with('&')) {
+            result.push_str(&f(&text));
+            text.clear();
+            result.push_str(token);
+        } else {
+            text.push_str(token);
+        }
+    }
+    result.push_str(&f(&text));
+    result
+}
+
+/// Convert SSML into plain text for engines without SSML support.
+/// Breaks are replaced with a comma and other tags are removed.
+pub fn to_plain_text(ssml: &str) -> String {
+    let text = TAG_REGEX.replace_all(ssml, |caps: &Captures| {
+        if caps[1].eq_ignore_ascii_case("break") {
+            String::from("、")
+        } else {
+            String::new()
+        }
+    });
+
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Split SSML text into chunks of at most `max` characters.
+/// Text is split after sentence terminators (。！？.!?) where possible and
+/// hard-split otherwise. Splits only happen outside elements, so tags stay balanced;
+/// an element longer than `max` is kept whole.
+///
+/// Example:
+/// ```rust
+/// let chunks = split_sentences("こんにちは。元気ですか？", 6);
+/// assert_eq!(chunks, vec!["こんにちは。", "元気ですか？"]);
+/// ```
+pub fn split_sentences(text: &str, max: usize) -> Vec<String> {
+    let max = max.max(1);
+
+    let mut sentences: Vec<Vec<(&str, usize)>> = vec![];
+    let mut sentence: Vec<(&str, usize)> = vec![];
+    for (atom, len) in atoms(text) {
+        sentence.push((atom, len));
+        if matches!(atom, "。" | "！" | "？" | "." | "!" | "?") {
+            sentences.push(std::mem::take(&mut sentence));
+        }
+    }
+    if !sentence.is_empty() {
+        sentences.push(sentence);
+    }
+
+    let mut chunks: Vec<String> = vec![];
+    let mut chunk = String::new();
+    let mut chunk_len = 0;
+    for sentence in sentences {
+        let sentence_len: usize = sentence.iter().map(|(_, len)| len).sum();
+        if chunk_len > 0 && chunk_len + sentence_len > max {
+            chunks.push(std::mem::take(&mut chunk));
+            chunk_len = 0;
+        }
+
+        for (atom, len) in sentence {
+            if chunk_len > 0 && chunk_len + len > max {
+                chunks.push(std::mem::take(&mut chunk));
+                chunk_len = 0;
+            }
+            chunk.push_str(atom);
+            chunk_len += len;
+        }
+    }
+    if !chunk.is_empty() {
+        chunks.push(chunk);
+    }
+
+    chunks
+}
+
+/// Split text into the pieces `split_sentences` may cut between, with their spoken length.
+/// A piece is a character, entity or tag outside any element, or a whole element.
+fn atoms(text: &str) -> Vec<(&str, usize)> {
+    let mut atoms = vec![];
+    let (mut start, mut end, mut len, mut depth) = (0, 0, 0, 0usize);
+    for token in tokenize(text) {
+        end += token.len();
+        len += token_len(token);
+        if tag_name(token).is_some() {
+            if token.starts_with("</") {
+                depth = depth.saturating_sub(1);
+            } else if !token.ends_with("/>") {
+                depth += 1;
+            }
+        }
+        if depth == 0 {
+            atoms.push((&text[start..end], len));
+            start = end;
+            len = 0;
+        }
+    }
+    // An element that is never closed runs to the end.
+    if start < end {
+        atoms.push((&text[start..end], len));
+    }
+    atoms
+}
+
+/// Truncate SSML to at most `max` bytes.
+/// Cuts only between characters, tags and entities, and closes tags left open at the cut.
+///
+/// Example:
+/// ```rust
+/// assert_eq!(truncate("<emphasis>あいう</emphasis>", 24), "<emphasis>あ</emphasis>");
+/// ```
+pub fn truncate(text: &str, max: usize) -> String {
+    if text.len() <= max {
+        return text.to_string();
+    }
+
+    let mut result = String::new();
+    let mut open: Vec<&str> = vec![];
+    for token in tokenize(text) {
+        let mut next_open = open.clone();
+        if let Some(name) = tag_name(token) {
+            if token.starts_with("</") {
+                next_open.pop();
+            } else if !token.ends_with("/>") {
+                next_open.push(name);
+            }
+        }
+
+        let closing: usize = next_open.iter().map(|name| name.len() + 3).sum();
+        if result.len() + token.len() + closing > max {
+            break;
+        }
+        result.push_str(token);
+        open = next_open;
+    }
+
+    for name in open.iter().rev() {
+        result.push_str(&format!("</{}>", name));
+    }
+    result
+}
+
+/// Name of the tag if the token is a tag.
+fn tag_name(token: &str) -> Option<&str> {
+    let inner = token.strip_prefix('<')?.strip_suffix('>')?;
+    let inner = inner.strip_prefix('/').unwrap_or(inner);
+    let end = inner
+        .find(|c: char| c.is_whitespace() || c == '/')
+        .unwrap_or(inner.len());
+    if end == 0 {
+        None
+    } else {
+        Some(&inner[..end])
+    }
+}
+
+/// Split text into tags, entities and single characters.
+fn tokenize(text: &str) -> Vec<&str> {
+    let mut tokens = vec![];
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        let end = match c {
+            '<' => rest.find('>').map(|i| i + 1),
+            '&' => rest.find(';').filter(|i| *i <= 8).map(|i| i + 1),
+            _ => None,
+        }
+        .unwrap_or(c.len_utf8());
+        tokens.push(&rest[..end]);
+        rest = &rest[end..];
+    }
+    tokens
+}
+
+/// Spoken length of a token. Tags are not spoken and entities are one character.
+fn token_len(token: &str) -> usize {
+    if token.starts_with('<') && token.len() > 1 {
+        0
+    } else {
+        1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sanitize_allowlist_keeps_allowed_tags() {
+        assert_eq!(
+            sanitize_allowlist(r#"<emphasis level="strong">はい</emphasis><break time="500ms"/>"#),
+            r#"<emphasis level="strong">はい</emphasis><break time="500ms"/>"#
+        );
+        assert_eq!(
+            sanitize_allowlist(r#"<SUB alias="えすえすえむえる">SSML</SUB>"#),
+            r#"<sub alias="えすえすえむえる">SSML</sub>"#
+        );
+    }
+
+    #[test]
+    fn sanitize_allowlist_escapes_other_tags_and_attributes() {
+        assert_eq!(
+            sanitize_allowlist(r#"<audio src="x"/>"#),
+            "&lt;audio src=&quot;x&quot;/&gt;"
+        );
+        assert_eq!(
+            sanitize_allowlist(r#"<break src="x"/>a"#),
+            "&lt;break src=&quot;x&quot;/&gt;a"
+        );
+        assert_eq!(
+            sanitize_allowlist(r#"<p class="x">a</p>"#),
+            "&lt;p class=&quot;x&quot;&gt;a&lt;/p&gt;"
+        );
+        assert_eq!(sanitize_allowlist("a & b"), "a &amp; b");
+    }
+
+    #[test]
+    fn sanitize_allowlist_balances_elements() {
+        assert_eq!(sanitize_allowlist("</emphasis>a"), "&lt;/emphasis&gt;a");
+        assert_eq!(sanitize_allowlist("<emphasis>a"), "<emphasis>a</emphasis>");
+        assert_eq!(
+            sanitize_allowlist("<p><s>a</p></s>"),
+            "<p><s>a&lt;/p&gt;</s></p>"
+        );
+    }
+
+    #[test]
+    fn map_text_skips_tags_and_entities() {
+        let upper = |text: &str| text.to_uppercase();
+        assert_eq!(
+            map_text(r#"a<break time="1s"/>b&amp;c"#, upper),
+            r#"A<break time="1s"/>B&amp;C"#
+        );
+        assert_eq!(map_text("", upper), "");
+    }
+
+    #[test]
+    fn split_sentences_splits_after_multibyte_terminators() {
+        assert_eq!(
+            split_sentences("こんにちは。元気ですか？", 6),
+            vec!["こんにちは。", "元気ですか？"]
+        );
+        assert_eq!(
+            split_sentences("こんにちは。元気ですか？", 12),
+            vec!["こんにちは。元気ですか？"]
+        );
+    }
+
+    #[test]
+    fn split_sentences_hard_splits_without_punctuation() {
+        assert_eq!(
+            split_sentences("あいうえおかきくけこ", 4),
+            vec!["あいうえ", "おかきく", "けこ"]
+        );
+        assert_eq!(split_sentences("abc", 0), vec!["a", "b", "c"]);
+        assert!(split_sentences("", 10).is_empty());
+    }
+
+    #[test]
+    fn split_sentences_keeps_elements_whole() {
+        assert_eq!(
+            split_sentences("<emphasis>あいうえお</emphasis>かき", 3),
+            vec!["<emphasis>あいうえお</emphasis>", "かき"]
+        );
+        assert_eq!(
+            split_sentences(r#"<prosody rate="fast">はい。いいえ。</prosody>"#, 3),
+            vec![r#"<prosody rate="fast">はい。いいえ。</prosody>"#]
+        );
+    }
+
+    #[test]
+    fn split_sentences_counts_entities_and_breaks() {
+        assert_eq!(
+            split_sentences("&amp;&amp;&amp;", 2),
+            vec!["&amp;&amp;", "&amp;"]
+        );
+        assert_eq!(
+            split_sentences(r#"あ<break time="200ms"/>い"#, 1),
+            vec![r#"あ<break time="200ms"/>"#, "い"]
+        );
+    }
+
+    #[test]
+    fn truncate_cuts_between_multibyte_characters() {
+        assert_eq!(truncate("あいうえお", 7), "あい");
+        assert_eq!(truncate("あいうえお", 15), "あいうえお");
+        assert_eq!(truncate("aあ", 3), "a");
+    }
+
+    #[test]
+    fn truncate_leaves_no_partial_tags_or_entities() {
+        let truncated = truncate(r#"あ<break time="500ms"/>い"#, 10);
+        assert_eq!(truncated, "あ");
+
+        assert_eq!(truncate("a&amp;b", 4), "a");
+        assert_eq!(
+            truncate("<emphasis>あいう</emphasis>", 24),
+            "<emphasis>あ</emphasis>"
+        );
+        // The closing tag must fit too, so the character is dropped.
+        assert_eq!(
+            truncate("<emphasis>あいう</emphasis>", 23),
+            "<emphasis></emphasis>"
+        );
+    }
+}
@@ -2,6 +2,7 @@ use serde::{Deserialize, Serialize};
 
 use super::mora::Mora;
 
+#[allow(dead_code)]
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct AccentPhrase {
     pub moras: Vec<Mora>,
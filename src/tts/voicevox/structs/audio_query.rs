@@ -3,6 +3,7 @@ use serde::{Deserialize, Serialize};
 use super::accent_phrase::AccentPhrase;
 
 #[allow(non_snake_case)]
+#[allow(dead_code)]
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct AudioQuery {
     pub accent_phrases: Vec<AccentPhrase>,
@@ -1,6 +1,42 @@
+use crate::database::user_config::{DEFAULT_PITCH, DEFAULT_SPEAKING_RATE};
+
 use super::structs::speaker::Speaker;
 
 const BASE_API_URL: &str = "https://deprecatedapis.tts.quest/v2/";
+/// Range of the VOICEVOX speed scale.
+const SPEED_SCALE_RANGE: (f32, f32) = (0.5, 2.0);
+/// Range of the VOICEVOX pitch scale.
+const PITCH_SCALE_RANGE: (f32, f32) = (-0.15, 0.15);
+
+/// VOICEVOX speed scale for a GCP style speaking rate.
+/// The default speaking rate is read at the normal VOICEVOX speed.
+pub fn voicevox_speed_scale(speaking_rate: f32) -> f32 {
+    (speaking_rate / DEFAULT_SPEAKING_RATE).clamp(SPEED_SCALE_RANGE.0, SPEED_SCALE_RANGE.1)
+}
+
+/// VOICEVOX pitch scale for a GCP style pitch in semitones.
+/// VOICEVOX pitch is log F0, so one semitone is ln(2) / 12.
+pub fn voicevox_pitch_scale(pitch: f32) -> f32 {
+    ((pitch - DEFAULT_PITCH) * std::f32::consts::LN_2 / 12.0)
+        .clamp(PITCH_SCALE_RANGE.0, PITCH_SCALE_RANGE.1)
+}
+
+/// Find the name of the VOICEVOX style with the id in a `get_styles` list.
+pub fn find_style(styles: &[(String, i64)], id: i64) -> Option<&String> {
+    styles
+        .iter()
+        .find(|(_, style_id)| *style_id == id)
+        .map(|(name, _)| name)
+}
+
+/// Text of the VOICEVOX credit field for a `get_speakers` result.
+/// Returns `None` when the speaker list could not be fetched, so the field is omitted.
+pub fn speaker_credits<E>(speakers: &Result<Vec<String>, E>) -> Option<String> {
+    speakers
+        .as_ref()
+        .ok()
+        .map(|speakers| format!("```\n{}\n```", speakers.join("\n")))
+}
 
 #[derive(Clone)]
 pub struct VOICEVOX {
@@ -9,7 +45,13 @@ pub struct VOICEVOX {
 
 impl VOICEVOX {
     pub async fn get_styles(&self) -> Vec<(String, i64)> {
-        let speakers = self.get_speaker_list().await;
+        let speakers = match self.get_speaker_list().await {
+            Ok(speakers) => speakers,
+            Err(err) => {
+                println!("Cannot get speaker list. {err:?}");
+                vec![]
+            }
+        };
         let mut speaker_list = vec![];
         for speaker in speakers {
             for style in speaker.styles {
@@ -20,39 +62,39 @@ impl VOICEVOX {
         speaker_list
     }
 
-    pub async fn get_speakers(&self) -> Vec<String> {
-        let speakers = self.get_speaker_list().await;
+    /// Names of the speakers, used for the credits.
+    /// Returns an error when the speaker list is unavailable.
+    pub async fn get_speakers(&self) -> Result<Vec<String>, reqwest::Error> {
+        let speakers = self.get_speaker_list().await?;
         let mut speaker_list = vec![];
         for speaker in speakers {
             speaker_list.push(speaker.name)
         }
 
-        speaker_list
+        Ok(speaker_list)
     }
 
     pub fn new(key: String) -> Self {
         Self { key }
     }
 
-    async fn get_speaker_list(&self) -> Vec<Speaker> {
+    async fn get_speaker_list(&self) -> Result<Vec<Speaker>, reqwest::Error> {
         let client = reqwest::Client::new();
-        match client
+        client
             .post(BASE_API_URL.to_string() + "voicevox/speakers/")
             .query(&[("key", self.key.clone())])
             .send()
+            .await?
+            .json()
             .await
-        {
-            Ok(response) => response.json().await.unwrap(),
-            Err(err) => {
-                panic!("Cannot get speaker list. {err:?}")
-            }
-        }
     }
 
     pub async fn synthesize(
         &self,
         text: String,
         speaker: i64,
+        speaking_rate: f32,
+        pitch: f32,
     ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
         let client = reqwest::Client::new();
         match client
@@ -60,6 +102,8 @@ impl VOICEVOX {
             .query(&[
                 ("speaker", speaker.to_string()),
                 ("text", text),
+                ("speed", voicevox_speed_scale(speaking_rate).to_string()),
+                ("pitch", voicevox_pitch_scale(pitch).to_string()),
                 ("key", self.key.clone()),
             ])
             .send()
@@ -73,3 +117,61 @@ impl VOICEVOX {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_rate_is_normal_speed() {
+        assert_eq!(voicevox_speed_scale(DEFAULT_SPEAKING_RATE), 1.0);
+    }
+
+    #[test]
+    fn speed_scales_with_rate_and_is_clamped() {
+        assert!(voicevox_speed_scale(DEFAULT_SPEAKING_RATE * 1.5) > 1.0);
+        assert_eq!(voicevox_speed_scale(0.25), 0.5);
+        assert_eq!(voicevox_speed_scale(4.0), 2.0);
+    }
+
+    #[test]
+    fn pitch_is_relative_to_default_and_clamped() {
+        assert_eq!(voicevox_pitch_scale(DEFAULT_PITCH), 0.0);
+        assert!(voicevox_pitch_scale(DEFAULT_PITCH + 1.0) > 0.0);
+        assert!(voicevox_pitch_scale(DEFAULT_PITCH - 1.0) < 0.0);
+        assert_eq!(voicevox_pitch_scale(20.0), 0.15);
+        assert_eq!(voicevox_pitch_scale(-20.0), -0.15);
+    }
+
+    #[test]
+    fn style_name_is_found_by_id() {
+        let styles = vec![
+            (String::from("四国めたん - ノーマル"), 2),
+            (String::from("ずんだもん - ノーマル"), 3),
+            (String::from("ずんだもん - あまあま"), 1),
+        ];
+        assert_eq!(
+            find_style(&styles, 3).map(String::as_str),
+            Some("ずんだもん - ノーマル")
+        );
+        assert_eq!(
+            find_style(&styles, 1).map(String::as_str),
+            Some("ずんだもん - あまあま")
+        );
+        assert_eq!(find_style(&styles, 99), None);
+        assert_eq!(find_style(&[], 1), None);
+    }
+
+    #[test]
+    fn credits_are_omitted_when_the_speaker_list_fails() {
+        let failed: Result<Vec<String>, &str> = Err("timeout");
+        assert_eq!(speaker_credits(&failed), None);
+
+        let speakers: Result<Vec<String>, &str> =
+            Ok(vec!["四国めたん".to_string(), "ずんだもん".to_string()]);
+        assert_eq!(
+            speaker_credits(&speakers),
+            Some("```\n四国めたん\nずんだもん\n```".to_string())
+        );
+    }
+}
@@ -0,0 +1,40 @@
+/// Truncate the text to at most `max` characters, cutting on a character boundary.
+/// When the text is cut, its end is replaced with `ellipsis` so the result still fits in `max`.
+///
+/// Example:
+/// ```rust
+/// assert_eq!(truncate_chars("あいうえお", 3, "…"), "あい…");
+/// assert_eq!(truncate_chars("あいうえお", 3, ""), "あいう");
+/// ```
+pub fn truncate_chars(text: &str, max: usize, ellipsis: &str) -> String {
+    if text.chars().count() <= max {
+        return text.to_string();
+    }
+    let keep = max.saturating_sub(ellipsis.chars().count());
+    let mut truncated = text.chars().take(keep).collect::<String>();
+    truncated.push_str(ellipsis);
+    truncated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_text_is_unchanged() {
+        assert_eq!(truncate_chars("abc", 3, "…"), "abc");
+        assert_eq!(truncate_chars("", 0, "…"), "");
+    }
+
+    #[test]
+    fn cuts_multibyte_text_on_char_boundaries() {
+        assert_eq!(truncate_chars("あいうえお", 3, ""), "あいう");
+        assert_eq!(truncate_chars("👍👍👍", 2, ""), "👍👍");
+    }
+
+    #[test]
+    fn ellipsis_counts_towards_the_limit() {
+        assert_eq!(truncate_chars("あいうえお", 3, "…"), "あい…");
+        assert_eq!(truncate_chars("abcdef", 4, "..."), "a...");
+    }
+}